@@ -0,0 +1,294 @@
+/*!
+ * 地址模块
+ * 将配送地址规范化为省、市、区、街道、楼栋和房间号
+ * 并生成用于地址坐标缓存的规范化键；外送订单送达后其坐标才写入缓存
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::error::ApiResult;
+use crate::models::{Fulfilment, OrderStatus};
+use crate::repository::OrderRepository;
+
+// 直辖市（省级和市级相同）
+const MUNICIPALITIES: [&str; 4] = ["北京", "上海", "天津", "重庆"];
+
+// 各级行政区划的后缀及名称最大长度（含后缀）
+const PROVINCE_SUFFIXES: [&str; 3] = ["特别行政区", "自治区", "省"];
+const CITY_SUFFIXES: [&str; 4] = ["自治州", "地区", "盟", "市"];
+const DISTRICT_SUFFIXES: [&str; 4] = ["区", "县", "旗", "市"];
+const MAX_PROVINCE_LEN: usize = 10;
+const MAX_CITY_LEN: usize = 10;
+const MAX_DISTRICT_LEN: usize = 8;
+
+// 楼栋标记，如"3号楼"、"5栋"、"B座"
+const BUILDING_MARKERS: [&str; 4] = ["号楼", "栋", "幢", "座"];
+
+// 房间号标记，如"2单元"、"1201室"
+const ROOM_MARKERS: [&str; 3] = ["单元", "室", "房"];
+
+/**
+ * 结构化地址
+ * 各部分均已规范化，缺失的部分为None
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Address {
+    pub province: Option<String>, // 省、自治区或直辖市
+    pub city: Option<String>,     // 市、自治州或地区
+    pub district: Option<String>, // 区、县或县级市
+    pub street: Option<String>,   // 街道、门牌号或小区
+    pub building: Option<String>, // 楼栋
+    pub room: Option<String>,     // 单元和房间号
+}
+
+/**
+ * 规范化地址文本
+ * 全角字母数字和标点转为半角，去除所有空白和首尾标点
+ *
+ * @param text - 原始文本
+ * @return String - 规范化后的文本
+ */
+pub fn normalize_text(text: &str) -> String {
+    let normalized: String = text.chars()
+        .map(|c| match c {
+            // 全角ASCII字符与半角相差固定偏移
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    normalized.trim_matches(|c: char| c.is_ascii_punctuation() || "，。、；：".contains(c)).to_string()
+}
+
+/**
+ * 从文本开头截取以指定后缀结尾的行政区划名称
+ * 取最早出现的后缀，名称超过最大长度时视为不存在
+ *
+ * @param text - 文本
+ * @param suffixes - 可能的后缀
+ * @param max_len - 名称最大字符数（含后缀）
+ * @return Option<(String, &str)> - 名称和剩余文本
+ */
+fn take_division<'a>(text: &'a str, suffixes: &[&str], max_len: usize) -> Option<(String, &'a str)> {
+    let end = suffixes.iter()
+        .filter_map(|suffix| text.find(suffix).map(|index| index + suffix.len()))
+        .min()?;
+    let name = &text[..end];
+    // 名称至少两个字符，避免把单独的后缀当作名称
+    let len = name.chars().count();
+    (len >= 2 && len <= max_len).then(|| (name.to_string(), &text[end..]))
+}
+
+/**
+ * 查找标记前连续字母数字的起始位置
+ *
+ * @param text - 文本
+ * @param index - 标记的位置
+ * @return usize - 起始位置，标记前没有字母数字时为标记的位置
+ */
+fn alphanumeric_start(text: &str, index: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '-')
+        .last()
+        .map_or(index, |(i, _)| i)
+}
+
+/**
+ * 将空字符串转为None
+ */
+fn non_empty(text: &str) -> Option<String> {
+    let text = normalize_text(text);
+    (!text.is_empty()).then_some(text)
+}
+
+impl Address {
+    /**
+     * 从完整地址文本解析结构化地址
+     * 依次识别省、市、区，其余部分按楼栋标记拆分为街道、楼栋和房间号
+     * 无法识别的部分保留在街道中
+     *
+     * @param text - 地址文本，如"北京市海淀区中关村大街1号3号楼1201室"
+     * @return Address - 解析结果
+     */
+    pub fn parse(text: &str) -> Address {
+        let text = normalize_text(text);
+        let mut rest = text.as_str();
+        let mut address = Address::default();
+
+        // 直辖市的省级和市级名称相同
+        if let Some(name) = MUNICIPALITIES.iter().find(|name| rest.starts_with(*name)) {
+            rest = &rest[name.len()..];
+            rest = rest.strip_prefix('市').unwrap_or(rest);
+            address.province = Some(format!("{}市", name));
+            address.city = address.province.clone();
+        } else {
+            if let Some((province, remaining)) = take_division(rest, &PROVINCE_SUFFIXES, MAX_PROVINCE_LEN) {
+                address.province = Some(province);
+                rest = remaining;
+            }
+            if let Some((city, remaining)) = take_division(rest, &CITY_SUFFIXES, MAX_CITY_LEN) {
+                address.city = Some(city);
+                rest = remaining;
+            }
+        }
+        if let Some((district, remaining)) = take_division(rest, &DISTRICT_SUFFIXES, MAX_DISTRICT_LEN) {
+            address.district = Some(district);
+            rest = remaining;
+        }
+
+        // 楼栋为标记前的字母数字加标记本身，标记之后为房间号
+        let marker = BUILDING_MARKERS.iter()
+            .filter_map(|marker| rest.rfind(marker).map(|index| (index, marker.len())))
+            .max_by_key(|(index, _)| *index);
+        match marker {
+            Some((index, marker_len)) => {
+                let start = alphanumeric_start(rest, index);
+                address.street = non_empty(&rest[..start]);
+                address.building = non_empty(&rest[start..index + marker_len]);
+                address.room = non_empty(&rest[index + marker_len..]);
+            }
+            // 没有楼栋时，从最早的带编号的房间号标记开始为房间号
+            None => {
+                let start = ROOM_MARKERS.iter()
+                    .filter_map(|marker| rest.find(marker))
+                    .map(|index| (alphanumeric_start(rest, index), index))
+                    .filter(|(start, index)| *start > 0 && start < index)
+                    .map(|(start, _)| start)
+                    .min()
+                    .unwrap_or(rest.len());
+                address.street = non_empty(&rest[..start]);
+                address.room = non_empty(&rest[start..]);
+            }
+        }
+        address
+    }
+
+    /**
+     * 规范化各部分文本
+     *
+     * @return Address - 规范化后的地址
+     */
+    pub fn normalized(&self) -> Address {
+        let part = |value: &Option<String>| value.as_deref().and_then(non_empty);
+        Address {
+            province: part(&self.province),
+            city: part(&self.city),
+            district: part(&self.district),
+            street: part(&self.street),
+            building: part(&self.building),
+            room: part(&self.room),
+        }
+    }
+
+    /**
+     * 地址坐标缓存的键
+     * 同一楼栋的坐标相同，因此不包含房间号
+     *
+     * @return String - 规范化键
+     */
+    pub fn cache_key(&self) -> String {
+        [&self.province, &self.city, &self.district, &self.street, &self.building]
+            .iter()
+            .filter_map(|part| part.as_deref())
+            .collect::<Vec<_>>()
+            .join("|")
+            .to_lowercase()
+    }
+
+    /**
+     * 是否缺少街道信息（无法用于配送）
+     */
+    pub fn is_empty(&self) -> bool {
+        self.street.is_none() && self.building.is_none()
+    }
+}
+
+/**
+ * 将已送达的外送订单的坐标写入地址坐标缓存
+ * 只有实际送达的订单才能证明坐标可用，已缓存的地址保持最早写入的坐标
+ *
+ * @param repo - 订单仓储
+ * @param reference - 订单编号或短码
+ * @param now - 当前时间
+ * @return ApiResult<()> - 操作结果，订单不存在、未完成或没有坐标时不做处理
+ */
+pub fn cache_delivered_location(repo: &dyn OrderRepository, reference: &str, now: DateTime<Utc>) -> ApiResult<()> {
+    let Some(order) = repo.get_order_by_reference(reference)? else {
+        return Ok(());
+    };
+    if order.status != OrderStatus::Completed || !matches!(order.fulfilment, Fulfilment::Delivery) {
+        return Ok(());
+    }
+    // 早期订单没有结构化地址，按地址文本解析
+    let address = order.address.clone()
+        .or_else(|| order.delivery_address.as_deref().map(Address::parse))
+        .filter(|address| !address.is_empty());
+    if let (Some(address), Some(location)) = (address, order.location()) {
+        repo.save_geocode(&address.cache_key(), location, now)?;
+    }
+    Ok(())
+}
+
+/**
+ * 输出完整地址文本，直辖市只输出一次
+ */
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let city = self.city.as_ref().filter(|city| Some(*city) != self.province.as_ref());
+        for part in [self.province.as_ref(), city, self.district.as_ref(), self.street.as_ref(), self.building.as_ref(), self.room.as_ref()]
+            .into_iter()
+            .flatten() {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        let address = Address::parse(" 北京市海淀区 中关村大街１号 3号楼1201室。");
+        assert_eq!(address.province.as_deref(), Some("北京市"));
+        assert_eq!(address.city.as_deref(), Some("北京市"));
+        assert_eq!(address.district.as_deref(), Some("海淀区"));
+        assert_eq!(address.street.as_deref(), Some("中关村大街1号"));
+        assert_eq!(address.building.as_deref(), Some("3号楼"));
+        assert_eq!(address.room.as_deref(), Some("1201室"));
+        assert_eq!(address.to_string(), "北京市海淀区中关村大街1号3号楼1201室");
+
+        let address = Address::parse("浙江省杭州市西湖区文三路90号东部软件园B座");
+        assert_eq!(address.province.as_deref(), Some("浙江省"));
+        assert_eq!(address.city.as_deref(), Some("杭州市"));
+        assert_eq!(address.street.as_deref(), Some("文三路90号东部软件园"));
+        assert_eq!(address.building.as_deref(), Some("B座"));
+        assert_eq!(address.room, None);
+
+        // 无法识别行政区划时整体作为街道
+        let address = Address::parse("望京SOHO");
+        assert_eq!(address.street.as_deref(), Some("望京SOHO"));
+        assert!(!address.is_empty());
+    }
+
+    #[test]
+    fn test_cache_key_ignores_room() {
+        let a = Address::parse("北京市海淀区中关村大街1号3号楼1201室");
+        let b = Address::parse("北京海淀区中关村大街１号 3号楼 502室");
+        assert_eq!(a.cache_key(), b.cache_key());
+        assert_ne!(a.cache_key(), Address::parse("北京市海淀区中关村大街2号3号楼").cache_key());
+
+        // 没有楼栋时房间号也不影响缓存键
+        let c = Address::parse("北京市海淀区中关村大街1号2单元502室");
+        assert_eq!(c.street.as_deref(), Some("中关村大街1号"));
+        assert_eq!(c.room.as_deref(), Some("2单元502室"));
+        assert_eq!(c.cache_key(), Address::parse("北京市海淀区中关村大街1号").cache_key());
+    }
+}
@@ -0,0 +1,246 @@
+/*!
+ * 门店配置模块
+ * 从环境变量读取门店相关配置，首次访问时加载
+ */
+
+use chrono::{Duration, FixedOffset};
+use std::env;
+use std::sync::{Arc, OnceLock};
+use crate::delivery::{DeliveryFees, DeliveryZones};
+use crate::eta::Estimator;
+use crate::models::Location;
+use crate::payment::{MockProvider, PaymentProvider};
+use crate::schedule::StoreHours;
+
+// 默认配置常量
+const DEFAULT_TIMEZONE: &str = "+08:00"; // 默认门店时区（北京时间）
+const DEFAULT_CANCEL_GRACE_SECS: i64 = 120; // 默认取消宽限期（秒）
+const DEFAULT_OPENING_HOURS: &str = "08:00-22:00"; // 默认营业时间
+const DEFAULT_SCHEDULE_LEAD_MINS: i64 = 30; // 默认预约订单提前发送给设备的时间（分钟）
+const DEFAULT_MOCK_PAYMENT_SECRET: &str = "mock-payment-secret"; // 模拟支付网关的默认回调签名密钥
+
+static STORE_TIMEZONE: OnceLock<Result<FixedOffset, String>> = OnceLock::new();
+
+/**
+ * 读取门店时区配置
+ * 由环境变量STORE_TIMEZONE配置，格式为UTC偏移量，如"+08:00"；启动时调用以检查配置
+ *
+ * @return Result<FixedOffset, String> - 门店时区，STORE_TIMEZONE无法解析时返回错误
+ */
+pub fn load_store_timezone() -> Result<FixedOffset, String> {
+    STORE_TIMEZONE.get_or_init(|| match env::var("STORE_TIMEZONE") {
+        Ok(value) => value.parse().map_err(|_| format!("Invalid STORE_TIMEZONE {:?}", value)),
+        Err(_) => Ok(DEFAULT_TIMEZONE.parse().expect("valid default timezone")),
+    }).clone()
+}
+
+/**
+ * 获取门店时区
+ * 配置已在启动时由load_store_timezone检查
+ *
+ * @return FixedOffset - 门店时区
+ */
+pub fn store_timezone() -> FixedOffset {
+    load_store_timezone().expect("STORE_TIMEZONE is checked at startup")
+}
+
+static LEGACY_ORDER_IDS: OnceLock<bool> = OnceLock::new();
+
+/**
+ * 是否允许按内部订单ID更新订单状态
+ * 旧版管理端按ID调用状态更新接口，兼容期内默认允许
+ * 设置环境变量ALLOW_LEGACY_ORDER_IDS=false关闭
+ *
+ * @return bool - 是否允许
+ */
+pub fn legacy_order_ids_enabled() -> bool {
+    *LEGACY_ORDER_IDS.get_or_init(|| {
+        env::var("ALLOW_LEGACY_ORDER_IDS")
+            .map(|value| !matches!(value.trim().to_lowercase().as_str(), "0" | "false" | "no" | "off"))
+            .unwrap_or(true)
+    })
+}
+
+static CANCEL_GRACE_PERIOD: OnceLock<Duration> = OnceLock::new();
+
+/**
+ * 获取客户自助取消订单的宽限期
+ * 订单开始制作后，下单时间在宽限期内的仍允许客户取消
+ * 由环境变量CANCEL_GRACE_PERIOD_SECS配置，设置为0表示只能取消待处理的订单
+ *
+ * @return Duration - 取消宽限期
+ */
+pub fn cancel_grace_period() -> Duration {
+    *CANCEL_GRACE_PERIOD.get_or_init(|| {
+        let secs = match env::var("CANCEL_GRACE_PERIOD_SECS") {
+            Ok(value) => value.trim().parse::<i64>().ok().filter(|s| *s >= 0).unwrap_or_else(|| {
+                log::warn!("Invalid CANCEL_GRACE_PERIOD_SECS {:?}, using {}", value, DEFAULT_CANCEL_GRACE_SECS);
+                DEFAULT_CANCEL_GRACE_SECS
+            }),
+            Err(_) => DEFAULT_CANCEL_GRACE_SECS,
+        };
+        Duration::seconds(secs)
+    })
+}
+
+static STORE_HOURS: OnceLock<Result<StoreHours, String>> = OnceLock::new();
+
+/**
+ * 获取门店营业时间表
+ * 由环境变量OPENING_HOURS配置每周营业时间（门店时区），如"mon-fri=08:00-22:00;sat,sun=10:00-20:00"
+ * 只写"HH:MM-HH:MM"表示每天相同；环境变量STORE_HOLIDAYS配置节假日例外，如"2024-10-01;2024-10-02=10:00-18:00"
+ *
+ * @return Result<&StoreHours, String> - 营业时间表，OPENING_HOURS或STORE_HOLIDAYS无法解析时返回错误
+ */
+pub fn store_hours() -> Result<&'static StoreHours, String> {
+    STORE_HOURS.get_or_init(|| {
+        let weekly: StoreHours = match env::var("OPENING_HOURS") {
+            Ok(value) => value.parse().map_err(|e| format!("OPENING_HOURS: {}", e))?,
+            Err(_) => DEFAULT_OPENING_HOURS.parse().expect("valid default opening hours"),
+        };
+        match env::var("STORE_HOLIDAYS") {
+            Ok(value) => weekly.with_holidays(&value).map_err(|e| format!("STORE_HOLIDAYS: {}", e)),
+            Err(_) => Ok(weekly),
+        }
+    }).as_ref().map_err(Clone::clone)
+}
+
+static SCHEDULE_LEAD_TIME: OnceLock<Duration> = OnceLock::new();
+
+/**
+ * 获取预约订单提前发送给设备的时间
+ * 预约订单在送达时间前的该时间内才发送给设备开始制作
+ * 由环境变量SCHEDULE_LEAD_MINUTES配置
+ *
+ * @return Duration - 提前量
+ */
+pub fn schedule_lead_time() -> Duration {
+    *SCHEDULE_LEAD_TIME.get_or_init(|| {
+        let mins = match env::var("SCHEDULE_LEAD_MINUTES") {
+            Ok(value) => value.trim().parse::<i64>().ok().filter(|m| *m >= 0).unwrap_or_else(|| {
+                log::warn!("Invalid SCHEDULE_LEAD_MINUTES {:?}, using {}", value, DEFAULT_SCHEDULE_LEAD_MINS);
+                DEFAULT_SCHEDULE_LEAD_MINS
+            }),
+            Err(_) => DEFAULT_SCHEDULE_LEAD_MINS,
+        };
+        Duration::minutes(mins)
+    })
+}
+
+static DELIVERY_ZONES: OnceLock<Result<Option<DeliveryZones>, String>> = OnceLock::new();
+
+/**
+ * 获取配送区域
+ * 由环境变量DELIVERY_ZONES_FILE指定GeoJSON文件，未配置时不限制配送范围
+ *
+ * @return Result<Option<&DeliveryZones>, String> - 配送区域，已配置但文件无法读取或解析时返回错误
+ */
+pub fn delivery_zones() -> Result<Option<&'static DeliveryZones>, String> {
+    DELIVERY_ZONES.get_or_init(|| match env::var("DELIVERY_ZONES_FILE") {
+        Ok(path) => DeliveryZones::load(&path).map(Some),
+        Err(_) => Ok(None),
+    }).as_ref().map(Option::as_ref).map_err(Clone::clone)
+}
+
+static DELIVERY_FEES: OnceLock<Result<Option<DeliveryFees>, String>> = OnceLock::new();
+
+/**
+ * 获取外送费规则
+ * 由环境变量STORE_LOCATION（门店坐标"纬度,经度"）和DELIVERY_FEE_TIERS（如"3=5;5=8;8=12"）配置
+ * 两者都配置时才按距离收取外送费，都未配置时不收外送费
+ *
+ * @return Result<Option<&DeliveryFees>, String> - 外送费规则，已配置但无法解析或缺少门店坐标时返回错误
+ */
+pub fn delivery_fees() -> Result<Option<&'static DeliveryFees>, String> {
+    DELIVERY_FEES.get_or_init(|| {
+        let store = match env::var("STORE_LOCATION") {
+            Ok(location) => Some(parse_location(&location).ok_or_else(|| format!("Invalid STORE_LOCATION {:?}", location))?),
+            Err(_) => None,
+        };
+        match (store, env::var("DELIVERY_FEE_TIERS")) {
+            (Some(store), Ok(tiers)) => DeliveryFees::new(store, &tiers).map(Some),
+            (None, Ok(_)) => Err("DELIVERY_FEE_TIERS requires STORE_LOCATION".to_string()),
+            (_, Err(_)) => Ok(None),
+        }
+    }).as_ref().map(Option::as_ref).map_err(Clone::clone)
+}
+
+/**
+ * 解析"纬度,经度"格式的坐标
+ *
+ * @param value - 坐标字符串
+ * @return Option<Location> - 解析结果
+ */
+fn parse_location(value: &str) -> Option<Location> {
+    let (lat, lng) = value.split_once(',')?;
+    Some(Location { lat: lat.trim().parse().ok()?, lng: lng.trim().parse().ok()? })
+}
+
+static ESTIMATOR: OnceLock<Estimator> = OnceLock::new();
+
+/**
+ * 获取订单预计时间估算器
+ * 由环境变量PREP_TIMES（各饮品每杯制作秒数，如"拿铁=150;浓缩咖啡=60"）、
+ * DEFAULT_PREP_SECS（默认每杯制作秒数）、COURIER_SPEED_KMH（配送速度）、
+ * DEFAULT_DELIVERY_MINS（未配置STORE_LOCATION时的配送分钟数）和STORE_LOCATION配置
+ *
+ * @return &Estimator - 估算器
+ */
+pub fn estimator() -> &'static Estimator {
+    ESTIMATOR.get_or_init(|| {
+        let mut estimator = Estimator::default();
+        if let Ok(value) = env::var("DEFAULT_PREP_SECS") {
+            match value.trim().parse::<i64>().ok().filter(|s| *s > 0) {
+                Some(secs) => estimator.default_prep = Duration::seconds(secs),
+                None => log::warn!("Invalid DEFAULT_PREP_SECS {:?}, using default", value),
+            }
+        }
+        if let Ok(value) = env::var("COURIER_SPEED_KMH") {
+            match value.trim().parse::<f64>().ok().filter(|s| s.is_finite() && *s > 0.0) {
+                Some(speed) => estimator.courier_speed_kmh = speed,
+                None => log::warn!("Invalid COURIER_SPEED_KMH {:?}, using default", value),
+            }
+        }
+        if let Ok(value) = env::var("DEFAULT_DELIVERY_MINS") {
+            match value.trim().parse::<i64>().ok().filter(|m| *m >= 0) {
+                Some(mins) => estimator.default_travel = Duration::minutes(mins),
+                None => log::warn!("Invalid DEFAULT_DELIVERY_MINS {:?}, using default", value),
+            }
+        }
+        if let Ok(value) = env::var("STORE_LOCATION") {
+            estimator.store = parse_location(&value);
+        }
+        if let Ok(spec) = env::var("PREP_TIMES") {
+            match estimator.clone().with_prep_times(&spec) {
+                Ok(configured) => estimator = configured,
+                Err(e) => log::warn!("{}, using default prep time", e),
+            }
+        }
+        estimator
+    })
+}
+
+static PAYMENT_PROVIDER: OnceLock<Result<Option<Arc<dyn PaymentProvider>>, String>> = OnceLock::new();
+
+/**
+ * 获取支付服务商
+ * 由环境变量PAYMENT_PROVIDER选择，目前支持"mock"（开发用模拟网关），
+ * 回调签名密钥由PAYMENT_WEBHOOK_SECRET配置；未配置时订单无需支付，直接进入待处理状态
+ *
+ * @return Result<Option<&Arc<dyn PaymentProvider>>, String> - 支付服务商，配置了不支持的服务商时返回错误
+ */
+pub fn payment_provider() -> Result<Option<&'static Arc<dyn PaymentProvider>>, String> {
+    PAYMENT_PROVIDER.get_or_init(|| {
+        let Ok(name) = env::var("PAYMENT_PROVIDER") else {
+            return Ok(None);
+        };
+        let secret = env::var("PAYMENT_WEBHOOK_SECRET").ok();
+        match name.trim() {
+            "mock" => {
+                let secret = secret.unwrap_or_else(|| DEFAULT_MOCK_PAYMENT_SECRET.to_string());
+                Ok(Some(Arc::new(MockProvider::new(&secret)) as Arc<dyn PaymentProvider>))
+            }
+            other => Err(format!("Unknown PAYMENT_PROVIDER {:?}", other)),
+        }
+    }).as_ref().map(Option::as_ref).map_err(Clone::clone)
+}
@@ -1,20 +1,46 @@
-/**
+/*!
  * 数据库操作模块
  * 提供所有与SQLite数据库交互的功能
  * 包括数据库初始化、订单的CRUD操作等
  */
 
-use rusqlite::{Connection, Result as SqliteResult, params};
-use crate::models::{Order, OrderItem, OrderStatus};
-use std::sync::Mutex;
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter};
+use rusqlite::{ErrorCode, ToSql};
+use rusqlite::types::{FromSql, FromSqlResult, ToSqlOutput, Value, ValueRef};
+use crate::error::{ApiError, ApiResult};
+use crate::models::{Courier, CourierPosition, Fulfilment, Location, Money, Order, OrderAuditEntry, OrderEstimate, OrderItem, OrderStatus, Payment, PaymentStatus, Refund, RefundStatus, StorePause};
+use std::collections::HashMap;
 use std::str::FromStr;
 
+/// 旧版本数据库中时间的存储格式（SQLite CURRENT_TIMESTAMP，UTC）
+const LEGACY_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// SQLite连接池类型
+pub type DbPool = Pool<SqliteConnectionManager>;
+
 /**
- * 应用状态结构体
- * 包含线程安全的数据库连接
+ * 创建数据库连接池
+ * 每个连接启用WAL模式和忙等待，允许读写并发
+ * 
+ * @param path - 数据库文件路径
+ * @param max_size - 连接池最大连接数
+ * @return ApiResult<DbPool> - 连接池
  */
-pub struct AppState {
-    pub db: Mutex<Connection>, // 使用互斥锁保护的数据库连接
+pub fn open_pool(path: &str, max_size: u32) -> ApiResult<DbPool> {
+    let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA busy_timeout = 5000;"
+        )
+    });
+    let pool = Pool::builder().max_size(max_size).build(manager)?;
+    let conn = pool.get()?;
+    init_db(&conn)?;
+    Ok(pool)
 }
 
 /**
@@ -22,9 +48,9 @@ pub struct AppState {
  * 创建必要的数据表结构
  * 
  * @param conn - 数据库连接
- * @return SqliteResult<()> - 操作结果
+ * @return ApiResult<()> - 操作结果
  */
-pub fn init_db(conn: &Connection) -> SqliteResult<()> {
+pub fn init_db(conn: &Connection) -> ApiResult<()> {
     // 创建订单表
     conn.execute(
         "CREATE TABLE IF NOT EXISTS orders (
@@ -56,6 +82,395 @@ pub fn init_db(conn: &Connection) -> SqliteResult<()> {
         [],
     )?;
 
+    // 执行数据库迁移
+    run_migrations(conn)?;
+
+    // 创建索引，加速订单项加载和订单列表的筛选排序
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_order_items_order_id ON order_items (order_id);
+         CREATE INDEX IF NOT EXISTS idx_orders_status ON orders (status);
+         CREATE INDEX IF NOT EXISTS idx_orders_created_at ON orders (created_at);"
+    )?;
+
+    Ok(())
+}
+
+/**
+ * 数据库迁移列表
+ * 按顺序执行，已执行的版本号记录在PRAGMA user_version中
+ * 只能追加新的迁移，不能修改已发布的迁移
+ */
+const MIGRATIONS: &[&str] = &[
+    // 1: 金额改为以分为单位的整数存储
+    "CREATE TABLE orders_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 订单ID
+        order_number TEXT NOT NULL UNIQUE,       -- 订单编号（唯一）
+        customer_name TEXT NOT NULL,             -- 客户姓名
+        phone_number TEXT NOT NULL,              -- 联系电话
+        delivery_address TEXT NOT NULL,          -- 配送地址
+        latitude REAL NOT NULL,                  -- 配送地址纬度
+        longitude REAL NOT NULL,                 -- 配送地址经度
+        notes TEXT,                              -- 订单备注
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP, -- 创建时间
+        total_amount INTEGER NOT NULL,           -- 订单总金额（分）
+        status TEXT NOT NULL DEFAULT 'pending'   -- 订单状态
+    );
+    INSERT INTO orders_new
+        SELECT id, order_number, customer_name, phone_number, delivery_address, latitude, longitude,
+               notes, created_at, CAST(ROUND(total_amount * 100) AS INTEGER), status
+        FROM orders;
+    DROP TABLE orders;
+    ALTER TABLE orders_new RENAME TO orders;
+
+    CREATE TABLE order_items_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 订单项ID
+        order_id INTEGER NOT NULL,               -- 关联的订单ID
+        name TEXT NOT NULL,                      -- 商品名称
+        quantity INTEGER NOT NULL,               -- 商品数量
+        price INTEGER NOT NULL,                  -- 商品单价（分）
+        FOREIGN KEY (order_id) REFERENCES orders (id) -- 外键约束
+    );
+    INSERT INTO order_items_new
+        SELECT id, order_id, name, quantity, CAST(ROUND(price * 100) AS INTEGER)
+        FROM order_items;
+    DROP TABLE order_items;
+    ALTER TABLE order_items_new RENAME TO order_items;",
+
+    // 2: 创建时间统一改为UTC的RFC 3339格式
+    "UPDATE orders SET created_at = strftime('%Y-%m-%dT%H:%M:%SZ', created_at)
+     WHERE created_at IS NOT NULL AND created_at NOT LIKE '%Z';",
+
+    // 3: 增加订单短码，已有订单取订单编号前8位（重复时追加订单ID）
+    "ALTER TABLE orders ADD COLUMN short_code TEXT;
+    UPDATE orders SET short_code = upper(substr(replace(order_number, '-', ''), 1, 8));
+    UPDATE orders SET short_code = short_code || id
+     WHERE EXISTS (SELECT 1 FROM orders o2 WHERE o2.short_code = orders.short_code AND o2.id < orders.id);
+    CREATE UNIQUE INDEX idx_orders_short_code ON orders (short_code);",
+
+    // 4: 增加订单查询令牌和取消信息（旧订单没有令牌，不能自助取消）
+    "ALTER TABLE orders ADD COLUMN lookup_token TEXT;
+    ALTER TABLE orders ADD COLUMN cancel_reason TEXT;
+    ALTER TABLE orders ADD COLUMN cancelled_at TEXT;",
+
+    // 5: 订单审计记录表
+    "CREATE TABLE order_audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 记录ID
+        order_id INTEGER NOT NULL,               -- 关联的订单ID
+        action TEXT NOT NULL,                    -- 操作类型
+        detail TEXT NOT NULL,                    -- 变更内容（JSON）
+        created_at TEXT NOT NULL,                -- 操作时间
+        FOREIGN KEY (order_id) REFERENCES orders (id) -- 外键约束
+    );
+    CREATE INDEX idx_order_audit_log_order_id ON order_audit_log (order_id);",
+
+    // 6: 预约送达时间，已有订单视为已发送给设备
+    "ALTER TABLE orders ADD COLUMN requested_time TEXT;
+    ALTER TABLE orders ADD COLUMN dispatched_at TEXT;
+    UPDATE orders SET dispatched_at = created_at;
+    CREATE INDEX idx_orders_held ON orders (requested_time) WHERE dispatched_at IS NULL;",
+
+    // 7: 增加履约方式，配送地址和坐标改为仅外送订单必填
+    "CREATE TABLE orders_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 订单ID
+        order_number TEXT NOT NULL UNIQUE,       -- 订单编号（唯一）
+        customer_name TEXT NOT NULL,             -- 客户姓名
+        phone_number TEXT NOT NULL,              -- 联系电话
+        delivery_address TEXT,                   -- 配送地址（仅外送订单）
+        latitude REAL,                           -- 配送地址纬度（仅外送订单）
+        longitude REAL,                          -- 配送地址经度（仅外送订单）
+        notes TEXT,                              -- 订单备注
+        created_at TEXT NOT NULL,                -- 创建时间
+        total_amount INTEGER NOT NULL,           -- 订单总金额（分）
+        status TEXT NOT NULL DEFAULT 'pending',  -- 订单状态
+        short_code TEXT,                         -- 订单短码
+        lookup_token TEXT,                       -- 订单查询令牌
+        cancel_reason TEXT,                      -- 取消原因
+        cancelled_at TEXT,                       -- 取消时间
+        requested_time TEXT,                     -- 预约送达时间
+        dispatched_at TEXT,                      -- 发送给设备的时间
+        fulfilment TEXT NOT NULL DEFAULT 'delivery', -- 履约方式
+        table_number TEXT                        -- 堂食桌号
+    );
+    INSERT INTO orders_new (id, order_number, customer_name, phone_number, delivery_address, latitude, longitude,
+                            notes, created_at, total_amount, status, short_code, lookup_token, cancel_reason,
+                            cancelled_at, requested_time, dispatched_at)
+        SELECT id, order_number, customer_name, phone_number, delivery_address, latitude, longitude,
+               notes, COALESCE(created_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')), total_amount, status, short_code,
+               lookup_token, cancel_reason, cancelled_at, requested_time, dispatched_at
+        FROM orders;
+    DROP TABLE orders;
+    ALTER TABLE orders_new RENAME TO orders;
+    CREATE UNIQUE INDEX idx_orders_short_code ON orders (short_code);
+    CREATE INDEX idx_orders_held ON orders (requested_time) WHERE dispatched_at IS NULL;",
+    // 8: 门店设置（暂停接单），表中只有一行
+    "CREATE TABLE store_settings (
+        id INTEGER PRIMARY KEY CHECK (id = 1),   -- 固定为1
+        ordering_paused INTEGER NOT NULL DEFAULT 0, -- 是否暂停接单
+        pause_reason TEXT,                       -- 暂停原因
+        paused_at TEXT                           -- 暂停时间
+    );
+    INSERT INTO store_settings (id) VALUES (1);",
+    // 9: 外送费（以分为单位）
+    "ALTER TABLE orders ADD COLUMN delivery_fee INTEGER NOT NULL DEFAULT 0;",
+    // 10: 预计出餐和送达时间
+    "ALTER TABLE orders ADD COLUMN estimated_ready_at TEXT;
+     ALTER TABLE orders ADD COLUMN estimated_arrival_at TEXT;",
+    // 11: 配送员及订单指派
+    "CREATE TABLE couriers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 配送员ID
+        name TEXT NOT NULL,                      -- 姓名
+        phone_number TEXT NOT NULL,              -- 联系电话
+        token TEXT NOT NULL UNIQUE,              -- 配送员接口访问令牌
+        created_at TEXT NOT NULL                 -- 创建时间
+    );
+    ALTER TABLE orders ADD COLUMN courier_id INTEGER REFERENCES couriers (id);
+    CREATE INDEX idx_orders_courier_id ON orders (courier_id) WHERE courier_id IS NOT NULL;",
+    // 12: 配送员最新位置，订单离开配送中状态时清除
+    "ALTER TABLE orders ADD COLUMN courier_lat REAL;
+    ALTER TABLE orders ADD COLUMN courier_lng REAL;
+    ALTER TABLE orders ADD COLUMN courier_located_at TEXT;
+    CREATE TRIGGER clear_courier_location AFTER UPDATE OF status ON orders
+    WHEN NEW.status != 'delivering' AND NEW.courier_located_at IS NOT NULL
+    BEGIN
+        UPDATE orders SET courier_lat = NULL, courier_lng = NULL, courier_located_at = NULL WHERE id = NEW.id;
+    END;",
+    // 13: 结构化配送地址和地址坐标缓存
+    "ALTER TABLE orders ADD COLUMN address_parts TEXT;
+    CREATE TABLE geocode_cache (
+        address_key TEXT PRIMARY KEY,            -- 规范化地址（不含房间号）
+        latitude REAL NOT NULL,                  -- 纬度
+        longitude REAL NOT NULL,                 -- 经度
+        updated_at TEXT NOT NULL                 -- 最后更新时间
+    );",
+    // 14: 支付记录
+    "CREATE TABLE payments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 支付记录ID
+        order_id INTEGER NOT NULL REFERENCES orders (id), -- 订单ID
+        provider TEXT NOT NULL,                  -- 支付服务商
+        reference TEXT NOT NULL UNIQUE,          -- 服务商的支付单号
+        amount INTEGER NOT NULL,                 -- 支付金额（分）
+        status TEXT NOT NULL,                    -- 支付状态
+        created_at TEXT NOT NULL,                -- 发起时间
+        updated_at TEXT NOT NULL                 -- 最后更新时间
+    );
+    CREATE INDEX idx_payments_order_id ON payments (order_id);",
+    // 15: 退款记录，订单上汇总已退款金额和退款状态
+    "CREATE TABLE refunds (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,    -- 退款记录ID
+        payment_id INTEGER NOT NULL REFERENCES payments (id), -- 原支付记录ID
+        reference TEXT UNIQUE,                   -- 服务商的退款单号（退款失败时为空）
+        amount INTEGER NOT NULL,                 -- 退款金额（分）
+        status TEXT NOT NULL,                    -- 退款结果
+        reason TEXT,                             -- 退款原因
+        created_at TEXT NOT NULL                 -- 申请时间
+    );
+    CREATE INDEX idx_refunds_payment_id ON refunds (payment_id);
+    ALTER TABLE orders ADD COLUMN refunded_amount INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE orders ADD COLUMN refund_status TEXT;",
+];
+
+/**
+ * 执行尚未应用的数据库迁移
+ * 每个迁移在独立事务中执行
+ * 迁移期间关闭外键约束以便重建数据表，提交前检查外键完整性
+ * 
+ * @param conn - 数据库连接
+ * @return ApiResult<()> - 操作结果
+ */
+fn run_migrations(conn: &Connection) -> ApiResult<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    // 外键开关只能在事务之外修改
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_migrations(conn, version);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+/**
+ * 从指定版本开始依次执行迁移
+ */
+fn apply_migrations(conn: &Connection, version: usize) -> ApiResult<()> {
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration)?;
+        let violations: i64 = tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+        if violations > 0 {
+            return Err(ApiError::Internal(format!("Migration {} left {} foreign key violations", i + 1, violations)));
+        }
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+        log::info!("Applied database migration {}", i + 1);
+    }
+    Ok(())
+}
+
+/**
+ * 将时间格式化为数据库存储格式
+ * 统一使用UTC的RFC 3339格式（精确到秒），保证按字符串比较即按时间排序
+ * 
+ * @param time - 时间
+ * @return String - 如"2024-01-01T08:00:00Z"
+ */
+pub fn format_timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/**
+ * 解析数据库中存储的时间
+ * 兼容旧版本的"YYYY-MM-DD HH:MM:SS"格式（按UTC处理）
+ * 
+ * @param value - 时间字符串
+ * @return Option<DateTime<Utc>> - 解析结果
+ */
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, LEGACY_TIMESTAMP_FORMAT).map(|t| t.and_utc()))
+        .ok()
+}
+
+/**
+ * 读取时间列
+ */
+fn get_timestamp(row: &Row, idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let value: String = row.get(idx)?;
+    parse_timestamp(&value).ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(
+        idx,
+        rusqlite::types::Type::Text,
+        format!("Invalid timestamp: {}", value).into(),
+    ))
+}
+
+/**
+ * 读取可为空的时间列
+ */
+fn get_optional_timestamp(row: &Row, idx: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    match row.get_ref(idx)? {
+        ValueRef::Null => Ok(None),
+        _ => get_timestamp(row, idx).map(Some),
+    }
+}
+
+/**
+ * 金额以分为单位的整数存入数据库
+ */
+impl ToSql for Money {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.cents()))
+    }
+}
+
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(Money::from_cents)
+    }
+}
+
+/// 订单查询的列列表，与map_order_row的读取顺序保持一致
+const ORDER_COLUMNS: &str =
+    "o.id, o.order_number, o.customer_name, o.phone_number, o.delivery_address, 
+     o.latitude, o.longitude, o.notes, o.created_at, o.total_amount, o.status, o.short_code, 
+     o.lookup_token, o.cancel_reason, o.cancelled_at, o.requested_time, o.dispatched_at, 
+     o.fulfilment, o.table_number, o.delivery_fee, o.estimated_ready_at, o.estimated_arrival_at, o.courier_id,
+     o.courier_lat, o.courier_lng, o.courier_located_at, o.address_parts, o.refunded_amount, o.refund_status";
+
+/**
+ * 将查询结果行映射为订单
+ * 订单项为空，需通过load_order_items填充
+ * 
+ * @param row - 按ORDER_COLUMNS选取的结果行
+ * @return rusqlite::Result<Order> - 订单
+ */
+fn map_order_row(row: &Row) -> rusqlite::Result<Order> {
+    Ok(Order {
+        id: row.get(0)?,
+        order_number: row.get(1)?,
+        customer_name: row.get(2)?,
+        phone_number: row.get(3)?,
+        fulfilment: Fulfilment::from_parts(&row.get::<_, String>(17)?, row.get(18)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(17, rusqlite::types::Type::Text, e.into())
+        })?,
+        delivery_address: row.get(4)?,
+        latitude: row.get(5)?,
+        longitude: row.get(6)?,
+        notes: row.get(7)?,
+        created_at: get_timestamp(row, 8)?,
+        delivery_fee: row.get(19)?,
+        total_amount: row.get(9)?,
+        status: OrderStatus::from_str(&row.get::<_, String>(10)?).unwrap_or(OrderStatus::Pending),
+        short_code: row.get(11)?,
+        items: Vec::new(),
+        lookup_token: row.get(12)?,
+        cancel_reason: row.get(13)?,
+        cancelled_at: get_optional_timestamp(row, 14)?,
+        requested_time: get_optional_timestamp(row, 15)?,
+        dispatched_at: get_optional_timestamp(row, 16)?,
+        estimated_ready_at: get_optional_timestamp(row, 20)?,
+        estimated_arrival_at: get_optional_timestamp(row, 21)?,
+        courier_id: row.get(22)?,
+        courier_location: match (row.get(23)?, row.get(24)?, get_optional_timestamp(row, 25)?) {
+            (Some(lat), Some(lng), Some(updated_at)) => Some(CourierPosition { lat, lng, updated_at }),
+            _ => None,
+        },
+        address: match row.get::<_, Option<String>>(26)? {
+            Some(parts) => Some(serde_json::from_str(&parts).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(26, rusqlite::types::Type::Text, e.into())
+            })?),
+            None => None,
+        },
+        refunded_amount: row.get(27)?,
+        refund_status: match row.get::<_, Option<String>>(28)? {
+            Some(status) => Some(RefundStatus::from_str(&status).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(28, rusqlite::types::Type::Text, e.into())
+            })?),
+            None => None,
+        },
+    })
+}
+
+/**
+ * 批量加载订单的商品项
+ * 用一次查询取回所有订单的商品项，避免逐个订单查询
+ * 
+ * @param conn - 数据库连接
+ * @param orders - 待填充商品项的订单列表
+ * @return ApiResult<()> - 操作结果
+ */
+fn load_order_items(conn: &Connection, orders: &mut [Order]) -> ApiResult<()> {
+    if orders.is_empty() {
+        return Ok(());
+    }
+
+    let placeholders = vec!["?"; orders.len()].join(", ");
+    let query = format!(
+        "SELECT order_id, name, quantity, price FROM order_items 
+         WHERE order_id IN ({}) 
+         ORDER BY order_id, id",
+        placeholders
+    );
+    let mut stmt = conn.prepare(&query)?;
+    let rows = stmt.query_map(params_from_iter(orders.iter().map(|o| o.id)), |row| {
+        Ok((row.get::<_, i64>(0)?, OrderItem {
+            name: row.get(1)?,
+            quantity: row.get(2)?,
+            price: row.get(3)?,
+        }))
+    })?;
+
+    // 按订单ID分组后回填
+    let mut items_by_order: HashMap<i64, Vec<OrderItem>> = HashMap::new();
+    for row in rows {
+        let (order_id, item) = row?;
+        items_by_order.entry(order_id).or_default().push(item);
+    }
+    for order in orders.iter_mut() {
+        order.items = items_by_order.remove(&order.id).unwrap_or_default();
+    }
     Ok(())
 }
 
@@ -64,129 +479,928 @@ pub fn init_db(conn: &Connection) -> SqliteResult<()> {
  * 
  * @param conn - 数据库连接
  * @param order_number - 订单编号
- * @return SqliteResult<Option<Order>> - 查询结果
+ * @return ApiResult<Option<Order>> - 查询结果
  */
-pub fn get_order_by_number(conn: &Connection, order_number: &str) -> SqliteResult<Option<Order>> {
-    // 准备查询语句
-    let mut stmt = conn.prepare(
-        "SELECT id, order_number, customer_name, phone_number, delivery_address, 
-                latitude, longitude, notes, created_at, total_amount, status 
-         FROM orders 
-         WHERE order_number = ?1"
-    )?;
+pub fn get_order_by_number(conn: &Connection, order_number: &str) -> ApiResult<Option<Order>> {
+    let query = format!("SELECT {} FROM orders o WHERE o.order_number = ?1", ORDER_COLUMNS);
+    let Some(mut order) = conn.query_row(&query, params![order_number], map_order_row).optional()? else {
+        return Ok(None);
+    };
 
-    let mut rows = stmt.query(params![order_number])?;
-
-    // 解析查询结果
-    if let Some(row) = rows.next()? {
-        let order_id: i64 = row.get(0)?;
-        let order = Order {
-            id: order_id,
-            order_number: row.get(1)?,
-            customer_name: row.get(2)?,
-            phone_number: row.get(3)?,
-            delivery_address: row.get(4)?,
-            latitude: row.get(5)?,
-            longitude: row.get(6)?,
-            notes: row.get(7)?,
-            created_at: row.get(8)?,
-            total_amount: row.get(9)?,
-            status: OrderStatus::from_str(&row.get::<_, String>(10)?).unwrap_or(OrderStatus::Pending),
-            items: get_order_items(conn, order_id)?, // 获取订单项
-        };
-        Ok(Some(order))
+    // 加载订单项
+    load_order_items(conn, std::slice::from_mut(&mut order))?;
+    Ok(Some(order))
+}
+
+/**
+ * 根据订单编号或短码查询订单
+ * 短码不区分大小写
+ * 
+ * @param conn - 数据库连接
+ * @param reference - 订单编号或短码
+ * @return ApiResult<Option<Order>> - 查询结果
+ */
+pub fn get_order_by_reference(conn: &Connection, reference: &str) -> ApiResult<Option<Order>> {
+    let query = format!(
+        "SELECT {} FROM orders o WHERE o.order_number = ?1 OR o.short_code = ?2",
+        ORDER_COLUMNS
+    );
+    let reference = reference.trim();
+    let Some(mut order) = conn
+        .query_row(&query, params![reference, reference.to_uppercase()], map_order_row)
+        .optional()? else {
+        return Ok(None);
+    };
+
+    load_order_items(conn, std::slice::from_mut(&mut order))?;
+    Ok(Some(order))
+}
+
+/**
+ * 订单列表过滤条件
+ * 由处理器根据查询参数构建
+ */
+#[derive(Debug, Default)]
+pub struct OrderFilter {
+    pub statuses: Vec<OrderStatus>,     // 状态过滤（为空时不过滤）
+    pub from: Option<DateTime<Utc>>,    // 创建时间下限（包含）
+    pub to: Option<DateTime<Utc>>,      // 创建时间上限（不包含）
+    pub search: Option<String>,         // 按客户姓名、电话、订单编号或短码搜索
+    pub courier_id: Option<i64>,        // 按指派的配送员过滤
+    pub limit: u32,                     // 每页数量
+    pub offset: u32,                    // 跳过的记录数
+}
+
+/**
+ * 根据过滤条件构建WHERE子句及其参数
+ * 
+ * @param filter - 过滤条件
+ * @return (String, Vec<Value>) - WHERE子句（可能为空）和参数列表
+ */
+fn build_filter_clause(filter: &OrderFilter) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+
+    if !filter.statuses.is_empty() {
+        let placeholders = vec!["?"; filter.statuses.len()].join(", ");
+        conditions.push(format!("o.status IN ({})", placeholders));
+        values.extend(filter.statuses.iter().map(|s| Value::Text(s.to_string())));
+    }
+    if let Some(from) = filter.from {
+        conditions.push("o.created_at >= ?".to_string());
+        values.push(Value::Text(format_timestamp(&from)));
+    }
+    if let Some(to) = filter.to {
+        conditions.push("o.created_at < ?".to_string());
+        values.push(Value::Text(format_timestamp(&to)));
+    }
+    if let Some(search) = &filter.search {
+        // 转义LIKE通配符，按子串匹配
+        let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+        conditions.push(
+            "(o.customer_name LIKE ? ESCAPE '\\' OR o.phone_number LIKE ? ESCAPE '\\' 
+              OR o.order_number LIKE ? ESCAPE '\\' OR o.short_code LIKE ? ESCAPE '\\')".to_string()
+        );
+        values.extend(std::iter::repeat_n(Value::Text(pattern), 4));
+    }
+
+    if let Some(courier_id) = filter.courier_id {
+        conditions.push("o.courier_id = ?".to_string());
+        values.push(Value::Integer(courier_id));
+    }
+
+    if conditions.is_empty() {
+        (String::new(), values)
     } else {
-        Ok(None)
+        (format!(" WHERE {}", conditions.join(" AND ")), values)
     }
 }
 
+/**
+ * 统计符合条件的订单总数
+ * 
+ * @param conn - 数据库连接
+ * @param filter - 过滤条件（忽略分页参数）
+ * @return ApiResult<i64> - 订单总数
+ */
+pub fn count_orders(conn: &Connection, filter: &OrderFilter) -> ApiResult<i64> {
+    let (where_clause, values) = build_filter_clause(filter);
+    let query = format!("SELECT COUNT(*) FROM orders o{}", where_clause);
+    let total = conn.query_row(&query, params_from_iter(values), |row| row.get(0))?;
+    Ok(total)
+}
+
 /**
  * 获取订单列表
- * 支持按状态筛选
+ * 支持按状态、创建时间和关键字筛选，并按创建时间倒序分页
  * 
  * @param conn - 数据库连接
- * @param status - 可选的状态过滤条件
- * @return SqliteResult<Vec<Order>> - 订单列表
+ * @param filter - 过滤和分页条件
+ * @return ApiResult<Vec<Order>> - 订单列表
  */
-pub fn get_orders(conn: &Connection, status: Option<&str>) -> SqliteResult<Vec<Order>> {
-    // 构建基础查询
-    let mut query = String::from(
-        "SELECT o.id, o.order_number, o.customer_name, o.phone_number, o.delivery_address, 
-                o.latitude, o.longitude, o.notes, o.created_at, o.total_amount, o.status 
-         FROM orders o"
-    );
+pub fn get_orders(conn: &Connection, filter: &OrderFilter) -> ApiResult<Vec<Order>> {
+    let (where_clause, mut values) = build_filter_clause(filter);
 
-    // 添加状态过滤条件
-    if let Some(_) = status {
-        query.push_str(" WHERE o.status = ?1");
-    }
-    query.push_str(" ORDER BY o.created_at DESC");
+    // 构建查询，按ID作为次要排序保证分页稳定
+    let query = format!(
+        "SELECT {} 
+         FROM orders o{}
+         ORDER BY o.created_at DESC, o.id DESC
+         LIMIT ? OFFSET ?",
+        ORDER_COLUMNS, where_clause
+    );
+    values.push(Value::Integer(filter.limit as i64));
+    values.push(Value::Integer(filter.offset as i64));
 
     let mut stmt = conn.prepare(&query)?;
-    
-    // 执行查询
-    let mut rows = if let Some(status_filter) = status {
-        stmt.query(params![status_filter])?
-    } else {
-        stmt.query([])?
+    let mut orders = stmt
+        .query_map(params_from_iter(values), map_order_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // 批量加载订单项
+    load_order_items(conn, &mut orders)?;
+    Ok(orders)
+}
+
+/**
+ * 按订单状态流转规则更新订单状态
+ * 仅当订单当前状态允许改为新状态时更新（见OrderStatus::allowed_previous）
+ * 
+ * @param conn - 数据库连接
+ * @param condition - 定位订单的SQL条件
+ * @param values - SQL条件的参数
+ * @param new_status - 新状态
+ * @return ApiResult<Option<(String, OrderStatus)>> - 更新成功时返回订单编号和更新前的状态，订单不存在时为None
+ */
+fn transition_order_status(
+    conn: &mut Connection,
+    condition: &str,
+    values: Vec<Value>,
+    new_status: &OrderStatus,
+) -> ApiResult<Option<(String, OrderStatus)>> {
+    let tx = conn.transaction()?;
+    let current = tx.query_row(
+        &format!("SELECT id, order_number, status FROM orders WHERE {}", condition),
+        params_from_iter(values),
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)),
+    ).optional()?;
+    let Some((order_id, order_number, previous)) = current else {
+        return Ok(None);
     };
 
-    // 解析查询结果
-    let mut orders = Vec::new();
-    while let Some(row) = rows.next()? {
-        let order_id: i64 = row.get(0)?;
-        let order = Order {
-            id: order_id,
-            order_number: row.get(1)?,
-            customer_name: row.get(2)?,
-            phone_number: row.get(3)?,
-            delivery_address: row.get(4)?,
-            latitude: row.get(5)?,
-            longitude: row.get(6)?,
-            notes: row.get(7)?,
-            created_at: row.get(8)?,
-            total_amount: row.get(9)?,
-            status: OrderStatus::from_str(&row.get::<_, String>(10)?).unwrap_or(OrderStatus::Pending),
-            items: get_order_items(conn, order_id)?,
-        };
-        orders.push(order);
+    let from = new_status.allowed_previous();
+    let query = format!(
+        "UPDATE orders SET status = ? WHERE id = ? AND status IN ({})",
+        vec!["?"; from.len()].join(", ")
+    );
+    let mut values = vec![Value::Text(new_status.to_string()), Value::Integer(order_id)];
+    values.extend(from.iter().map(|s| Value::Text(s.to_string())));
+    if from.is_empty() || tx.execute(&query, params_from_iter(values))? == 0 {
+        return Err(ApiError::InvalidOrderState(format!("Order is {} and cannot become {}", previous, new_status)));
     }
+    tx.commit()?;
 
-    Ok(orders)
+    let previous = OrderStatus::from_str(&previous).map_err(ApiError::Internal)?;
+    Ok(Some((order_number, previous)))
 }
 
 /**
- * 更新订单状态
+ * 根据订单ID更新订单状态
  * 
  * @param conn - 数据库连接
  * @param order_id - 订单ID
  * @param new_status - 新状态
- * @return SqliteResult<bool> - 是否更新成功
+ * @return ApiResult<Option<(String, OrderStatus)>> - 更新成功时返回订单编号和更新前的状态，订单不存在时为None
  */
-pub fn update_order_status(conn: &Connection, order_id: i64, new_status: &str) -> SqliteResult<bool> {
+pub fn update_order_status(conn: &mut Connection, order_id: i64, new_status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>> {
+    transition_order_status(conn, "id = ?", vec![Value::Integer(order_id)], new_status)
+}
+
+/**
+ * 根据订单编号或短码更新订单状态
+ * 
+ * @param conn - 数据库连接
+ * @param reference - 订单编号或短码
+ * @param new_status - 新状态
+ * @return ApiResult<Option<(String, OrderStatus)>> - 更新成功时返回订单编号和更新前的状态，订单不存在时为None
+ */
+pub fn update_order_status_by_reference(
+    conn: &mut Connection,
+    reference: &str,
+    new_status: &OrderStatus,
+) -> ApiResult<Option<(String, OrderStatus)>> {
+    let reference = reference.trim();
+    let values = vec![Value::Text(reference.to_string()), Value::Text(reference.to_uppercase())];
+    transition_order_status(conn, "order_number = ? OR short_code = ?", values, new_status)
+}
+
+/**
+ * 取消订单并记录取消原因
+ * 仅当订单仍处于预期状态时更新，避免覆盖并发发生的状态变化
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @param expected_status - 订单当前应处的状态
+ * @param reason - 取消原因
+ * @param cancelled_at - 取消时间
+ * @return ApiResult<bool> - 是否取消成功
+ */
+pub fn cancel_order(
+    conn: &Connection,
+    order_number: &str,
+    expected_status: &str,
+    reason: Option<&str>,
+    cancelled_at: &DateTime<Utc>,
+) -> ApiResult<bool> {
     let result = conn.execute(
-        "UPDATE orders SET status = ?1 WHERE id = ?2",
-        params![new_status, order_id],
+        "UPDATE orders SET status = ?1, cancel_reason = ?2, cancelled_at = ?3 
+         WHERE order_number = ?4 AND status = ?5",
+        params![
+            OrderStatus::Cancelled.to_string(),
+            reason,
+            format_timestamp(cancelled_at),
+            order_number,
+            expected_status,
+        ],
     )?;
 
     Ok(result > 0)
 }
 
 /**
- * 根据订单编号更新订单状态
+ * 取出到期的暂存预约订单并标记为已发送
+ * 已取消或已不处于待处理状态的订单不会被取出
+ * 
+ * @param conn - 数据库连接
+ * @param due_before - 预约送达时间不晚于该时间的订单视为到期
+ * @param dispatched_at - 发送时间
+ * @return ApiResult<Vec<Order>> - 需要发送给设备的订单
+ */
+pub fn release_held_orders(
+    conn: &mut Connection,
+    due_before: &DateTime<Utc>,
+    dispatched_at: &DateTime<Utc>,
+) -> ApiResult<Vec<Order>> {
+    let tx = conn.transaction()?;
+    let order_numbers = tx
+        .prepare(
+            "UPDATE orders SET dispatched_at = ?1 
+             WHERE dispatched_at IS NULL AND status = ?2 AND requested_time <= ?3 
+             RETURNING order_number"
+        )?
+        .query_map(
+            params![format_timestamp(dispatched_at), OrderStatus::Pending.to_string(), format_timestamp(due_before)],
+            |row| row.get::<_, String>(0),
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut orders = Vec::with_capacity(order_numbers.len());
+    for order_number in &order_numbers {
+        orders.extend(get_order_by_number(&tx, order_number)?);
+    }
+    tx.commit()?;
+    Ok(orders)
+}
+
+/**
+ * 批量保存订单预计时间
+ * 
+ * @param conn - 数据库连接
+ * @param estimates - 订单预计时间列表
+ * @return ApiResult<()> - 操作结果
+ */
+pub fn update_order_estimates(conn: &mut Connection, estimates: &[OrderEstimate]) -> ApiResult<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE orders SET estimated_ready_at = ?1, estimated_arrival_at = ?2 WHERE order_number = ?3"
+        )?;
+        for estimate in estimates {
+            stmt.execute(params![
+                format_timestamp(&estimate.ready_at),
+                format_timestamp(&estimate.arrival_at),
+                estimate.order_number,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/**
+ * 查询门店暂停接单信息
+ * 
+ * @param conn - 数据库连接
+ * @return ApiResult<Option<StorePause>> - 暂停信息，正常接单时返回None
+ */
+pub fn get_store_pause(conn: &Connection) -> ApiResult<Option<StorePause>> {
+    let pause = conn.query_row(
+        "SELECT ordering_paused, pause_reason, paused_at FROM store_settings WHERE id = 1",
+        [],
+        |row| {
+            if !row.get::<_, bool>(0)? {
+                return Ok(None);
+            }
+            Ok(Some(StorePause { reason: row.get(1)?, paused_at: get_timestamp(row, 2)? }))
+        },
+    ).optional()?;
+    Ok(pause.flatten())
+}
+
+/**
+ * 设置或解除门店暂停接单
+ * 
+ * @param conn - 数据库连接
+ * @param pause - 暂停信息，None表示恢复接单
+ * @return ApiResult<()> - 操作结果
+ */
+pub fn set_store_pause(conn: &Connection, pause: Option<&StorePause>) -> ApiResult<()> {
+    conn.execute(
+        "UPDATE store_settings SET ordering_paused = ?1, pause_reason = ?2, paused_at = ?3 WHERE id = 1",
+        params![
+            pause.is_some(),
+            pause.and_then(|p| p.reason.as_deref()),
+            pause.map(|p| format_timestamp(&p.paused_at)),
+        ],
+    )?;
+    Ok(())
+}
+
+/**
+ * 将查询结果行映射为配送员
+ */
+fn map_courier_row(row: &Row) -> rusqlite::Result<Courier> {
+    Ok(Courier {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        phone_number: row.get(2)?,
+        token: row.get(3)?,
+        created_at: get_timestamp(row, 4)?,
+    })
+}
+
+// 配送员查询的列
+const COURIER_COLUMNS: &str = "id, name, phone_number, token, created_at";
+
+/**
+ * 创建配送员
+ * 
+ * @param conn - 数据库连接
+ * @param courier - 配送员信息（ID由数据库生成）
+ * @return ApiResult<Courier> - 创建的配送员
+ */
+pub fn create_courier(conn: &Connection, courier: &Courier) -> ApiResult<Courier> {
+    let id = conn.query_row(
+        "INSERT INTO couriers (name, phone_number, token, created_at) VALUES (?1, ?2, ?3, ?4) RETURNING id",
+        params![courier.name, courier.phone_number, courier.token, format_timestamp(&courier.created_at)],
+        |row| row.get(0),
+    ).map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == ErrorCode::ConstraintViolation => {
+            ApiError::Conflict("Courier token already in use".to_string())
+        }
+        e => e.into(),
+    })?;
+    Ok(Courier { id, ..courier.clone() })
+}
+
+/**
+ * 获取所有配送员
+ * 
+ * @param conn - 数据库连接
+ * @return ApiResult<Vec<Courier>> - 按创建顺序排列的配送员
+ */
+pub fn get_couriers(conn: &Connection) -> ApiResult<Vec<Courier>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM couriers ORDER BY id", COURIER_COLUMNS))?;
+    let couriers = stmt.query_map([], map_courier_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(couriers)
+}
+
+/**
+ * 根据ID查询配送员
+ * 
+ * @param conn - 数据库连接
+ * @param courier_id - 配送员ID
+ * @return ApiResult<Option<Courier>> - 查询结果
+ */
+pub fn get_courier(conn: &Connection, courier_id: i64) -> ApiResult<Option<Courier>> {
+    let query = format!("SELECT {} FROM couriers WHERE id = ?1", COURIER_COLUMNS);
+    Ok(conn.query_row(&query, params![courier_id], map_courier_row).optional()?)
+}
+
+/**
+ * 根据访问令牌查询配送员
+ * 
+ * @param conn - 数据库连接
+ * @param token - 配送员接口访问令牌
+ * @return ApiResult<Option<Courier>> - 查询结果
+ */
+pub fn get_courier_by_token(conn: &Connection, token: &str) -> ApiResult<Option<Courier>> {
+    let query = format!("SELECT {} FROM couriers WHERE token = ?1", COURIER_COLUMNS);
+    Ok(conn.query_row(&query, params![token], map_courier_row).optional()?)
+}
+
+/**
+ * 指派或取消指派订单的配送员，并写入审计记录
+ * 已完成或已取消的订单不能再指派
  * 
  * @param conn - 数据库连接
  * @param order_number - 订单编号
- * @param new_status - 新状态
- * @return SqliteResult<bool> - 是否更新成功
+ * @param courier_id - 配送员ID（None表示取消指派）
+ * @param entry - 审计记录
+ * @return ApiResult<Option<Order>> - 指派后的订单；订单已结束时为None
  */
-pub fn update_order_status_by_number(conn: &Connection, order_number: &str, new_status: &str) -> SqliteResult<bool> {
-    let result = conn.execute(
-        "UPDATE orders SET status = ?1 WHERE order_number = ?2",
-        params![new_status, order_number],
+pub fn assign_courier(
+    conn: &mut Connection,
+    order_number: &str,
+    courier_id: Option<i64>,
+    entry: &OrderAuditEntry,
+) -> ApiResult<Option<Order>> {
+    let tx = conn.transaction()?;
+    let order_id = tx.query_row(
+        "UPDATE orders SET courier_id = ?1 WHERE order_number = ?2 AND status NOT IN (?3, ?4) RETURNING id",
+        params![courier_id, order_number, OrderStatus::Completed.to_string(), OrderStatus::Cancelled.to_string()],
+        |row| row.get::<_, i64>(0),
+    ).optional()?;
+    let Some(order_id) = order_id else {
+        return Ok(None);
+    };
+    insert_audit_entry(&tx, order_id, entry)?;
+
+    let assigned = get_order_by_number(&tx, order_number)?;
+    tx.commit()?;
+    Ok(assigned)
+}
+
+/**
+ * 按配送员上报的事件更新订单状态，并写入审计记录
+ * 仅当订单指派给该配送员且处于预期状态时更新
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @param courier_id - 上报事件的配送员ID
+ * @param from - 允许的当前状态
+ * @param to - 新状态
+ * @param entry - 审计记录
+ * @return ApiResult<bool> - 是否更新成功
+ */
+pub fn update_courier_order_status(
+    conn: &mut Connection,
+    order_number: &str,
+    courier_id: i64,
+    from: &[OrderStatus],
+    to: &OrderStatus,
+    entry: &OrderAuditEntry,
+) -> ApiResult<bool> {
+    let tx = conn.transaction()?;
+    let placeholders = vec!["?"; from.len()].join(", ");
+    let query = format!(
+        "UPDATE orders SET status = ? WHERE order_number = ? AND courier_id = ? AND status IN ({}) RETURNING id",
+        placeholders
+    );
+    let mut values = vec![Value::Text(to.to_string()), Value::Text(order_number.to_string()), Value::Integer(courier_id)];
+    values.extend(from.iter().map(|s| Value::Text(s.to_string())));
+    let order_id = tx.query_row(&query, params_from_iter(values), |row| row.get::<_, i64>(0)).optional()?;
+    let Some(order_id) = order_id else {
+        return Ok(false);
+    };
+    insert_audit_entry(&tx, order_id, entry)?;
+    tx.commit()?;
+    Ok(true)
+}
+
+/**
+ * 更新配送员所有配送中订单的最新位置
+ * 
+ * @param conn - 数据库连接
+ * @param courier_id - 配送员ID
+ * @param location - 配送员位置
+ * @param updated_at - 上报时间
+ * @return ApiResult<usize> - 更新的订单数
+ */
+pub fn update_courier_location(
+    conn: &Connection,
+    courier_id: i64,
+    location: Location,
+    updated_at: &DateTime<Utc>,
+) -> ApiResult<usize> {
+    let updated = conn.execute(
+        "UPDATE orders SET courier_lat = ?1, courier_lng = ?2, courier_located_at = ?3 
+         WHERE courier_id = ?4 AND status = ?5",
+        params![location.lat, location.lng, format_timestamp(updated_at), courier_id, OrderStatus::Delivering.to_string()],
     )?;
+    Ok(updated)
+}
 
-    Ok(result > 0)
+/**
+ * 从地址坐标缓存中查询坐标
+ * 
+ * @param conn - 数据库连接
+ * @param address_key - 规范化地址键
+ * @return ApiResult<Option<Location>> - 缓存的坐标
+ */
+pub fn lookup_geocode(conn: &Connection, address_key: &str) -> ApiResult<Option<Location>> {
+    let location = conn.query_row(
+        "SELECT latitude, longitude FROM geocode_cache WHERE address_key = ?1",
+        params![address_key],
+        |row| Ok(Location { lat: row.get(0)?, lng: row.get(1)? }),
+    ).optional()?;
+    Ok(location)
+}
+
+/**
+ * 保存地址坐标到缓存，已缓存的地址保持最早写入的坐标
+ * 
+ * @param conn - 数据库连接
+ * @param address_key - 规范化地址键
+ * @param location - 坐标
+ * @param updated_at - 更新时间
+ * @return ApiResult<()> - 操作结果
+ */
+pub fn save_geocode(conn: &Connection, address_key: &str, location: Location, updated_at: &DateTime<Utc>) -> ApiResult<()> {
+    conn.execute(
+        "INSERT INTO geocode_cache (address_key, latitude, longitude, updated_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (address_key) DO NOTHING",
+        params![address_key, location.lat, location.lng, format_timestamp(updated_at)],
+    )?;
+    Ok(())
+}
+
+/**
+ * 将查询结果行映射为支付记录
+ */
+fn map_payment_row(row: &Row) -> rusqlite::Result<Payment> {
+    let status: String = row.get(4)?;
+    Ok(Payment {
+        order_number: row.get(0)?,
+        provider: row.get(1)?,
+        reference: row.get(2)?,
+        amount: row.get(3)?,
+        status: PaymentStatus::from_str(&status).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, e.into())
+        })?,
+        created_at: get_timestamp(row, 5)?,
+        updated_at: get_timestamp(row, 6)?,
+        refunded_amount: row.get(7)?,
+    })
+}
+
+// 支付记录查询的列（p为payments，o为orders），已退款金额包含退款中的金额
+const PAYMENT_COLUMNS: &str = "o.order_number, p.provider, p.reference, p.amount, p.status, p.created_at, p.updated_at, 
+     (SELECT COALESCE(SUM(r.amount), 0) FROM refunds r WHERE r.payment_id = p.id AND r.status != 'failed')";
+
+/**
+ * 创建支付记录
+ * 每个订单同时只能有一笔等待结果的支付，避免重复支付
+ * 
+ * @param conn - 数据库连接
+ * @param payment - 支付记录
+ * @return ApiResult<()> - 操作结果，订单不存在时返回OrderNotFound，已有等待结果的支付时返回Conflict
+ */
+pub fn create_payment(conn: &Connection, payment: &Payment) -> ApiResult<()> {
+    let inserted = conn.execute(
+        "INSERT INTO payments (order_id, provider, reference, amount, status, created_at, updated_at) 
+         SELECT id, ?1, ?2, ?3, ?4, ?5, ?6 FROM orders WHERE order_number = ?7 
+         AND NOT EXISTS (SELECT 1 FROM payments p WHERE p.order_id = orders.id AND p.status = ?8)",
+        params![
+            payment.provider,
+            payment.reference,
+            payment.amount,
+            payment.status.to_string(),
+            format_timestamp(&payment.created_at),
+            format_timestamp(&payment.updated_at),
+            payment.order_number,
+            PaymentStatus::Pending.to_string(),
+        ],
+    ).map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == ErrorCode::ConstraintViolation => {
+            ApiError::Conflict("Payment reference already in use".to_string())
+        }
+        e => e.into(),
+    })?;
+    if inserted == 0 {
+        return match get_order_by_number(conn, &payment.order_number)? {
+            Some(_) => Err(ApiError::Conflict("A payment for this order is already in progress".to_string())),
+            None => Err(ApiError::OrderNotFound),
+        };
+    }
+    Ok(())
+}
+
+/**
+ * 根据服务商的支付单号查询支付记录
+ * 
+ * @param conn - 数据库连接
+ * @param reference - 服务商的支付单号
+ * @return ApiResult<Option<Payment>> - 查询结果
+ */
+pub fn get_payment(conn: &Connection, reference: &str) -> ApiResult<Option<Payment>> {
+    let query = format!(
+        "SELECT {} FROM payments p JOIN orders o ON o.id = p.order_id WHERE p.reference = ?1",
+        PAYMENT_COLUMNS
+    );
+    Ok(conn.query_row(&query, params![reference], map_payment_row).optional()?)
+}
+
+/**
+ * 查询订单的所有支付记录
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @return ApiResult<Vec<Payment>> - 按发起顺序排列的支付记录
+ */
+pub fn get_order_payments(conn: &Connection, order_number: &str) -> ApiResult<Vec<Payment>> {
+    let query = format!(
+        "SELECT {} FROM payments p JOIN orders o ON o.id = p.order_id WHERE o.order_number = ?1 ORDER BY p.id",
+        PAYMENT_COLUMNS
+    );
+    let mut stmt = conn.prepare(&query)?;
+    let payments = stmt.query_map(params![order_number], map_payment_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(payments)
+}
+
+/**
+ * 记录支付结果，支付成功且金额与订单总金额一致时将待支付的订单转为待处理
+ * 在同一事务中更新支付记录、订单状态并写入审计记录
+ * 只处理仍在等待结果的支付记录，重复的回调不会产生影响
+ * 
+ * @param conn - 数据库连接
+ * @param reference - 服务商的支付单号
+ * @param status - 支付结果
+ * @param amount - 支付金额（支付成功时为服务商实际收取的金额）
+ * @param updated_at - 处理时间
+ * @param dispatched_at - 订单发送给设备的时间（预约订单未到期时为None）
+ * @param entry - 审计记录
+ * @return ApiResult<Option<Order>> - 订单因本次支付成功转为待处理时返回订单，否则为None
+ */
+pub fn complete_payment(
+    conn: &mut Connection,
+    reference: &str,
+    status: PaymentStatus,
+    amount: Money,
+    updated_at: &DateTime<Utc>,
+    dispatched_at: Option<&DateTime<Utc>>,
+    entry: &OrderAuditEntry,
+) -> ApiResult<Option<Order>> {
+    let tx = conn.transaction()?;
+    let order_id = tx.query_row(
+        "UPDATE payments SET status = ?1, amount = ?2, updated_at = ?3 WHERE reference = ?4 AND status = ?5 RETURNING order_id",
+        params![status.to_string(), amount, format_timestamp(updated_at), reference, PaymentStatus::Pending.to_string()],
+        |row| row.get::<_, i64>(0),
+    ).optional()?;
+    let Some(order_id) = order_id else {
+        return Ok(None);
+    };
+    insert_audit_entry(&tx, order_id, entry)?;
+
+    // 订单在等待支付期间被取消时保持取消状态，金额不符时仍等待支付
+    let paid = if status == PaymentStatus::Succeeded {
+        tx.query_row(
+            "UPDATE orders SET status = ?1, dispatched_at = ?2 WHERE id = ?3 AND status = ?4 AND total_amount = ?5 RETURNING order_number",
+            params![
+                OrderStatus::Pending.to_string(),
+                dispatched_at.map(format_timestamp),
+                order_id,
+                OrderStatus::AwaitingPayment.to_string(),
+                amount,
+            ],
+            |row| row.get::<_, String>(0),
+        ).optional()?
+    } else {
+        None
+    };
+    let order = match paid {
+        Some(order_number) => get_order_by_number(&tx, &order_number)?,
+        None => None,
+    };
+    tx.commit()?;
+    Ok(order)
+}
+
+/**
+ * 将查询结果行映射为退款记录
+ */
+fn map_refund_row(row: &Row) -> rusqlite::Result<Refund> {
+    let status: String = row.get(4)?;
+    Ok(Refund {
+        order_number: row.get(0)?,
+        payment_reference: row.get(1)?,
+        reference: row.get(2)?,
+        amount: row.get(3)?,
+        status: PaymentStatus::from_str(&status).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, e.into())
+        })?,
+        reason: row.get(5)?,
+        created_at: get_timestamp(row, 6)?,
+    })
+}
+
+/**
+ * 预留退款金额，保存一条退款中的记录
+ * 在向服务商申请退款之前调用，退款中和成功的金额合计不能超过原支付金额，
+ * 避免并发的退款重复向服务商申请
+ * 
+ * @param conn - 数据库连接
+ * @param refund - 退款记录（状态为退款中）
+ * @return ApiResult<i64> - 退款记录ID，原支付记录不存在或未支付成功时返回PaymentNotFound，
+ *                          超出剩余可退金额时返回Conflict
+ */
+pub fn reserve_refund(conn: &mut Connection, refund: &Refund) -> ApiResult<i64> {
+    let tx = conn.transaction()?;
+    let payment = tx.query_row(
+        "SELECT p.id, p.amount, 
+             (SELECT COALESCE(SUM(r.amount), 0) FROM refunds r WHERE r.payment_id = p.id AND r.status != ?3) 
+         FROM payments p WHERE p.reference = ?1 AND p.status = ?2",
+        params![refund.payment_reference, PaymentStatus::Succeeded.to_string(), PaymentStatus::Failed.to_string()],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Money>(1)?, row.get::<_, Money>(2)?)),
+    ).optional()?;
+    let Some((payment_id, amount, reserved)) = payment else {
+        return Err(ApiError::PaymentNotFound);
+    };
+    if reserved + refund.amount > amount {
+        return Err(ApiError::Conflict("Refund exceeds the refundable amount".to_string()));
+    }
+
+    tx.execute(
+        "INSERT INTO refunds (payment_id, amount, status, reason, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            payment_id,
+            refund.amount,
+            PaymentStatus::Pending.to_string(),
+            refund.reason,
+            format_timestamp(&refund.created_at),
+        ],
+    )?;
+    let refund_id = tx.last_insert_rowid();
+    tx.commit()?;
+    Ok(refund_id)
+}
+
+/**
+ * 记录服务商的退款结果，并更新订单的已退款金额和退款状态
+ * 在同一事务中写入审计记录；失败的退款释放预留的金额
+ * 
+ * @param conn - 数据库连接
+ * @param refund_id - 退款记录ID
+ * @param status - 退款结果（成功或失败）
+ * @param reference - 服务商的退款单号（退款失败时为None）
+ * @param entry - 审计记录
+ * @return ApiResult<()> - 操作结果，退款记录不存在或已有结果时返回Conflict
+ */
+pub fn finish_refund(
+    conn: &mut Connection,
+    refund_id: i64,
+    status: PaymentStatus,
+    reference: Option<&str>,
+    entry: &OrderAuditEntry,
+) -> ApiResult<()> {
+    let tx = conn.transaction()?;
+    let refund = tx.query_row(
+        "UPDATE refunds SET status = ?1, reference = ?2 WHERE id = ?3 AND status = ?4 RETURNING payment_id, amount",
+        params![status.to_string(), reference, refund_id, PaymentStatus::Pending.to_string()],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Money>(1)?)),
+    ).optional().map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == ErrorCode::ConstraintViolation => {
+            ApiError::Conflict("Refund reference already in use".to_string())
+        }
+        e => e.into(),
+    })?;
+    let Some((payment_id, amount)) = refund else {
+        return Err(ApiError::Conflict("Refund has already been completed".to_string()));
+    };
+
+    let (order_id, refunded, paid) = tx.query_row(
+        "SELECT o.id, o.refunded_amount + ?2, 
+             (SELECT COALESCE(SUM(p.amount), 0) FROM payments p WHERE p.order_id = o.id AND p.status = ?3) 
+         FROM orders o WHERE o.id = (SELECT order_id FROM payments WHERE id = ?1)",
+        params![
+            payment_id,
+            if status == PaymentStatus::Succeeded { amount } else { Money::ZERO },
+            PaymentStatus::Succeeded.to_string(),
+        ],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Money>(1)?, row.get::<_, Money>(2)?)),
+    )?;
+    tx.execute(
+        "UPDATE orders SET refunded_amount = ?1, refund_status = ?2 WHERE id = ?3",
+        params![refunded, RefundStatus::after_refund(status, refunded, paid).to_string(), order_id],
+    )?;
+    insert_audit_entry(&tx, order_id, entry)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/**
+ * 查询订单的所有退款记录
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @return ApiResult<Vec<Refund>> - 按申请顺序排列的退款记录
+ */
+pub fn get_order_refunds(conn: &Connection, order_number: &str) -> ApiResult<Vec<Refund>> {
+    let mut stmt = conn.prepare(
+        "SELECT o.order_number, p.reference, r.reference, r.amount, r.status, r.reason, r.created_at 
+         FROM refunds r 
+         JOIN payments p ON p.id = r.payment_id 
+         JOIN orders o ON o.id = p.order_id 
+         WHERE o.order_number = ?1 
+         ORDER BY r.id"
+    )?;
+    let refunds = stmt.query_map(params![order_number], map_refund_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(refunds)
+}
+
+/**
+ * 插入订单项
+ */
+fn insert_order_items(conn: &Connection, order_id: i64, items: &[OrderItem]) -> ApiResult<()> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO order_items (order_id, name, quantity, price) VALUES (?1, ?2, ?3, ?4)"
+    )?;
+    for item in items {
+        stmt.execute(params![order_id, item.name, item.quantity, item.price])?;
+    }
+    Ok(())
+}
+
+/**
+ * 替换待处理订单的商品并记录审计
+ * 在同一事务中更新总金额、替换商品项并写入审计记录
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @param items - 新的商品列表
+ * @param total_amount - 重新计算的订单总金额
+ * @param entry - 审计记录
+ * @return ApiResult<Option<Order>> - 修改后的订单，订单已不处于待处理状态时返回None
+ */
+pub fn amend_order_items(
+    conn: &mut Connection,
+    order_number: &str,
+    items: &[OrderItem],
+    total_amount: Money,
+    entry: &OrderAuditEntry,
+) -> ApiResult<Option<Order>> {
+    let tx = conn.transaction()?;
+
+    // 仅修改仍处于待处理状态的订单，未提交的事务在返回时自动回滚
+    let order_id = tx.query_row(
+        "UPDATE orders SET total_amount = ?1 WHERE order_number = ?2 AND status = ?3 RETURNING id",
+        params![total_amount, order_number, OrderStatus::Pending.to_string()],
+        |row| row.get::<_, i64>(0),
+    ).optional()?;
+    let Some(order_id) = order_id else {
+        return Ok(None);
+    };
+
+    tx.execute("DELETE FROM order_items WHERE order_id = ?1", params![order_id])?;
+    insert_order_items(&tx, order_id, items)?;
+    insert_audit_entry(&tx, order_id, entry)?;
+
+    let amended = get_order_by_number(&tx, order_number)?;
+    tx.commit()?;
+    Ok(amended)
+}
+
+/**
+ * 写入订单审计记录
+ */
+fn insert_audit_entry(conn: &Connection, order_id: i64, entry: &OrderAuditEntry) -> ApiResult<()> {
+    conn.execute(
+        "INSERT INTO order_audit_log (order_id, action, detail, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![order_id, entry.action, entry.detail.to_string(), format_timestamp(&entry.created_at)],
+    )?;
+    Ok(())
+}
+
+/**
+ * 查询订单的审计记录，按时间先后排列
+ * 
+ * @param conn - 数据库连接
+ * @param order_number - 订单编号
+ * @return ApiResult<Vec<OrderAuditEntry>> - 审计记录列表
+ */
+pub fn get_order_audit(conn: &Connection, order_number: &str) -> ApiResult<Vec<OrderAuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.action, a.detail, a.created_at 
+         FROM order_audit_log a JOIN orders o ON o.id = a.order_id 
+         WHERE o.order_number = ?1 
+         ORDER BY a.id"
+    )?;
+    let entries = stmt.query_map(params![order_number], |row| {
+        let detail: String = row.get(1)?;
+        Ok(OrderAuditEntry {
+            action: row.get(0)?,
+            detail: serde_json::from_str(&detail).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                1,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            ))?,
+            created_at: get_timestamp(row, 2)?,
+        })
+    })?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(entries)
 }
 
 /**
@@ -195,70 +1409,469 @@ pub fn update_order_status_by_number(conn: &Connection, order_number: &str, new_
  * 
  * @param conn - 数据库连接
  * @param order - 订单信息
- * @return SqliteResult<Order> - 创建的订单
+ * @return ApiResult<Order> - 从数据库读回的订单（包含生成的ID和默认值）
  */
-pub fn create_order(conn: &mut Connection, order: &Order) -> SqliteResult<Order> {
+pub fn create_order(conn: &mut Connection, order: &Order) -> ApiResult<Order> {
     // 开始事务
     let tx = conn.transaction()?;
 
     // 插入订单主表
     tx.execute(
-        "INSERT INTO orders (order_number, customer_name, phone_number, delivery_address, latitude, longitude, notes, total_amount, status) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO orders (order_number, short_code, customer_name, phone_number, delivery_address, latitude, longitude, notes, created_at, total_amount, status, lookup_token, requested_time, dispatched_at, fulfilment, table_number, delivery_fee, address_parts) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
         params![
             order.order_number,
+            order.short_code,
             order.customer_name,
             order.phone_number,
             order.delivery_address,
             order.latitude,
             order.longitude,
             order.notes,
+            format_timestamp(&order.created_at),
             order.total_amount,
             order.status.to_string(),
+            order.lookup_token,
+            order.requested_time.as_ref().map(format_timestamp),
+            order.dispatched_at.as_ref().map(format_timestamp),
+            order.fulfilment.kind(),
+            order.fulfilment.table_number(),
+            order.delivery_fee,
+            order.address.as_ref().map(|address| serde_json::json!(address).to_string()),
         ],
-    )?;
+    ).map_err(|e| match e {
+        // 订单编号或短码与已有订单重复
+        rusqlite::Error::SqliteFailure(err, _) if err.code == ErrorCode::ConstraintViolation => {
+            ApiError::Conflict(format!("Order {} already exists", order.order_number))
+        }
+        e => e.into(),
+    })?;
 
     let order_id = tx.last_insert_rowid();
 
     // 插入订单项
-    for item in &order.items {
-        tx.execute(
-            "INSERT INTO order_items (order_id, name, quantity, price) VALUES (?1, ?2, ?3, ?4)",
-            params![order_id, item.name, item.quantity, item.price],
-        )?;
-    }
+    insert_order_items(&tx, order_id, &order.items)?;
+
+    // 在同一事务中读回订单，获取数据库生成的ID和默认值
+    let created = get_order_by_number(&tx, &order.order_number)?
+        .ok_or_else(|| ApiError::Internal(format!("Order {} missing after insert", order_id)))?;
 
     // 提交事务
     tx.commit()?;
 
-    Ok(order.clone())
+    Ok(created)
 }
 
 /**
- * 获取订单的商品项列表
- * 
- * @param conn - 数据库连接
- * @param order_id - 订单ID
- * @return SqliteResult<Vec<OrderItem>> - 订单项列表
+ * 单元测试模块
  */
-pub fn get_order_items(conn: &Connection, order_id: i64) -> SqliteResult<Vec<OrderItem>> {
-    let mut stmt = conn.prepare(
-        "SELECT name, quantity, price FROM order_items WHERE order_id = ?1"
-    )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SubsecRound;
 
-    // 查询并映射结果
-    let items = stmt.query_map(params![order_id], |row| {
-        Ok(OrderItem {
-            name: row.get(0)?,
-            quantity: row.get(1)?,
-            price: row.get(2)?,
-        })
-    })?;
+    fn sample_order(order_number: &str, customer_name: &str, status: OrderStatus) -> Order {
+        Order {
+            customer_name: customer_name.to_string(),
+            fulfilment: Fulfilment::Delivery,
+            delivery_address: Some("测试地址".to_string()),
+            latitude: Some(30.0),
+            longitude: Some(120.0),
+            created_at: Utc::now(),
+            delivery_fee: Money::from_cents(500),
+            total_amount: Money::from_cents(1500),
+            status,
+            items: vec![OrderItem { name: "拿铁".to_string(), quantity: 1, price: Money::from_cents(1000) }],
+            lookup_token: Some("token".to_string()),
+            dispatched_at: Some(Utc::now()),
+            ..Order::sample(order_number)
+        }
+    }
 
-    // 收集结果
-    let mut result = Vec::new();
-    for item in items {
-        result.push(item?);
+    #[test]
+    fn test_get_orders_with_filter() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let created = create_order(&mut conn, &sample_order("A001", "张三", OrderStatus::Pending)).unwrap();
+        assert_eq!(created.id, 1);
+        assert_eq!(created.items.len(), 1);
+        assert_eq!(created.delivery_fee, Money::from_cents(500));
+        create_order(&mut conn, &sample_order("A002", "李四", OrderStatus::Preparing)).unwrap();
+        create_order(&mut conn, &sample_order("A003", "张_五", OrderStatus::Completed)).unwrap();
+
+        // 短码重复时返回冲突错误
+        let mut duplicate = sample_order("A004", "赵六", OrderStatus::Pending);
+        duplicate.short_code = "A001".to_string();
+        assert!(matches!(create_order(&mut conn, &duplicate), Err(ApiError::Conflict(_))));
+
+        let filter = OrderFilter {
+            statuses: vec![OrderStatus::Pending, OrderStatus::Preparing],
+            limit: 1,
+            offset: 0,
+            ..Default::default()
+        };
+        assert_eq!(count_orders(&conn, &filter).unwrap(), 2);
+        let orders = get_orders(&conn, &filter).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_number, "A002");
+        assert_eq!(orders[0].items.len(), 1);
+
+        // 搜索关键字中的通配符按字面匹配
+        let filter = OrderFilter { search: Some("_".to_string()), limit: 10, ..Default::default() };
+        let orders = get_orders(&conn, &filter).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].customer_name, "张_五");
+    }
+
+    #[test]
+    fn test_amend_order_items() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        create_order(&mut conn, &sample_order("A001", "张三", OrderStatus::Pending)).unwrap();
+        create_order(&mut conn, &sample_order("A002", "李四", OrderStatus::Preparing)).unwrap();
+
+        let items = vec![
+            OrderItem { name: "美式".to_string(), quantity: 2, price: Money::from_cents(1500) },
+            OrderItem { name: "拿铁".to_string(), quantity: 1, price: Money::from_cents(1800) },
+        ];
+        let entry = OrderAuditEntry {
+            action: "items_amended".to_string(),
+            detail: serde_json::json!({ "reason": "改单" }),
+            created_at: Utc::now(),
+        };
+        let amended = amend_order_items(&mut conn, "A001", &items, Money::from_cents(4800), &entry).unwrap().unwrap();
+        assert_eq!(amended.total_amount, Money::from_cents(4800));
+        assert_eq!(amended.items.len(), 2);
+        assert_eq!(get_order_audit(&conn, "A001").unwrap()[0].detail["reason"], "改单");
+
+        // 非待处理订单不修改，也不写审计记录
+        assert!(amend_order_items(&mut conn, "A002", &items, Money::from_cents(4800), &entry).unwrap().is_none());
+        let unchanged = get_order_by_number(&conn, "A002").unwrap().unwrap();
+        assert_eq!(unchanged.items.len(), 1);
+        assert!(get_order_audit(&conn, "A002").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_release_held_orders() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let now = Utc::now().trunc_subsecs(0);
+        for (order_number, hours, status) in [
+            ("A001", 1, OrderStatus::Pending),
+            ("A002", 3, OrderStatus::Pending),
+            ("A003", 1, OrderStatus::Cancelled),
+        ] {
+            let mut order = sample_order(order_number, "张三", status);
+            order.requested_time = Some(now + chrono::Duration::hours(hours));
+            order.dispatched_at = None;
+            create_order(&mut conn, &order).unwrap();
+        }
+
+        // 只取出到期且仍待处理的订单，且不会重复取出
+        let due_before = now + chrono::Duration::hours(2);
+        let released = release_held_orders(&mut conn, &due_before, &now).unwrap();
+        assert_eq!(released.iter().map(|o| o.order_number.as_str()).collect::<Vec<_>>(), vec!["A001"]);
+        assert_eq!(released[0].dispatched_at, Some(now));
+        assert!(release_held_orders(&mut conn, &due_before, &now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_courier_assignment() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        create_order(&mut conn, &sample_order("A001", "张三", OrderStatus::Preparing)).unwrap();
+        create_order(&mut conn, &sample_order("A002", "李四", OrderStatus::Completed)).unwrap();
+        let courier = create_courier(&conn, &Courier {
+            id: 0,
+            name: "王师傅".to_string(),
+            phone_number: "13900139000".to_string(),
+            token: "courier-token".to_string(),
+            created_at: Utc::now().trunc_subsecs(0),
+        }).unwrap();
+        assert_eq!(get_courier_by_token(&conn, "courier-token").unwrap().unwrap().id, courier.id);
+        assert!(get_courier_by_token(&conn, "other").unwrap().is_none());
+
+        let entry = OrderAuditEntry {
+            action: "courier_assigned".to_string(),
+            detail: serde_json::json!({ "after": courier.id }),
+            created_at: Utc::now().trunc_subsecs(0),
+        };
+        let assigned = assign_courier(&mut conn, "A001", Some(courier.id), &entry).unwrap().unwrap();
+        assert_eq!(assigned.courier_id, Some(courier.id));
+        // 已完成的订单不能指派
+        assert!(assign_courier(&mut conn, "A002", Some(courier.id), &entry).unwrap().is_none());
+
+        let filter = OrderFilter { courier_id: Some(courier.id), limit: 10, ..Default::default() };
+        assert_eq!(get_orders(&conn, &filter).unwrap().len(), 1);
+
+        // 状态不符时不更新
+        let from = [OrderStatus::Pending, OrderStatus::Preparing];
+        assert!(update_courier_order_status(&mut conn, "A001", courier.id, &from, &OrderStatus::Delivering, &entry).unwrap());
+        assert!(!update_courier_order_status(&mut conn, "A001", courier.id, &from, &OrderStatus::Delivering, &entry).unwrap());
+        assert_eq!(get_order_audit(&conn, "A001").unwrap().len(), 2);
+
+        // 配送中订单保存最新位置，完成后清除
+        let location = Location { lat: 39.99, lng: 116.32 };
+        assert_eq!(update_courier_location(&conn, courier.id, location, &Utc::now()).unwrap(), 1);
+        let position = get_order_by_number(&conn, "A001").unwrap().unwrap().courier_location.unwrap();
+        assert_eq!((position.lat, position.lng), (location.lat, location.lng));
+        update_order_status_by_reference(&mut conn, "A001", &OrderStatus::Completed).unwrap();
+        // 已完成的订单不能再改变状态
+        assert!(matches!(
+            update_order_status_by_reference(&mut conn, "A001", &OrderStatus::Cancelled),
+            Err(ApiError::InvalidOrderState(_))
+        ));
+        assert!(get_order_by_number(&conn, "A001").unwrap().unwrap().courier_location.is_none());
+    }
+
+    #[test]
+    fn test_payment_completion() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let mut order = sample_order("A001", "张三", OrderStatus::AwaitingPayment);
+        order.dispatched_at = None;
+        create_order(&mut conn, &order).unwrap();
+        let now = Utc::now().trunc_subsecs(0);
+        let payment = |reference: &str| Payment {
+            order_number: "A001".to_string(),
+            provider: "mock".to_string(),
+            reference: reference.to_string(),
+            amount: order.total_amount,
+            status: PaymentStatus::Pending,
+            refunded_amount: Money::ZERO,
+            created_at: now,
+            updated_at: now,
+        };
+        create_payment(&conn, &payment("P1")).unwrap();
+        // 已有等待结果的支付时不能再发起支付
+        assert!(matches!(create_payment(&conn, &payment("P2")), Err(ApiError::Conflict(_))));
+        assert!(matches!(create_payment(&conn, &Payment { order_number: "X".to_string(), ..payment("P3") }), Err(ApiError::OrderNotFound)));
+
+        let entry = OrderAuditEntry {
+            action: "payment_failed".to_string(),
+            detail: serde_json::json!({}),
+            created_at: now,
+        };
+        // 支付失败时订单仍等待支付
+        assert!(complete_payment(&mut conn, "P1", PaymentStatus::Failed, order.total_amount, &now, Some(&now), &entry).unwrap().is_none());
+        assert_eq!(get_payment(&conn, "P1").unwrap().unwrap().status, PaymentStatus::Failed);
+        assert_eq!(get_order_by_number(&conn, "A001").unwrap().unwrap().status, OrderStatus::AwaitingPayment);
+        assert!(matches!(create_payment(&conn, &payment("P1")), Err(ApiError::Conflict(_))));
+        create_payment(&conn, &payment("P2")).unwrap();
+
+        // 实际收取的金额与订单不符时订单仍等待支付
+        assert!(complete_payment(&mut conn, "P2", PaymentStatus::Succeeded, Money::from_cents(1), &now, Some(&now), &entry).unwrap().is_none());
+        let mismatched = get_payment(&conn, "P2").unwrap().unwrap();
+        assert_eq!((mismatched.status, mismatched.amount), (PaymentStatus::Succeeded, Money::from_cents(1)));
+        assert_eq!(get_order_by_number(&conn, "A001").unwrap().unwrap().status, OrderStatus::AwaitingPayment);
+        create_payment(&conn, &payment("P3")).unwrap();
+
+        let paid = complete_payment(&mut conn, "P3", PaymentStatus::Succeeded, order.total_amount, &now, Some(&now), &entry).unwrap().unwrap();
+        assert_eq!(paid.status, OrderStatus::Pending);
+        assert_eq!(paid.dispatched_at, Some(now));
+        // 重复的回调不再处理
+        assert!(complete_payment(&mut conn, "P3", PaymentStatus::Failed, order.total_amount, &now, None, &entry).unwrap().is_none());
+        assert_eq!(get_payment(&conn, "P3").unwrap().unwrap().status, PaymentStatus::Succeeded);
+        assert_eq!(get_order_audit(&conn, "A001").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_refund_totals() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let order = create_order(&mut conn, &sample_order("A001", "张三", OrderStatus::Pending)).unwrap();
+        let now = Utc::now().trunc_subsecs(0);
+        create_payment(&conn, &Payment {
+            order_number: "A001".to_string(),
+            provider: "mock".to_string(),
+            reference: "P1".to_string(),
+            amount: order.total_amount,
+            status: PaymentStatus::Pending,
+            refunded_amount: Money::ZERO,
+            created_at: now,
+            updated_at: now,
+        }).unwrap();
+        let entry = OrderAuditEntry {
+            action: "refund".to_string(),
+            detail: serde_json::json!({}),
+            created_at: now,
+        };
+        let refund = |cents: i64| Refund {
+            order_number: "A001".to_string(),
+            payment_reference: "P1".to_string(),
+            reference: None,
+            amount: Money::from_cents(cents),
+            status: PaymentStatus::Pending,
+            reason: None,
+            created_at: now,
+        };
+
+        // 支付成功前不能退款
+        assert!(matches!(reserve_refund(&mut conn, &refund(300)), Err(ApiError::PaymentNotFound)));
+        complete_payment(&mut conn, "P1", PaymentStatus::Succeeded, order.total_amount, &now, None, &entry).unwrap();
+
+        let r1 = reserve_refund(&mut conn, &refund(300)).unwrap();
+        finish_refund(&mut conn, r1, PaymentStatus::Succeeded, Some("R1"), &entry).unwrap();
+        assert!(matches!(finish_refund(&mut conn, r1, PaymentStatus::Failed, None, &entry), Err(ApiError::Conflict(_))));
+        let order = get_order_by_number(&conn, "A001").unwrap().unwrap();
+        assert_eq!(order.refunded_amount, Money::from_cents(300));
+        assert_eq!(order.refund_status, Some(RefundStatus::PartiallyRefunded));
+
+        // 退款中的金额已预留，并发的退款不能超出可退金额
+        let r2 = reserve_refund(&mut conn, &refund(1200)).unwrap();
+        assert!(matches!(reserve_refund(&mut conn, &refund(1)), Err(ApiError::Conflict(_))));
+        assert_eq!(get_order_payments(&conn, "A001").unwrap()[0].refunded_amount, order.total_amount);
+
+        // 失败的退款释放预留金额，不计入已退款金额
+        finish_refund(&mut conn, r2, PaymentStatus::Failed, None, &entry).unwrap();
+        let order = get_order_by_number(&conn, "A001").unwrap().unwrap();
+        assert_eq!(order.refunded_amount, Money::from_cents(300));
+        assert_eq!(order.refund_status, Some(RefundStatus::Failed));
+        assert!(matches!(reserve_refund(&mut conn, &refund(1201)), Err(ApiError::Conflict(_))));
+
+        let r3 = reserve_refund(&mut conn, &refund(1200)).unwrap();
+        finish_refund(&mut conn, r3, PaymentStatus::Succeeded, Some("R2"), &entry).unwrap();
+        let order = get_order_by_number(&conn, "A001").unwrap().unwrap();
+        assert_eq!(order.refunded_amount, order.total_amount);
+        assert_eq!(order.refund_status, Some(RefundStatus::Refunded));
+        assert_eq!(get_order_refunds(&conn, "A001").unwrap().len(), 3);
+        assert_eq!(get_order_audit(&conn, "A001").unwrap().len(), 4);
     }
-    Ok(result)
-}
\ No newline at end of file
+
+    #[test]
+    fn test_store_pause() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        assert_eq!(get_store_pause(&conn).unwrap(), None);
+
+        let pause = StorePause { reason: Some("设备维护".to_string()), paused_at: Utc::now().trunc_subsecs(0) };
+        set_store_pause(&conn, Some(&pause)).unwrap();
+        assert_eq!(get_store_pause(&conn).unwrap(), Some(pause));
+
+        set_store_pause(&conn, None).unwrap();
+        assert_eq!(get_store_pause(&conn).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_float_amounts_to_cents() {
+        // 模拟迁移前以浮点数存储金额的数据库
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE orders (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, order_number TEXT NOT NULL UNIQUE,
+                customer_name TEXT NOT NULL, phone_number TEXT NOT NULL, delivery_address TEXT NOT NULL,
+                latitude REAL NOT NULL, longitude REAL NOT NULL, notes TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP, total_amount DECIMAL(10,2) NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending');
+             CREATE TABLE order_items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, order_id INTEGER NOT NULL, name TEXT NOT NULL,
+                quantity INTEGER NOT NULL, price REAL NOT NULL);
+             INSERT INTO orders (order_number, customer_name, phone_number, delivery_address, latitude, longitude, created_at, total_amount)
+                VALUES ('OLD1', '张三', '13800138000', '测试地址', 30.0, 120.0, '2024-05-01 12:30:00', 45.5);
+             INSERT INTO order_items (order_id, name, quantity, price) VALUES (1, '拿铁', 3, 3.5), (1, '美式', 1, 35.0);"
+        ).unwrap();
+
+        init_db(&conn).unwrap();
+        // 重复初始化不会再次执行迁移
+        init_db(&conn).unwrap();
+
+        let order = get_order_by_number(&conn, "OLD1").unwrap().unwrap();
+        assert_eq!(order.total_amount, Money::from_cents(4550));
+        assert_eq!(format_timestamp(&order.created_at), "2024-05-01T12:30:00Z");
+        // 创建时间由应用写入，列上不再有默认值
+        let (not_null, default): (bool, Option<String>) = conn.query_row(
+            "SELECT \"notnull\", dflt_value FROM pragma_table_info('orders') WHERE name = 'created_at'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert!(not_null && default.is_none());
+        assert_eq!(order.items[0].price, Money::from_cents(350));
+        assert_eq!(order.items.iter().map(|i| i.price * i.quantity).sum::<Money>(), order.total_amount);
+
+        assert_eq!(order.fulfilment, Fulfilment::Delivery);
+        assert_eq!(order.delivery_address.as_deref(), Some("测试地址"));
+
+        // 旧订单补齐了短码，可按短码查询
+        assert_eq!(order.short_code, "OLD1");
+        assert_eq!(get_order_by_reference(&conn, "old1").unwrap().unwrap().id, order.id);
+    }
+
+    /**
+     * 大数据量下订单列表加载的基准测试
+     * 与逐个订单查询商品项的方式对比耗时
+     * 运行方式: cargo test --release bench_get_orders -- --ignored --nocapture
+     */
+    #[test]
+    #[ignore]
+    fn bench_get_orders_large_database() {
+        use std::time::Instant;
+
+        const ORDER_COUNT: usize = 20_000;
+        const ITEMS_PER_ORDER: usize = 3;
+
+        // 生成测试数据
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let statuses = [OrderStatus::Pending, OrderStatus::Preparing, OrderStatus::Delivering, OrderStatus::Completed];
+        let started = Instant::now();
+        let tx = conn.transaction().unwrap();
+        for i in 0..ORDER_COUNT {
+            tx.execute(
+                "INSERT INTO orders (order_number, customer_name, phone_number, delivery_address, latitude, longitude, total_amount, status, created_at) 
+                 VALUES (?1, ?2, '13800138000', '测试地址', 30.0, 120.0, 3000, ?3, strftime('%Y-%m-%dT%H:%M:%SZ', '2024-01-01', ?4))",
+                params![format!("N{:06}", i), format!("客户{}", i), statuses[i % statuses.len()].to_string(), format!("+{} minutes", i)],
+            ).unwrap();
+            let order_id = tx.last_insert_rowid();
+            for j in 0..ITEMS_PER_ORDER {
+                tx.execute(
+                    "INSERT INTO order_items (order_id, name, quantity, price) VALUES (?1, ?2, 1, 1000)",
+                    params![order_id, format!("商品{}", j)],
+                ).unwrap();
+            }
+        }
+        tx.commit().unwrap();
+        println!("seeded {} orders in {:?}", ORDER_COUNT, started.elapsed());
+
+        // 逐个订单查询商品项（旧实现）
+        let naive = |conn: &Connection, orders: &mut [Order]| {
+            let mut stmt = conn.prepare("SELECT name, quantity, price FROM order_items WHERE order_id = ?1").unwrap();
+            for order in orders.iter_mut() {
+                order.items = stmt.query_map(params![order.id], |row| Ok(OrderItem {
+                    name: row.get(0)?,
+                    quantity: row.get(1)?,
+                    price: row.get(2)?,
+                })).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+            }
+        };
+
+        for limit in [20, 100, ORDER_COUNT as u32] {
+            let filter = OrderFilter { statuses: vec![OrderStatus::Pending, OrderStatus::Preparing], limit, ..Default::default() };
+
+            let started = Instant::now();
+            let orders = get_orders(&conn, &filter).unwrap();
+            let total = started.elapsed();
+            assert!(orders.iter().all(|o| o.items.len() == ITEMS_PER_ORDER));
+
+            // 单独比较商品项加载的耗时
+            let unloaded: Vec<Order> = orders.iter().cloned().map(|o| Order { items: Vec::new(), ..o }).collect();
+            let mut batched_orders = unloaded.clone();
+            let started = Instant::now();
+            load_order_items(&conn, &mut batched_orders).unwrap();
+            let batched = started.elapsed();
+
+            let mut naive_orders = unloaded;
+            let started = Instant::now();
+            naive(&conn, &mut naive_orders);
+            let per_order = started.elapsed();
+
+            println!(
+                "limit {:>6}: {} orders in {:?}; items batched {:?}, per-order {:?}",
+                limit, orders.len(), total, batched, per_order
+            );
+        }
+
+        let started = Instant::now();
+        let total = count_orders(&conn, &OrderFilter { statuses: vec![OrderStatus::Completed], ..Default::default() }).unwrap();
+        println!("count {} completed orders in {:?}", total, started.elapsed());
+    }
+}
@@ -0,0 +1,348 @@
+/*!
+ * 配送范围模块
+ * 从GeoJSON文件加载配送区域，判断配送坐标是否在可配送范围内
+ * 并按门店到配送地址的距离计算外送费
+ */
+
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use crate::models::{Location, Money};
+
+// 地球平均半径（公里），用于计算球面距离
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/**
+ * 多边形
+ * 坐标按GeoJSON约定为（经度，纬度），第一个环为外边界，其余为内部空洞
+ */
+#[derive(Debug, Clone, PartialEq)]
+struct Polygon {
+    rings: Vec<Vec<(f64, f64)>>, // 外边界和空洞
+}
+
+impl Polygon {
+    /**
+     * 判断坐标是否在多边形内（在外边界内且不在任何空洞内）
+     */
+    fn contains(&self, lng: f64, lat: f64) -> bool {
+        match self.rings.split_first() {
+            Some((exterior, holes)) => {
+                ring_contains(exterior, lng, lat) && !holes.iter().any(|hole| ring_contains(hole, lng, lat))
+            }
+            None => false,
+        }
+    }
+}
+
+/**
+ * 射线法判断坐标是否在闭合环内
+ */
+fn ring_contains(ring: &[(f64, f64)], lng: f64, lat: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > lat) != (yj > lat) && lng < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/**
+ * 配送区域
+ * 对应GeoJSON中的一个Polygon或MultiPolygon要素
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryZone {
+    pub name: Option<String>, // 区域名称，取自要素的properties.name
+    polygons: Vec<Polygon>,   // 区域包含的多边形
+}
+
+impl DeliveryZone {
+    /**
+     * 判断坐标是否在该区域内
+     *
+     * @param lat - 纬度
+     * @param lng - 经度
+     * @return bool - 是否在区域内
+     */
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        self.polygons.iter().any(|polygon| polygon.contains(lng, lat))
+    }
+}
+
+/**
+ * 全部配送区域
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeliveryZones {
+    zones: Vec<DeliveryZone>, // 配送区域列表
+}
+
+impl DeliveryZones {
+    /**
+     * 解析GeoJSON格式的配送区域
+     * 支持FeatureCollection、Feature以及Polygon和MultiPolygon几何对象
+     *
+     * @param geojson - GeoJSON文本
+     * @return Result<DeliveryZones, String> - 解析结果
+     */
+    pub fn from_geojson(geojson: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(geojson).map_err(|e| format!("Invalid GeoJSON: {}", e))?;
+        let mut zones = Vec::new();
+        collect_zones(&value, None, &mut zones)?;
+        if zones.is_empty() {
+            return Err("GeoJSON contains no delivery zone polygons".to_string());
+        }
+        Ok(DeliveryZones { zones })
+    }
+
+    /**
+     * 从文件加载配送区域
+     *
+     * @param path - GeoJSON文件路径
+     * @return Result<DeliveryZones, String> - 加载结果
+     */
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Self::from_geojson(&content)
+    }
+
+    /**
+     * 查找包含坐标的配送区域
+     *
+     * @param lat - 纬度
+     * @param lng - 经度
+     * @return Option<&DeliveryZone> - 第一个包含该坐标的区域，不在任何区域内时返回None
+     */
+    pub fn zone_for(&self, lat: f64, lng: f64) -> Option<&DeliveryZone> {
+        self.zones.iter().find(|zone| zone.contains(lat, lng))
+    }
+}
+
+/**
+ * 计算两个坐标之间的球面距离（haversine公式）
+ *
+ * @param from - 起点
+ * @param to - 终点
+ * @return f64 - 距离（公里）
+ */
+pub fn haversine_km(from: Location, to: Location) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (to.lng - from.lng).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/**
+ * 外送费档位
+ * 配送距离不超过max_km时收取fee
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeTier {
+    pub max_km: f64, // 档位的最大配送距离（公里）
+    pub fee: Money,  // 外送费
+}
+
+/**
+ * 外送费报价
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeQuote {
+    pub distance_km: f64, // 门店到配送地址的距离（公里）
+    pub fee: Money,       // 外送费
+}
+
+/**
+ * 外送费规则
+ * 按门店到配送地址的直线距离所在的档位收费，超过最远档位的地址不配送
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryFees {
+    store: Location,     // 门店坐标
+    tiers: Vec<FeeTier>, // 按距离从近到远排列的收费档位
+}
+
+impl DeliveryFees {
+    /**
+     * 创建外送费规则
+     *
+     * @param store - 门店坐标
+     * @param tiers - 收费档位，格式见parse_tiers
+     * @return Result<DeliveryFees, String> - 档位为空或格式错误时返回错误
+     */
+    pub fn new(store: Location, tiers: &str) -> Result<Self, String> {
+        Ok(DeliveryFees { store, tiers: parse_tiers(tiers)? })
+    }
+
+    /**
+     * 计算门店到某个坐标的距离
+     *
+     * @param to - 配送地址坐标
+     * @return f64 - 距离（公里）
+     */
+    pub fn distance_km(&self, to: Location) -> f64 {
+        haversine_km(self.store, to)
+    }
+
+    /**
+     * 计算配送到某个坐标的外送费
+     *
+     * @param to - 配送地址坐标
+     * @return Option<FeeQuote> - 报价，超过最远档位时返回None
+     */
+    pub fn quote(&self, to: Location) -> Option<FeeQuote> {
+        let distance_km = self.distance_km(to);
+        self.tiers.iter()
+            .find(|tier| distance_km <= tier.max_km)
+            .map(|tier| FeeQuote { distance_km, fee: tier.fee })
+    }
+}
+
+/**
+ * 解析外送费档位
+ * 格式为以分号分隔的"最大距离(公里)=外送费(元)"，如"3=5;5=8;8=12"
+ */
+fn parse_tiers(s: &str) -> Result<Vec<FeeTier>, String> {
+    let mut tiers = s.split(';').map(str::trim).filter(|e| !e.is_empty())
+        .map(|entry| {
+            let invalid = || format!("Invalid delivery fee tier: {}", entry);
+            let (max_km, fee) = entry.split_once('=').ok_or_else(invalid)?;
+            let max_km: f64 = max_km.trim().parse().map_err(|_| invalid())?;
+            let fee = Money::from_str(fee.trim()).map_err(|_| invalid())?;
+            if !(max_km.is_finite() && max_km > 0.0) || fee.is_negative() {
+                return Err(invalid());
+            }
+            Ok(FeeTier { max_km, fee })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if tiers.is_empty() {
+        return Err("No delivery fee tiers configured".to_string());
+    }
+    tiers.sort_by(|a, b| a.max_km.total_cmp(&b.max_km));
+    Ok(tiers)
+}
+
+/**
+ * 递归收集GeoJSON对象中的配送区域
+ */
+fn collect_zones(value: &Value, name: Option<String>, zones: &mut Vec<DeliveryZone>) -> Result<(), String> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            let features = value["features"].as_array().ok_or("FeatureCollection without features")?;
+            for feature in features {
+                collect_zones(feature, None, zones)?;
+            }
+        }
+        Some("Feature") => {
+            let name = value["properties"]["name"].as_str().map(str::to_string);
+            collect_zones(&value["geometry"], name, zones)?;
+        }
+        Some("Polygon") => {
+            zones.push(DeliveryZone { name, polygons: vec![parse_polygon(&value["coordinates"])?] });
+        }
+        Some("MultiPolygon") => {
+            let polygons = value["coordinates"].as_array().ok_or("MultiPolygon without coordinates")?
+                .iter()
+                .map(parse_polygon)
+                .collect::<Result<_, _>>()?;
+            zones.push(DeliveryZone { name, polygons });
+        }
+        Some(other) => log::warn!("Ignoring unsupported GeoJSON type {} in delivery zones", other),
+        None => return Err("GeoJSON object without type".to_string()),
+    }
+    Ok(())
+}
+
+/**
+ * 解析多边形坐标数组
+ */
+fn parse_polygon(value: &Value) -> Result<Polygon, String> {
+    let rings = value.as_array().ok_or("Polygon coordinates must be an array")?
+        .iter()
+        .map(|ring| {
+            let points = ring.as_array().ok_or("Polygon ring must be an array")?
+                .iter()
+                .map(|point| match point.as_array().map(|p| (p.first().and_then(Value::as_f64), p.get(1).and_then(Value::as_f64))) {
+                    Some((Some(lng), Some(lat))) => Ok((lng, lat)),
+                    _ => Err("Polygon position must be [lng, lat]".to_string()),
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if points.len() < 4 {
+                return Err("Polygon ring needs at least 4 positions".to_string());
+            }
+            Ok(points)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if rings.is_empty() {
+        return Err("Polygon without rings".to_string());
+    }
+    Ok(Polygon { rings })
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_lookup() {
+        // 中关村区域挖去中间一块，另有一个多边形组成的望京区域
+        let zones = DeliveryZones::from_geojson(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": { "name": "中关村" }, "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[116.28, 39.96], [116.34, 39.96], [116.34, 40.00], [116.28, 40.00], [116.28, 39.96]],
+                        [[116.30, 39.97], [116.31, 39.97], [116.31, 39.98], [116.30, 39.98], [116.30, 39.97]]
+                    ]
+                } },
+                { "type": "Feature", "properties": { "name": "望京" }, "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[116.46, 39.98], [116.50, 39.98], [116.48, 40.02], [116.46, 39.98]]]]
+                } }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(zones.zone_for(39.99, 116.32).unwrap().name.as_deref(), Some("中关村"));
+        assert!(zones.zone_for(39.975, 116.305).is_none()); // 空洞内
+        assert_eq!(zones.zone_for(39.99, 116.48).unwrap().name.as_deref(), Some("望京"));
+        assert!(zones.zone_for(39.99, 116.46).is_none());
+        assert!(zones.zone_for(39.90, 116.40).is_none());
+    }
+
+    #[test]
+    fn test_delivery_fee_tiers() {
+        // 中关村到天安门约10.9公里
+        let zhongguancun = Location { lat: 39.9834, lng: 116.3164 };
+        let tiananmen = Location { lat: 39.9087, lng: 116.3975 };
+        let distance = haversine_km(zhongguancun, tiananmen);
+        assert!((distance - 10.9).abs() < 0.2, "distance {}", distance);
+
+        let fees = DeliveryFees::new(zhongguancun, "12=10; 3=5; 5=8").unwrap();
+        assert_eq!(fees.quote(zhongguancun).unwrap().fee, Money::from_cents(500));
+        assert_eq!(fees.quote(tiananmen).unwrap().fee, Money::from_cents(1000));
+        assert!(fees.quote(Location { lat: 40.2, lng: 116.3 }).is_none());
+
+        assert!(DeliveryFees::new(zhongguancun, "").is_err());
+        assert!(DeliveryFees::new(zhongguancun, "3=-1").is_err());
+        assert!(DeliveryFees::new(zhongguancun, "abc=5").is_err());
+    }
+
+    #[test]
+    fn test_invalid_geojson() {
+        assert!(DeliveryZones::from_geojson("not json").is_err());
+        assert!(DeliveryZones::from_geojson(r#"{ "type": "FeatureCollection", "features": [] }"#).is_err());
+        assert!(DeliveryZones::from_geojson(r#"{ "type": "Polygon", "coordinates": [[[116.3, 39.9], [116.4, 39.9]]] }"#).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+ * 错误处理模块
+ * 定义统一的API错误类型及其JSON响应格式
+ * 每种错误对应稳定的错误代码，供前端识别
+ */
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::validation::FieldError;
+
+/// 统一的结果类型
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/**
+ * API错误类型
+ * 所有处理器和数据库操作的错误都转换为该类型
+ */
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("Request validation failed")]
+    Validation(Vec<FieldError>), // 请求字段校验失败
+
+    #[error("Malformed request: {0}")]
+    BadRequest(String), // 请求格式错误（JSON、路径或查询参数无法解析）
+
+    #[error("{0}")]
+    InvalidStatus(String), // 无效的订单状态
+
+    #[error("Order not found")]
+    OrderNotFound, // 订单不存在
+
+    #[error("Invalid order token")]
+    InvalidToken, // 订单查询令牌不匹配
+
+    #[error("Invalid or missing courier token")]
+    Unauthorized, // 配送员令牌缺失或无效
+
+    #[error("Courier not found")]
+    CourierNotFound, // 配送员不存在
+
+    #[error("Payment not found")]
+    PaymentNotFound, // 支付记录不存在
+
+    #[error("Invalid payment signature")]
+    InvalidSignature, // 支付回调签名缺失或无效
+
+    #[error("Payments are not enabled")]
+    PaymentsDisabled, // 未配置支付服务商
+
+    #[error("{0}")]
+    InvalidOrderState(String), // 订单当前状态不允许该操作
+
+    #[error("{0}")]
+    StoreClosed(String), // 门店休息或已暂停接单
+
+    #[error("Conflict: {0}")]
+    Conflict(String), // 与已有数据冲突（如唯一约束）
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error), // 数据库操作失败
+
+    #[error("Database unavailable: {0}")]
+    Pool(#[from] r2d2::Error), // 无法从连接池获取连接
+
+    #[error("Internal error: {0}")]
+    Internal(String), // 其他内部错误
+}
+
+/**
+ * 错误响应模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub success: bool,    // 始终为false
+    pub code: String,     // 稳定的错误代码
+    pub message: String,  // 可读的错误描述
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>, // 字段错误列表（仅校验失败时存在）
+}
+
+impl ApiError {
+    /**
+     * 获取错误代码
+     * 错误代码属于API契约的一部分，不应随意修改
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::InvalidStatus(_) => "invalid_status",
+            ApiError::OrderNotFound => "order_not_found",
+            ApiError::InvalidToken => "invalid_token",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::CourierNotFound => "courier_not_found",
+            ApiError::PaymentNotFound => "payment_not_found",
+            ApiError::InvalidSignature => "invalid_signature",
+            ApiError::PaymentsDisabled => "payments_disabled",
+            ApiError::InvalidOrderState(_) => "invalid_order_state",
+            ApiError::StoreClosed(_) => "store_closed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Database(_) => "database_error",
+            ApiError::Pool(_) => "database_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) | ApiError::BadRequest(_) | ApiError::InvalidStatus(_) => StatusCode::BAD_REQUEST,
+            ApiError::OrderNotFound | ApiError::CourierNotFound | ApiError::PaymentNotFound
+                | ApiError::PaymentsDisabled => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized | ApiError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidToken => StatusCode::FORBIDDEN,
+            ApiError::InvalidOrderState(_) | ApiError::StoreClosed(_) | ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // 内部错误只记录日志，不向客户端暴露细节
+        let message = if status.is_server_error() {
+            log::error!("{}", self);
+            "Internal server error".to_string()
+        } else {
+            self.to_string()
+        };
+        let errors = match self {
+            ApiError::Validation(errors) => Some(errors.clone()),
+            _ => None,
+        };
+
+        HttpResponse::build(status).json(ErrorResponse {
+            success: false,
+            code: self.code().to_string(),
+            message,
+            errors,
+        })
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(err: JsonPayloadError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+impl From<QueryPayloadError> for ApiError {
+    fn from(err: QueryPayloadError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+impl From<PathError> for ApiError {
+    fn from(err: PathError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn test_validation_error_body() {
+        let err = ApiError::Validation(vec![FieldError::new("customer_name", "required")]);
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["code"], "validation_failed");
+        assert_eq!(json["errors"][0]["field"], "customer_name");
+    }
+
+    #[actix_web::test]
+    async fn test_internal_error_hides_details() {
+        let resp = ApiError::Internal("lock poisoned".to_string()).error_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], "internal_error");
+        assert_eq!(json["message"], "Internal server error");
+    }
+}
@@ -0,0 +1,246 @@
+/*!
+ * 订单预计时间模块
+ * 根据饮品制作时间、制作队列和配送距离估算订单的出餐和送达时间
+ * 订单状态变化时重新估算所有进行中的订单
+ */
+
+use chrono::{DateTime, Duration, Utc};
+use crate::delivery::haversine_km;
+use crate::error::ApiResult;
+use crate::db::OrderFilter;
+use crate::models::{Fulfilment, Location, Order, OrderEstimate, OrderItem, OrderStatus};
+use crate::repository::OrderRepository;
+
+// 默认估算参数
+const DEFAULT_PREP_SECS: i64 = 120;     // 每杯饮品的默认制作时间（秒）
+const DEFAULT_COURIER_SPEED_KMH: f64 = 15.0; // 默认配送速度（公里/小时）
+const DEFAULT_DELIVERY_MINS: i64 = 20;  // 未配置门店坐标时的默认配送时间（分钟）
+
+/**
+ * 预计时间估算器
+ * 假设饮品按下单顺序逐杯制作
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimator {
+    pub default_prep: Duration,             // 未单独配置的饮品每杯制作时间
+    pub prep_times: Vec<(String, Duration)>, // 按饮品名称前缀配置的每杯制作时间
+    pub store: Option<Location>,            // 门店坐标（用于计算配送距离）
+    pub courier_speed_kmh: f64,             // 配送速度（公里/小时）
+    pub default_travel: Duration,           // 未配置门店坐标时的配送时间
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Estimator {
+            default_prep: Duration::seconds(DEFAULT_PREP_SECS),
+            prep_times: Vec::new(),
+            store: None,
+            courier_speed_kmh: DEFAULT_COURIER_SPEED_KMH,
+            default_travel: Duration::minutes(DEFAULT_DELIVERY_MINS),
+        }
+    }
+}
+
+impl Estimator {
+    /**
+     * 解析并设置各饮品的制作时间
+     * 格式为以分号分隔的"饮品名称=秒数"，如"拿铁=150;浓缩咖啡=60"
+     * 订单商品名称以饮品名称开头即匹配，较长的名称优先
+     *
+     * @param spec - 制作时间配置
+     * @return Result<Estimator, String> - 解析结果
+     */
+    pub fn with_prep_times(mut self, spec: &str) -> Result<Self, String> {
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || format!("Invalid prep time: {}", entry);
+            let (name, secs) = entry.split_once('=').ok_or_else(invalid)?;
+            let secs: i64 = secs.trim().parse().ok().filter(|s| *s > 0).ok_or_else(invalid)?;
+            self.prep_times.push((name.trim().to_string(), Duration::seconds(secs)));
+        }
+        self.prep_times.sort_by_key(|(name, _)| std::cmp::Reverse(name.chars().count()));
+        Ok(self)
+    }
+
+    /**
+     * 计算订单商品的总制作时间
+     *
+     * @param items - 订单商品
+     * @return Duration - 制作时间
+     */
+    pub fn prep_time(&self, items: &[OrderItem]) -> Duration {
+        items.iter()
+            .map(|item| {
+                let per_cup = self.prep_times.iter()
+                    .find(|(name, _)| item.name.starts_with(name.as_str()))
+                    .map_or(self.default_prep, |(_, time)| *time);
+                per_cup * item.quantity.max(0)
+            })
+            .fold(Duration::zero(), |total, time| total + time)
+    }
+
+    /**
+     * 计算订单出餐后到送达的时间
+     * 自取和堂食订单为0；外送订单按门店到配送坐标的距离和配送速度计算
+     *
+     * @param order - 订单
+     * @return Duration - 配送时间
+     */
+    pub fn travel_time(&self, order: &Order) -> Duration {
+        if !matches!(order.fulfilment, Fulfilment::Delivery) {
+            return Duration::zero();
+        }
+        match (self.store, order.location()) {
+            (Some(store), Some(location)) => {
+                let hours = haversine_km(store, location) / self.courier_speed_kmh;
+                Duration::seconds((hours * 3600.0).round() as i64)
+            }
+            _ => self.default_travel,
+        }
+    }
+
+    /**
+     * 估算进行中订单的出餐和送达时间
+     * 制作中的订单排在队列最前，其次是已发送给设备的待处理订单（按发送顺序）
+     * 配送中的订单已出餐；尚未发送的预约订单按预约时间倒推
+     *
+     * @param orders - 进行中的订单（待处理、制作中和配送中）
+     * @param now - 当前时间
+     * @return Vec<OrderEstimate> - 各订单的预计时间
+     */
+    pub fn plan(&self, orders: &[Order], now: DateTime<Utc>) -> Vec<OrderEstimate> {
+        let mut queue: Vec<&Order> = orders.iter()
+            .filter(|o| o.status == OrderStatus::Preparing
+                || (o.status == OrderStatus::Pending && o.dispatched_at.is_some()))
+            .collect();
+        queue.sort_by_key(|o| (o.status != OrderStatus::Preparing, o.dispatched_at, o.created_at, o.id));
+
+        let estimate = |order: &Order, ready_at: DateTime<Utc>| OrderEstimate {
+            order_number: order.order_number.clone(),
+            ready_at,
+            arrival_at: ready_at + self.travel_time(order),
+        };
+
+        let mut estimates = Vec::with_capacity(orders.len());
+        let mut cursor = now;
+        for order in queue {
+            let ready_at = match order.status {
+                // 已超时的订单视为即将出餐
+                OrderStatus::Preparing => order.estimated_ready_at
+                    .unwrap_or(cursor + self.prep_time(&order.items))
+                    .max(now),
+                // 预约订单不早于预约时间倒推的出餐时间
+                _ => {
+                    let queued = cursor + self.prep_time(&order.items);
+                    match order.requested_time {
+                        Some(time) => queued.max(time - self.travel_time(order)),
+                        None => queued,
+                    }
+                }
+            };
+            cursor = cursor.max(ready_at);
+            estimates.push(estimate(order, ready_at));
+        }
+
+        for order in orders {
+            match order.status {
+                // 提前取走的订单以取走时间为出餐时间
+                OrderStatus::Delivering => {
+                    let ready_at = order.estimated_ready_at.map_or(now, |time| time.min(now));
+                    estimates.push(estimate(order, ready_at));
+                }
+                OrderStatus::Pending if order.dispatched_at.is_none() => {
+                    if let Some(time) = order.requested_time {
+                        estimates.push(OrderEstimate {
+                            order_number: order.order_number.clone(),
+                            ready_at: time - self.travel_time(order),
+                            arrival_at: time,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        estimates
+    }
+}
+
+/**
+ * 重新估算所有进行中订单的预计时间并保存
+ * 只保存有变化的估算结果
+ *
+ * @param repo - 订单仓储
+ * @param estimator - 预计时间估算器
+ * @param now - 当前时间
+ * @return ApiResult<()> - 操作结果
+ */
+pub fn refresh_estimates(repo: &dyn OrderRepository, estimator: &Estimator, now: DateTime<Utc>) -> ApiResult<()> {
+    let filter = OrderFilter {
+        statuses: vec![OrderStatus::Pending, OrderStatus::Preparing, OrderStatus::Delivering],
+        limit: u32::MAX,
+        ..Default::default()
+    };
+    let orders = repo.get_orders(&filter)?;
+    let changed: Vec<OrderEstimate> = estimator.plan(&orders, now)
+        .into_iter()
+        .filter(|estimate| orders.iter().any(|o| o.order_number == estimate.order_number
+            && (o.estimated_ready_at != Some(estimate.ready_at) || o.estimated_arrival_at != Some(estimate.arrival_at))))
+        .collect();
+    if !changed.is_empty() {
+        repo.update_order_estimates(&changed)?;
+    }
+    Ok(())
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Money;
+
+    fn order(order_number: &str, status: OrderStatus, items: &[(&str, i32)], created_secs: i64) -> Order {
+        let sample = Order::sample(order_number);
+        let created_at = sample.created_at + Duration::seconds(created_secs);
+        Order {
+            id: created_secs,
+            created_at,
+            status,
+            items: items.iter()
+                .map(|(name, quantity)| OrderItem { name: name.to_string(), quantity: *quantity, price: Money::ZERO })
+                .collect(),
+            dispatched_at: Some(created_at),
+            ..sample
+        }
+    }
+
+    #[test]
+    fn test_prep_time_by_drink() {
+        let estimator = Estimator::default().with_prep_times("咖啡=90; 冷萃咖啡=30").unwrap();
+        let items = order("A", OrderStatus::Pending, &[("冷萃咖啡 (大杯)", 2), ("拿铁 (中杯) - 燕麦奶", 1)], 0).items;
+        assert_eq!(estimator.prep_time(&items), Duration::seconds(30 * 2 + 120));
+        assert!(Estimator::default().with_prep_times("拿铁=0").is_err());
+    }
+
+    #[test]
+    fn test_plan_queue() {
+        let now: DateTime<Utc> = "2024-05-01T02:10:00Z".parse().unwrap();
+        let estimator = Estimator { store: Some(Location { lat: 39.98, lng: 116.31 }), ..Default::default() };
+
+        // 制作中的订单已超时，视为即将出餐；待处理订单依次排队
+        let mut preparing = order("A", OrderStatus::Preparing, &[("拿铁", 1)], 0);
+        preparing.estimated_ready_at = Some(now - Duration::minutes(1));
+        let mut delivery = order("B", OrderStatus::Pending, &[("拿铁", 2)], 60);
+        delivery.fulfilment = Fulfilment::Delivery;
+        (delivery.latitude, delivery.longitude) = (Some(39.98), Some(116.35)); // 约3.4公里
+        let pickup = order("C", OrderStatus::Pending, &[("美式", 1)], 120);
+
+        let estimates = estimator.plan(&[pickup, delivery, preparing], now);
+        let find = |number: &str| estimates.iter().find(|e| e.order_number == number).unwrap();
+        assert_eq!(find("A").ready_at, now);
+        assert_eq!(find("B").ready_at, now + Duration::minutes(4));
+        assert_eq!((find("B").arrival_at - find("B").ready_at).num_minutes(), 13);
+        assert_eq!(find("C").ready_at, now + Duration::minutes(6));
+        assert_eq!(find("C").arrival_at, find("C").ready_at);
+    }
+}
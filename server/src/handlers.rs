@@ -1,163 +1,2135 @@
-/**
+/*!
  * HTTP请求处理器模块
  * 处理所有与订单相关的HTTP请求
  * 包括创建订单、查询订单、更新订单状态等功能
  */
 
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SubsecRound, TimeZone, Utc};
 use uuid::Uuid;
-use crate::db::{self, AppState};
-use crate::models::{CreateOrderRequest, CreateOrderResponse, OrderList, OrderStatus, UpdateOrderStatusRequest, UpdateOrderStatusResponse, OrderQuery, Order};
+use crate::address::{self, Address};
+use crate::config;
+use crate::db::OrderFilter;
+use crate::error::{ApiError, ApiResult};
+use crate::eta;
+use crate::menu;
+use crate::models::{generate_lookup_token, generate_short_code, items_total, AmendOrderRequest, AssignCourierRequest, BatchQuery, CancelOrderRequest, Courier, CourierLocationResponse, CourierOrderList, CreateCourierRequest, CreateCourierResponse, CreateOrderRequest, CreateOrderResponse, CreatePaymentRequest, DeliveryCheckResponse, DeliveryQuoteResponse, Fulfilment, GeocodeQuery, GeocodeResponse, Location, MockPaymentRequest, Money, OrderAuditEntry, OrderFeatureCollection, OrderItem, OrderItemRequest, OrderList, OrderResponse, OrderLookupQuery, OrderStatus, OrderStatusView, Payment, PaymentResponse, PaymentStatus, PauseOrderingRequest, RefundOrderRequest, RefundResponse, StorePause, StoreStatus, UpdateOrderStatusRequest, UpdateOrderStatusResponse, OrderQuery, Order};
+use crate::repository::AppState;
+use crate::payment::{self, PaymentEvent};
+use crate::routing::{self, BatchOptions};
+use crate::schedule;
+use crate::serial_comm::DeviceEvent;
+use crate::validation::{FieldError, Validate, INVALID_FORMAT, MISMATCH, OUTSIDE_DELIVERY_ZONE, OUT_OF_RANGE, REQUIRED, UNKNOWN_ITEM};
 use std::sync::mpsc::Sender;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::str::FromStr;
 
+// 订单列表分页常量
+const DEFAULT_PAGE_SIZE: u32 = 20; // 默认每页数量
+const MAX_PAGE_SIZE: u32 = 100;    // 每页最大数量
+const MAX_EXPORT_ORDERS: u32 = 10000; // 地图导出的最大订单数
+
+const MAX_SHORT_CODE_ATTEMPTS: u32 = 5; // 短码冲突时的最大生成次数
+
+// 审计记录的操作类型
+const AUDIT_ITEMS_AMENDED: &str = "items_amended"; // 修改订单商品
+const AUDIT_COURIER_ASSIGNED: &str = "courier_assigned"; // 指派配送员
+const AUDIT_COURIER_PICKED_UP: &str = "courier_picked_up"; // 配送员取餐
+const AUDIT_COURIER_DELIVERED: &str = "courier_delivered"; // 配送员送达
+const AUDIT_PAYMENT_SUCCEEDED: &str = "payment_succeeded"; // 支付成功
+const AUDIT_PAYMENT_FAILED: &str = "payment_failed"; // 支付失败
+const AUDIT_PAYMENT_AMOUNT_MISMATCH: &str = "payment_amount_mismatch"; // 支付成功但金额与订单不符
+
+/// 设备事件发送器（串口未连接时为None）
+pub type OrderSender = Option<Mutex<Sender<DeviceEvent>>>;
+
+/**
+ * 注册API路由
+ * 同时将请求解析错误统一转换为ApiError响应
+ * 
+ * @param cfg - 服务配置
+ */
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(|err, _| ApiError::from(err).into()))
+        .app_data(web::QueryConfig::default().error_handler(|err, _| ApiError::from(err).into()))
+        .app_data(web::PathConfig::default().error_handler(|err, _| ApiError::from(err).into()))
+        .service(
+            web::scope("/api")
+                .route("/admin/orders.geojson", web::get().to(export_orders_geojson))
+                .route("/orders/create", web::post().to(create_order))
+                .route("/orders", web::get().to(get_orders))
+                .route("/orders/{reference}", web::get().to(get_order))
+                .route("/orders/{reference}/status", web::put().to(update_order_status))
+                .route("/orders/{reference}/cancel", web::post().to(cancel_order))
+                .route("/orders/{reference}/items", web::put().to(amend_order))
+                .route("/orders/{reference}/audit", web::get().to(get_order_audit))
+                .route("/orders/{reference}/courier", web::put().to(assign_courier))
+                .route("/orders/{reference}/payment", web::post().to(create_payment))
+                .route("/orders/{reference}/refunds", web::post().to(refund_order))
+                .route("/orders/{reference}/refunds", web::get().to(get_order_refunds))
+                .route("/payments/webhook", web::post().to(payment_webhook))
+                .route("/payments/mock/{reference}", web::post().to(mock_payment))
+                .route("/couriers", web::post().to(create_courier))
+                .route("/couriers", web::get().to(get_couriers))
+                .route("/courier/orders", web::get().to(get_courier_orders))
+                .route("/courier/location", web::post().to(update_courier_location))
+                .route("/courier/orders/{reference}/pickup", web::post().to(courier_pickup))
+                .route("/courier/orders/{reference}/delivered", web::post().to(courier_delivered))
+                .route("/addresses/geocode", web::get().to(geocode_address))
+                .route("/delivery/check", web::get().to(check_delivery))
+                .route("/delivery/quote", web::get().to(quote_delivery))
+                .route("/delivery/batches", web::get().to(get_delivery_batches))
+                .route("/store/status", web::get().to(get_store_status))
+                .route("/store/pause", web::put().to(set_store_pause)),
+        );
+}
+
+/**
+ * 通过串口将事件发送给设备
+ * 串口未连接或发送失败时只记录日志，不影响请求处理
+ * 
+ * @param order_sender - 设备事件发送器
+ * @param event - 设备事件
+ */
+pub fn notify_device(order_sender: &OrderSender, event: DeviceEvent) {
+    if let Some(sender) = order_sender.as_ref().and_then(|a| a.lock().ok())
+        && let Err(e) = sender.send(event) {
+        log::error!("Failed to send event through serial port: {}", e);
+    }
+}
+
 /**
  * 创建新订单的处理器
+ * 请求数据未通过校验时返回400及字段错误列表
+ * 订单短码随机生成，与已有订单冲突时重新生成
+ * 启用支付时订单处于待支付状态，支付成功后才发送给设备
+ * 预约订单先暂存，到达提前量后由调度任务发送给设备
+ * 暂停接单时拒绝所有订单，休息时只接受预约到营业时间内的订单
  * 
  * @param order_req - 订单创建请求
- * @param app_state - 应用状态（包含数据库连接）
+ * @param app_state - 应用状态（包含订单仓储）
  * @param order_sender - 订单发送器（用于串口通信）
- * @return Result<HttpResponse> - 包含订单创建结果的HTTP响应
+ * @return ApiResult<HttpResponse> - 包含订单创建结果的HTTP响应
  */
 pub async fn create_order(
     order_req: web::Json<CreateOrderRequest>,
     app_state: web::Data<AppState>,
-    order_sender: web::Data<Option<Mutex<Sender<Order>>>>,
-) -> Result<HttpResponse> {
+    order_sender: web::Data<OrderSender>,
+) -> ApiResult<HttpResponse> {
     let order_req = order_req.into_inner();
+
+    // 校验请求数据，失败时返回字段级错误
+    let now = Utc::now().trunc_subsecs(0);
+    let mut errors = order_req.validate().err().unwrap_or_default();
+    let requested_time = order_req.requested_time.as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .and_then(|v| {
+            let parsed = parse_query_time(v, false).map(|t| t.trunc_subsecs(0));
+            let error = match parsed {
+                Some(time) => schedule::check_requested_time(&app_state.hours, time, now),
+                None => Some(INVALID_FORMAT),
+            };
+            if let Some(code) = error {
+                errors.push(FieldError::new("requested_time", code));
+            }
+            parsed
+        });
+
+    // 外送地址规范化后用作坐标缓存的键，客户端未提供坐标时从缓存中查询
+    let address = match order_req.fulfilment {
+        Fulfilment::Delivery => order_req.address.as_ref()
+            .map(Address::normalized)
+            .or_else(|| order_req.delivery_address.as_deref().map(Address::parse))
+            .filter(|address| !address.is_empty()),
+        _ => None,
+    };
+    let location = match (&order_req.fulfilment, order_req.location, address.as_ref().map(Address::cache_key)) {
+        (Fulfilment::Delivery, None, Some(key)) => app_state.run(move |repo| repo.lookup_geocode(&key)).await?,
+        (Fulfilment::Delivery, location, _) => location,
+        _ => None,
+    };
+    if matches!(order_req.fulfilment, Fulfilment::Delivery) && location.is_none() {
+        errors.push(FieldError::new("location", REQUIRED));
+    }
+
+    // 外送费由服务器按配送距离计算，客户确认的金额必须与报价一致
+    let delivery_fee = match (&order_req.fulfilment, location) {
+        (Fulfilment::Delivery, Some(location)) if location.validate().is_ok() => {
+            let fee = quote_delivery_fee(&app_state, location);
+            if fee.is_none() {
+                errors.push(FieldError::new("location", OUTSIDE_DELIVERY_ZONE));
+            }
+            fee
+        }
+        (Fulfilment::Delivery, _) => None,
+        _ => Some(Money::ZERO),
+    };
+    if let Some(fee) = delivery_fee
+        && order_req.delivery_fee.unwrap_or_default() != fee {
+        errors.push(FieldError::new("delivery_fee", MISMATCH));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+    if requested_time.is_none() && !app_state.hours.is_open_at(now) {
+        return Err(ApiError::StoreClosed("Store is closed, please schedule the order for opening hours".to_string()));
+    }
+
     let order_number = Uuid::new_v4().to_string();
+    // 启用支付时订单先等待支付，支付成功后才发送给设备
+    let awaiting_payment = app_state.payments.is_some();
+    let due = !awaiting_payment && schedule::is_due(requested_time, now);
 
     // 转换 CreateOrderRequest 到 Order
-    let order = Order {
+    let mut order = Order {
         id: 0, // 数据库会自动生成
         order_number,
+        short_code: String::new(), // 保存时生成
         customer_name: order_req.customer_name,
         phone_number: order_req.phone_number,
-        delivery_address: order_req.delivery_address,
-        latitude: order_req.location.lat,
-        longitude: order_req.location.lng,
+        fulfilment: order_req.fulfilment,
+        delivery_address: address.as_ref().map(Address::to_string),
+        address,
+        latitude: location.map(|l| l.lat),
+        longitude: location.map(|l| l.lng),
         notes: order_req.notes,
-        created_at: chrono::Local::now().naive_local().to_string(),
+        created_at: now,
+        delivery_fee: delivery_fee.unwrap_or_default(),
         total_amount: order_req.total_amount,
-        status: OrderStatus::Pending,
+        status: if awaiting_payment { OrderStatus::AwaitingPayment } else { OrderStatus::Pending },
         items: order_req.items.into_iter().map(|item| item.into()).collect(),
+        lookup_token: Some(generate_lookup_token()),
+        cancel_reason: None,
+        cancelled_at: None,
+        requested_time,
+        dispatched_at: due.then_some(now),
+        estimated_ready_at: None,
+        estimated_arrival_at: None,
+        courier_id: None,
+        courier_location: None,
+        refunded_amount: Money::ZERO,
+        refund_status: None,
     };
 
-    if let Ok(mut conn) = app_state.db.lock() {
-        match db::create_order(&mut *conn, &order) {
-            Ok(created_order) => {
-                if let Some(sender) = order_sender.as_ref().as_ref().and_then(|a| a.lock().ok()) {
-                    if let Err(e) = sender.send(created_order.clone()) {
-                        log::error!("Failed to send order through serial port: {}", e);
-                    }
+    let estimator = Arc::clone(&app_state.estimator);
+    let created_order = app_state.run(move |repo| {
+        if let Some(pause) = repo.get_store_pause()? {
+            return Err(ApiError::StoreClosed(match pause.reason {
+                Some(reason) => format!("Ordering is paused: {}", reason),
+                None => "Ordering is paused".to_string(),
+            }));
+        }
+        let mut attempt = 1;
+        let created = loop {
+            order.short_code = generate_short_code();
+            match repo.create_order(&order) {
+                Err(ApiError::Conflict(_)) if attempt < MAX_SHORT_CODE_ATTEMPTS => {
+                    log::warn!("Short code {} already in use, regenerating", order.short_code);
+                    attempt += 1;
                 }
-                Ok(HttpResponse::Ok().json(CreateOrderResponse {
-                    success: true,
-                    order_number: created_order.order_number,
-                }))
-            }
-            Err(e) => {
-                log::error!("Failed to create order: {}", e);
-                Ok(HttpResponse::InternalServerError().json(CreateOrderResponse {
-                    success: false,
-                    order_number: String::new(),
-                }))
+                result => break result?,
             }
-        }
+        };
+        // 新订单加入制作队列，重新估算后返回带预计时间的订单
+        eta::refresh_estimates(repo, &estimator, now)?;
+        repo.get_order_by_reference(&created.order_number)?.ok_or(ApiError::OrderNotFound)
+    }).await?;
+
+    if due {
+        notify_device(&order_sender, DeviceEvent::NewOrder(Box::new(created_order.clone())));
+    } else if awaiting_payment {
+        log::info!("Order {} awaiting payment", created_order.order_number);
+    } else if let Some(time) = created_order.requested_time {
+        log::info!("Holding scheduled order {} due at {}", created_order.order_number, time);
+    }
+
+    Ok(HttpResponse::Ok().json(CreateOrderResponse {
+        success: true,
+        order_number: created_order.order_number.clone(),
+        lookup_token: created_order.lookup_token.clone().unwrap_or_default(),
+        order: created_order,
+    }))
+}
+
+/**
+ * 解析查询参数中的时间
+ * 支持日期、日期时间和RFC 3339格式，不带时区的时间按门店时区处理
+ * 
+ * @param value - 时间字符串
+ * @param end_of_range - 为true且只给出日期时，返回次日零点（用于包含当天的终点）
+ * @return Option<DateTime<Utc>> - 解析结果
+ */
+fn parse_query_time(value: &str, end_of_range: bool) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let local = if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end_of_range { date.succ_opt()? } else { date };
+        date.and_hms_opt(0, 0, 0)?
     } else {
-        Ok(HttpResponse::InternalServerError().json(CreateOrderResponse {
-            success: false,
-            order_number: String::new(),
-        }))
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+            .ok()?
+    };
+    config::store_timezone().from_local_datetime(&local).single().map(|t| t.with_timezone(&Utc))
+}
+
+/**
+ * 将订单查询参数转换为数据库过滤条件
+ * 
+ * @param query - 查询参数
+ * @return ApiResult<OrderFilter> - 过滤条件，参数无效时返回错误
+ */
+fn parse_order_filter(query: &OrderQuery) -> ApiResult<OrderFilter> {
+    let statuses = query.status.as_deref()
+        .map(|s| s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty() && *s != "all")
+            .map(OrderStatus::from_str)
+            .collect::<Result<Vec<_>, _>>())
+        .transpose()
+        .map_err(ApiError::InvalidStatus)?
+        .unwrap_or_default();
+
+    let mut errors = Vec::new();
+    let from = query.from.as_deref().and_then(|v| {
+        let parsed = parse_query_time(v, false);
+        if parsed.is_none() {
+            errors.push(FieldError::new("from", INVALID_FORMAT));
+        }
+        parsed
+    });
+    let to = query.to.as_deref().and_then(|v| {
+        let parsed = parse_query_time(v, true);
+        if parsed.is_none() {
+            errors.push(FieldError::new("to", INVALID_FORMAT));
+        }
+        parsed
+    });
+    let page = query.page.unwrap_or(1);
+    if page == 0 {
+        errors.push(FieldError::new("page", OUT_OF_RANGE));
     }
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        errors.push(FieldError::new("page_size", OUT_OF_RANGE));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    Ok(OrderFilter {
+        statuses,
+        from,
+        to,
+        search: query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()).map(str::to_string),
+        courier_id: query.courier_id,
+        limit: page_size,
+        offset: (page - 1).saturating_mul(page_size),
+    })
 }
 
 /**
  * 获取订单列表的处理器
- * 支持按状态筛选订单
+ * 支持按状态（可多选）、创建时间范围和关键字筛选，结果分页返回
  * 
- * @param app_state - 应用状态（包含数据库连接）
- * @param query - 查询参数（可选的状态过滤）
- * @return Result<HttpResponse> - 包含订单列表的HTTP响应
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param query - 查询参数（过滤和分页条件）
+ * @return ApiResult<HttpResponse> - 包含订单列表和总数的HTTP响应
  */
 pub async fn get_orders(
     app_state: web::Data<AppState>,
     query: web::Query<OrderQuery>,
-) -> Result<HttpResponse> {
-    if let Ok(db) = app_state.db.lock() {
-        let status_filter = query.status.as_ref().and_then(|s| OrderStatus::from_str(s).ok());
-        let status_str = status_filter.as_ref().map(|s| s.to_string());
-        
-        match db::get_orders(&db, status_str.as_deref()) {
-            Ok(orders) => Ok(HttpResponse::Ok().json(OrderList { orders })),
-            Err(e) => {
-                log::error!("Failed to get orders: {}", e);
-                Ok(HttpResponse::InternalServerError().finish())
-            }
-        }
-    } else {
-        Ok(HttpResponse::InternalServerError().finish())
-    }
+) -> ApiResult<HttpResponse> {
+    let filter = parse_order_filter(&query)?;
+    let page = filter.offset / filter.limit + 1;
+    let page_size = filter.limit;
+
+    let (orders, total) = app_state.run(move |repo| {
+        Ok((repo.get_orders(&filter)?, repo.count_orders(&filter)?))
+    }).await?;
+    Ok(HttpResponse::Ok().json(OrderList {
+        orders,
+        total,
+        page,
+        page_size,
+    }))
+}
+
+/**
+ * 导出订单地图的处理器
+ * 以GeoJSON FeatureCollection返回有配送坐标的订单，按创建时间倒序
+ * 支持与订单列表相同的状态、创建时间范围和关键字筛选，忽略分页参数
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param query - 查询参数（过滤条件）
+ * @return ApiResult<HttpResponse> - GeoJSON格式的HTTP响应
+ */
+pub async fn export_orders_geojson(
+    app_state: web::Data<AppState>,
+    query: web::Query<OrderQuery>,
+) -> ApiResult<HttpResponse> {
+    let filter = OrderFilter {
+        limit: MAX_EXPORT_ORDERS,
+        offset: 0,
+        ..parse_order_filter(&query)?
+    };
+    let orders = app_state.run(move |repo| repo.get_orders(&filter)).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(OrderFeatureCollection::from_orders(&orders)))
 }
 
 /**
  * 获取单个订单详情的处理器
+ * 提供正确的查询令牌时返回完整订单，未提供令牌时只返回订单状态
  * 
- * @param app_state - 应用状态（包含数据库连接）
- * @param order_number - 订单编号
- * @return Result<HttpResponse> - 包含订单详情的HTTP响应
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @param query - 查询参数（订单查询令牌）
+ * @return ApiResult<HttpResponse> - 包含订单详情或订单状态的HTTP响应
  */
 pub async fn get_order(
     app_state: web::Data<AppState>,
-    order_number: web::Path<String>,
-) -> Result<HttpResponse> {
-    if let Ok(db) = app_state.db.lock() {
-        match db::get_order_by_number(&db, &order_number) {
-            Ok(Some(order)) => Ok(HttpResponse::Ok().json(order)),
-            Ok(None) => Ok(HttpResponse::NotFound().finish()),
-            Err(e) => {
-                log::error!("Failed to get order: {}", e);
-                Ok(HttpResponse::InternalServerError().finish())
-            }
-        }
-    } else {
-        Ok(HttpResponse::InternalServerError().finish())
+    reference: web::Path<String>,
+    query: web::Query<OrderLookupQuery>,
+) -> ApiResult<HttpResponse> {
+    let reference = reference.into_inner();
+    let order = app_state.run(move |repo| repo.get_order_by_reference(&reference)).await?
+        .ok_or(ApiError::OrderNotFound)?;
+    match query.token.as_deref().map(str::trim).filter(|token| !token.is_empty()) {
+        Some(token) if order.verify_lookup_token(token) => Ok(HttpResponse::Ok().json(order)),
+        Some(_) => Err(ApiError::InvalidToken),
+        None => Ok(HttpResponse::Ok().json(OrderStatusView::from(&order))),
     }
 }
 
 /**
  * 更新订单状态的处理器
+ * 兼容期内，找不到订单编号或短码的纯数字参数按旧版内部订单ID处理，
+ * 并在响应中添加Deprecation头
+ * 状态按OrderStatus::allowed_previous流转，其他变化返回409；
+ * 待支付、待处理或制作中的订单被取消时自动退还已支付的金额
  * 
- * @param app_state - 应用状态（包含数据库连接）
- * @param order_id - 订单ID
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
  * @param status_update - 新的订单状态
- * @return Result<HttpResponse> - 包含更新结果的HTTP响应
+ * @return ApiResult<HttpResponse> - 包含更新结果的HTTP响应
  */
 pub async fn update_order_status(
     app_state: web::Data<AppState>,
-    order_id: web::Path<i64>,
+    reference: web::Path<String>,
     status_update: web::Json<UpdateOrderStatusRequest>,
-) -> Result<HttpResponse> {
-    if let Ok(db) = app_state.db.lock() {
-        match db::update_order_status(&db, order_id.into_inner(), &status_update.status.to_string()) {
-            Ok(true) => Ok(HttpResponse::Ok().json(UpdateOrderStatusResponse {
-                success: true,
-                message: None,
-            })),
-            Ok(false) => Ok(HttpResponse::NotFound().json(UpdateOrderStatusResponse {
-                success: false,
-                message: Some("Order not found".to_string()),
-            })),
-            Err(e) => {
-                log::error!("Failed to update order status: {}", e);
-                Ok(HttpResponse::InternalServerError().json(UpdateOrderStatusResponse {
-                    success: false,
-                    message: Some(format!("Failed to update order status: {}", e)),
-                }))
+) -> ApiResult<HttpResponse> {
+    let reference = reference.into_inner();
+    let new_status = status_update.into_inner().status;
+    let legacy_id = reference.parse::<i64>().ok().filter(|_| config::legacy_order_ids_enabled());
+
+    // 返回值：(已更新订单的编号和更新前的状态, 是否按旧版订单ID更新)
+    let estimator = Arc::clone(&app_state.estimator);
+    let provider = app_state.payments.clone();
+    let (updated, by_legacy_id) = app_state.run(move |repo| {
+        let result = match repo.update_order_status_by_reference(&reference, &new_status)? {
+            Some(updated) => (Some(updated), false),
+            None => match legacy_id {
+                Some(order_id) => (repo.update_order_status(order_id, &new_status)?, true),
+                None => (None, false),
+            },
+        };
+        if let Some((order_number, previous)) = &result.0 {
+            let now = Utc::now().trunc_subsecs(0);
+            eta::refresh_estimates(repo, &estimator, now)?;
+            match new_status {
+                OrderStatus::Cancelled if previous.refunds_on_cancel() => {
+                    payment::refund_cancelled_order(repo, provider.as_deref(), order_number, now)
+                }
+                OrderStatus::Completed => address::cache_delivered_location(repo, order_number, now)?,
+                _ => {}
             }
         }
-    } else {
-        Ok(HttpResponse::InternalServerError().finish())
+        Ok(result)
+    }).await?;
+    if updated.is_none() {
+        return Err(ApiError::OrderNotFound);
+    }
+
+    let mut response = HttpResponse::Ok();
+    if let Some(order_id) = legacy_id.filter(|_| by_legacy_id) {
+        log::warn!("Order status updated by deprecated internal id {}", order_id);
+        response.insert_header(("Deprecation", "true"));
+    }
+    Ok(response.json(UpdateOrderStatusResponse {
+        success: true,
+        message: None,
+    }))
+}
+
+/**
+ * 客户自助取消订单的处理器
+ * 需提供下单时返回的查询令牌；待处理的订单或处于取消宽限期内的订单可以取消
+ * 取消成功后通知设备停止制作，已支付的订单自动退款
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @param cancel_req - 取消请求（令牌和取消原因）
+ * @param order_sender - 设备事件发送器
+ * @return ApiResult<HttpResponse> - 包含取消后订单的HTTP响应
+ */
+pub async fn cancel_order(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    cancel_req: web::Json<CancelOrderRequest>,
+    order_sender: web::Data<OrderSender>,
+) -> ApiResult<HttpResponse> {
+    let cancel_req = cancel_req.into_inner();
+    cancel_req.validate().map_err(ApiError::Validation)?;
+
+    let reference = reference.into_inner();
+    let reason = cancel_req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string);
+    let now = Utc::now().trunc_subsecs(0);
+    let estimator = Arc::clone(&app_state.estimator);
+    let provider = app_state.payments.clone();
+    let order = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        if !order.verify_lookup_token(cancel_req.token.trim()) {
+            return Err(ApiError::InvalidToken);
+        }
+        if !order.can_customer_cancel(now, config::cancel_grace_period()) {
+            return Err(ApiError::InvalidOrderState(format!("Order is {} and can no longer be cancelled", order.status)));
+        }
+        // 状态在读取后被设备更新时取消失败，由客户重试
+        if !repo.cancel_order(&order.order_number, &order.status, reason.as_deref(), now)? {
+            return Err(ApiError::InvalidOrderState("Order status changed, please try again".to_string()));
+        }
+        // 取消的订单离开制作队列，后续订单提前
+        eta::refresh_estimates(repo, &estimator, now)?;
+        payment::refund_cancelled_order(repo, provider.as_deref(), &order.order_number, now);
+        repo.get_order_by_reference(&order.order_number)?.ok_or(ApiError::OrderNotFound)
+    }).await?;
+
+    log::info!("Order {} cancelled by customer", order.order_number);
+    // 尚未发送给设备的预约订单无需通知设备
+    if order.dispatched_at.is_some() {
+        notify_device(&order_sender, DeviceEvent::OrderCancelled {
+            order_number: order.order_number.clone(),
+            reason: order.cancel_reason.clone(),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(OrderResponse {
+        success: true,
+        order,
+    }))
+}
+
+/**
+ * 修改订单商品的处理器
+ * 仅待处理（尚未开始制作）的订单可以修改；单价由服务器端确定（订单中已有的商品沿用原单价，
+ * 新增商品按菜单价格计算，不在菜单中则拒绝），忽略客户端提交的价格；已支付的订单不能改变总金额；修改前后的内容写入审计记录，并将修改后的订单重新发送给设备
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @param amend_req - 新的商品列表和修改原因
+ * @param order_sender - 设备事件发送器
+ * @return ApiResult<HttpResponse> - 包含修改后订单的HTTP响应
+ */
+pub async fn amend_order(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    amend_req: web::Json<AmendOrderRequest>,
+    order_sender: web::Data<OrderSender>,
+) -> ApiResult<HttpResponse> {
+    let amend_req = amend_req.into_inner();
+    amend_req.validate().map_err(ApiError::Validation)?;
+
+    let reference = reference.into_inner();
+    let reason = amend_req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string);
+    let requested = amend_req.items;
+    let now = Utc::now().trunc_subsecs(0);
+    let estimator = Arc::clone(&app_state.estimator);
+    let order = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        if order.status != OrderStatus::Pending {
+            return Err(ApiError::InvalidOrderState(format!("Order is {} and can no longer be amended", order.status)));
+        }
+        let items = reprice_items(requested, &order.items)?;
+        // 外送费不随商品变化
+        let total_amount = items_total(&items) + order.delivery_fee;
+        // 改变总金额需要补收或退款，已支付的订单不允许
+        if total_amount != order.total_amount
+            && repo.get_order_payments(&order.order_number)?.iter().any(|p| p.status == PaymentStatus::Succeeded) {
+            return Err(ApiError::InvalidOrderState("Order has been paid and its total can no longer change".to_string()));
+        }
+        let entry = OrderAuditEntry {
+            action: AUDIT_ITEMS_AMENDED.to_string(),
+            detail: serde_json::json!({
+                "before": { "items": order.items, "total_amount": order.total_amount },
+                "after": { "items": items, "total_amount": total_amount },
+                "reason": reason,
+            }),
+            created_at: now,
+        };
+        let amended = repo.amend_order_items(&order.order_number, &items, total_amount, &entry)?
+            .ok_or_else(|| ApiError::InvalidOrderState("Order status changed, please try again".to_string()))?;
+        // 商品变化影响制作时间
+        eta::refresh_estimates(repo, &estimator, now)?;
+        repo.get_order_by_reference(&amended.order_number)?.ok_or(ApiError::OrderNotFound)
+    }).await?;
+
+    log::info!("Order {} items amended, new total {}", order.order_number, order.total_amount);
+    // 尚未发送给设备的预约订单在到期时会发送修改后的内容
+    if order.dispatched_at.is_some() {
+        notify_device(&order_sender, DeviceEvent::OrderAmended(Box::new(order.clone())));
+    }
+
+    Ok(HttpResponse::Ok().json(OrderResponse {
+        success: true,
+        order,
+    }))
+}
+
+/**
+ * 按服务器端价格重新计算修改后的商品单价
+ * 订单中已有的商品沿用下单时的单价，新增商品按菜单价格计算
+ *
+ * @param requested - 客户端提交的商品列表（其中的价格被忽略）
+ * @param existing - 订单当前的商品列表
+ * @return ApiResult<Vec<OrderItem>> - 重新定价后的商品列表，存在未知商品时返回校验错误
+ */
+fn reprice_items(requested: Vec<OrderItemRequest>, existing: &[OrderItem]) -> ApiResult<Vec<OrderItem>> {
+    let mut errors = Vec::new();
+    let items: Vec<OrderItem> = requested.into_iter().enumerate().filter_map(|(i, item)| {
+        let price = existing.iter().find(|e| e.name == item.name).map(|e| e.price)
+            .or_else(|| menu::unit_price(&item.name));
+        match price {
+            Some(price) => Some(OrderItem { name: item.name, quantity: item.quantity, price }),
+            None => {
+                errors.push(FieldError::new(format!("items[{}].name", i), UNKNOWN_ITEM));
+                None
+            }
+        }
+    }).collect();
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+    Ok(items)
+}
+
+/**
+ * 获取订单审计记录的处理器
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @return ApiResult<HttpResponse> - 包含审计记录列表的HTTP响应
+ */
+pub async fn get_order_audit(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let reference = reference.into_inner();
+    let entries = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        repo.get_order_audit(&order.order_number)
+    }).await?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/**
+ * 发起订单支付的处理器
+ * 需提供下单时返回的查询令牌；仅待支付的订单可以发起支付，支付失败后可以重新发起
+ * 已有等待结果的支付时返回该支付记录，避免重复点击产生多笔支付
+ * 
+ * @param app_state - 应用状态（包含订单仓储和支付服务商）
+ * @param reference - 订单编号或短码
+ * @param payment_req - 发起支付请求（查询令牌）
+ * @return ApiResult<HttpResponse> - 包含支付记录和支付页面地址的HTTP响应
+ */
+pub async fn create_payment(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    payment_req: web::Json<CreatePaymentRequest>,
+) -> ApiResult<HttpResponse> {
+    let provider = app_state.payments.clone().ok_or(ApiError::PaymentsDisabled)?;
+    let reference = reference.into_inner();
+    let token = payment_req.into_inner().token;
+    let now = Utc::now().trunc_subsecs(0);
+    let (payment, pay_url) = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        if !order.verify_lookup_token(token.trim()) {
+            return Err(ApiError::InvalidToken);
+        }
+        if order.status != OrderStatus::AwaitingPayment {
+            return Err(ApiError::InvalidOrderState(format!("Order is {} and does not need payment", order.status)));
+        }
+        if let Some(pending) = repo.get_order_payments(&order.order_number)?
+            .into_iter()
+            .find(|p| p.status == PaymentStatus::Pending) {
+            return Ok((pending, None));
+        }
+        let session = provider.create_payment(&order)?;
+        let payment = Payment {
+            order_number: order.order_number,
+            provider: provider.name().to_string(),
+            reference: session.reference,
+            amount: order.total_amount,
+            status: PaymentStatus::Pending,
+            refunded_amount: Money::ZERO,
+            created_at: now,
+            updated_at: now,
+        };
+        repo.create_payment(&payment)?;
+        Ok((payment, session.pay_url))
+    }).await?;
+
+    log::info!("Payment {} created for order {}", payment.reference, payment.order_number);
+    Ok(HttpResponse::Ok().json(PaymentResponse {
+        success: true,
+        payment,
+        pay_url,
+    }))
+}
+
+/**
+ * 支付服务商回调的处理器
+ * 签名校验通过后记录支付结果
+ * 
+ * @param app_state - 应用状态（包含订单仓储和支付服务商）
+ * @param req - HTTP请求（签名在请求头中）
+ * @param body - 回调请求体原文
+ * @param order_sender - 设备事件发送器
+ * @return ApiResult<HttpResponse> - 包含支付记录的HTTP响应
+ */
+pub async fn payment_webhook(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Bytes,
+    order_sender: web::Data<OrderSender>,
+) -> ApiResult<HttpResponse> {
+    let provider = app_state.payments.clone().ok_or(ApiError::PaymentsDisabled)?;
+    let signature = req.headers().get(payment::SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(ApiError::InvalidSignature)?;
+    let event = provider.parse_webhook(&body, signature)?;
+    apply_payment_event(&app_state, &order_sender, provider.name(), event).await
+}
+
+/**
+ * 模拟支付的处理器
+ * 仅模拟支付网关可用，构造带签名的回调并按真实回调的流程处理
+ * 
+ * @param app_state - 应用状态（包含订单仓储和支付服务商）
+ * @param reference - 支付单号
+ * @param mock_req - 模拟的支付结果
+ * @param order_sender - 设备事件发送器
+ * @return ApiResult<HttpResponse> - 包含支付记录的HTTP响应
+ */
+pub async fn mock_payment(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    mock_req: web::Json<MockPaymentRequest>,
+    order_sender: web::Data<OrderSender>,
+) -> ApiResult<HttpResponse> {
+    let provider = app_state.payments.clone().ok_or(ApiError::PaymentsDisabled)?;
+    let reference = reference.into_inner();
+    let payment = app_state.run(move |repo| repo.get_payment(&reference)).await?
+        .ok_or(ApiError::PaymentNotFound)?;
+    let event = PaymentEvent {
+        reference: payment.reference,
+        status: if mock_req.succeeded { PaymentStatus::Succeeded } else { PaymentStatus::Failed },
+        amount: payment.amount,
+    };
+    let (body, signature) = provider.simulate_webhook(&event)
+        .ok_or_else(|| ApiError::BadRequest(format!("Payment provider {} does not support simulated payments", provider.name())))?;
+    let event = provider.parse_webhook(&body, &signature)?;
+    apply_payment_event(&app_state, &order_sender, provider.name(), event).await
+}
+
+/**
+ * 记录服务商通知的支付结果
+ * 支付成功的金额与支付记录不一致时按支付失败处理；重复的通知直接返回当前的支付记录
+ * 订单支付成功后重新估算预计时间，到期的订单发送给设备；
+ * 订单已不在待支付状态（已取消或已由其他方式支付）时自动退还本次支付
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param order_sender - 设备事件发送器
+ * @param provider - 支付服务商名称
+ * @param event - 支付结果
+ * @return ApiResult<HttpResponse> - 包含支付记录的HTTP响应
+ */
+async fn apply_payment_event(
+    app_state: &AppState,
+    order_sender: &OrderSender,
+    provider: &str,
+    event: PaymentEvent,
+) -> ApiResult<HttpResponse> {
+    if event.status == PaymentStatus::Pending {
+        return Err(ApiError::BadRequest("Payment event must be succeeded or failed".to_string()));
+    }
+    let provider = provider.to_string();
+    let now = Utc::now().trunc_subsecs(0);
+    let estimator = Arc::clone(&app_state.estimator);
+    let payments = app_state.payments.clone();
+    let (payment, paid_order) = app_state.run(move |repo| {
+        let payment = repo.get_payment(&event.reference)?.ok_or(ApiError::PaymentNotFound)?;
+        let order = repo.get_order_by_reference(&payment.order_number)?.ok_or(ApiError::OrderNotFound)?;
+        let amount_matches = event.amount == payment.amount;
+        // 服务商已收取的金额按实际金额记为支付成功；金额不符时订单仍等待支付，收取的金额随后退还
+        let (action, amount) = match event.status {
+            PaymentStatus::Succeeded if amount_matches => (AUDIT_PAYMENT_SUCCEEDED, event.amount),
+            PaymentStatus::Succeeded => (AUDIT_PAYMENT_AMOUNT_MISMATCH, event.amount),
+            _ => (AUDIT_PAYMENT_FAILED, payment.amount),
+        };
+        let entry = OrderAuditEntry {
+            action: action.to_string(),
+            detail: serde_json::json!({
+                "provider": provider,
+                "reference": payment.reference,
+                "amount": event.amount,
+                "expected_amount": payment.amount,
+                "amount_mismatch": !amount_matches,
+            }),
+            created_at: now,
+        };
+        // 预约订单未到期时暂存，由调度任务发送给设备
+        let dispatched_at = schedule::is_due(order.requested_time, now).then_some(now);
+        let status = event.status;
+        let paid_order = repo.complete_payment(&payment.reference, status, amount, now, dispatched_at, &entry)?;
+        if paid_order.is_some() {
+            eta::refresh_estimates(repo, &estimator, now)?;
+        } else if status == PaymentStatus::Succeeded {
+            payment::refund_unused_payment(repo, payments.as_deref(), &payment.reference, now);
+        }
+        let payment = repo.get_payment(&payment.reference)?.ok_or(ApiError::PaymentNotFound)?;
+        Ok((payment, paid_order))
+    }).await?;
+
+    log::info!("Payment {} for order {} is {}", payment.reference, payment.order_number, payment.status);
+    if let Some(order) = paid_order
+        && order.dispatched_at.is_some() {
+        notify_device(order_sender, DeviceEvent::NewOrder(Box::new(order)));
+    }
+    Ok(HttpResponse::Ok().json(PaymentResponse {
+        success: true,
+        payment,
+        pay_url: None,
+    }))
+}
+
+/**
+ * 订单退款的处理器（管理员）
+ * 未指定金额时退还全部可退金额；服务商拒绝退款时返回失败的退款记录，可稍后重试
+ * 
+ * @param app_state - 应用状态（包含订单仓储和支付服务商）
+ * @param reference - 订单编号或短码
+ * @param refund_req - 退款金额和原因
+ * @return ApiResult<HttpResponse> - 包含退款记录和退款后订单的HTTP响应
+ */
+pub async fn refund_order(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    refund_req: web::Json<RefundOrderRequest>,
+) -> ApiResult<HttpResponse> {
+    let provider = app_state.payments.clone().ok_or(ApiError::PaymentsDisabled)?;
+    let refund_req = refund_req.into_inner();
+    refund_req.validate().map_err(ApiError::Validation)?;
+
+    let reference = reference.into_inner();
+    let reason = refund_req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string);
+    let now = Utc::now().trunc_subsecs(0);
+    let (refunds, order) = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        let refunds = payment::refund_order(repo, provider.as_ref(), &order.order_number, refund_req.amount, reason.as_deref(), now)?;
+        if refunds.is_empty() {
+            return Err(ApiError::InvalidOrderState("Order has no refundable payments".to_string()));
+        }
+        let order = repo.get_order_by_reference(&order.order_number)?.ok_or(ApiError::OrderNotFound)?;
+        Ok((refunds, order))
+    }).await?;
+
+    for refund in &refunds {
+        log::info!("Refund of {} for order {} is {}", refund.amount, refund.order_number, refund.status);
+    }
+    Ok(HttpResponse::Ok().json(RefundResponse {
+        success: true,
+        refunds,
+        order,
+    }))
+}
+
+/**
+ * 获取订单退款记录的处理器
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @return ApiResult<HttpResponse> - 包含退款记录列表的HTTP响应
+ */
+pub async fn get_order_refunds(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let reference = reference.into_inner();
+    let refunds = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        repo.get_order_refunds(&order.order_number)
+    }).await?;
+    Ok(HttpResponse::Ok().json(refunds))
+}
+
+/**
+ * 创建配送员的处理器
+ * 访问令牌仅在创建时返回一次，由管理员转交给配送员
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param courier_req - 配送员姓名和电话
+ * @return ApiResult<HttpResponse> - 包含配送员和访问令牌的HTTP响应
+ */
+pub async fn create_courier(
+    app_state: web::Data<AppState>,
+    courier_req: web::Json<CreateCourierRequest>,
+) -> ApiResult<HttpResponse> {
+    let courier_req = courier_req.into_inner();
+    courier_req.validate().map_err(ApiError::Validation)?;
+
+    let courier = Courier {
+        id: 0, // 仓储会自动生成
+        name: courier_req.name.trim().to_string(),
+        phone_number: courier_req.phone_number.trim().to_string(),
+        token: generate_lookup_token(),
+        created_at: Utc::now().trunc_subsecs(0),
+    };
+    let courier = app_state.run(move |repo| repo.create_courier(&courier)).await?;
+    log::info!("Courier {} ({}) created", courier.id, courier.name);
+
+    Ok(HttpResponse::Ok().json(CreateCourierResponse {
+        success: true,
+        token: courier.token.clone(),
+        courier,
+    }))
+}
+
+/**
+ * 获取配送员列表的处理器
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @return ApiResult<HttpResponse> - 包含配送员列表的HTTP响应
+ */
+pub async fn get_couriers(app_state: web::Data<AppState>) -> ApiResult<HttpResponse> {
+    let couriers = app_state.run(|repo| repo.get_couriers()).await?;
+    Ok(HttpResponse::Ok().json(couriers))
+}
+
+/**
+ * 指派订单配送员的处理器
+ * 仅外送订单可以指派，已完成或已取消的订单不能再指派；指派变化写入审计记录
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param reference - 订单编号或短码
+ * @param assign_req - 配送员ID（为空时取消指派）
+ * @return ApiResult<HttpResponse> - 包含指派后订单的HTTP响应
+ */
+pub async fn assign_courier(
+    app_state: web::Data<AppState>,
+    reference: web::Path<String>,
+    assign_req: web::Json<AssignCourierRequest>,
+) -> ApiResult<HttpResponse> {
+    let reference = reference.into_inner();
+    let courier_id = assign_req.into_inner().courier_id;
+    let now = Utc::now().trunc_subsecs(0);
+    let order = app_state.run(move |repo| {
+        let order = repo.get_order_by_reference(&reference)?.ok_or(ApiError::OrderNotFound)?;
+        if !matches!(order.fulfilment, Fulfilment::Delivery) {
+            return Err(ApiError::InvalidOrderState("Only delivery orders can be assigned to a courier".to_string()));
+        }
+        if let Some(courier_id) = courier_id {
+            repo.get_courier(courier_id)?.ok_or(ApiError::CourierNotFound)?;
+        }
+        let entry = OrderAuditEntry {
+            action: AUDIT_COURIER_ASSIGNED.to_string(),
+            detail: serde_json::json!({ "before": order.courier_id, "after": courier_id }),
+            created_at: now,
+        };
+        repo.assign_courier(&order.order_number, courier_id, &entry)?
+            .ok_or_else(|| ApiError::InvalidOrderState(format!("Order is {} and can no longer be assigned", order.status)))
+    }).await?;
+
+    match order.courier_id {
+        Some(courier_id) => log::info!("Order {} assigned to courier {}", order.order_number, courier_id),
+        None => log::info!("Order {} courier unassigned", order.order_number),
+    }
+    Ok(HttpResponse::Ok().json(OrderResponse {
+        success: true,
+        order,
+    }))
+}
+
+/**
+ * 从请求头中读取配送员访问令牌
+ * 令牌以"Authorization: Bearer <令牌>"的形式提供
+ * 
+ * @param req - HTTP请求
+ * @return ApiResult<String> - 令牌，缺失时返回Unauthorized
+ */
+fn courier_token(req: &HttpRequest) -> ApiResult<String> {
+    req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .ok_or(ApiError::Unauthorized)
+}
+
+/**
+ * 获取配送员进行中订单的处理器
+ * 返回指派给当前配送员且尚未完成的订单，包含配送地址和坐标
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param req - HTTP请求（携带配送员访问令牌）
+ * @return ApiResult<HttpResponse> - 包含配送员和订单列表的HTTP响应
+ */
+pub async fn get_courier_orders(app_state: web::Data<AppState>, req: HttpRequest) -> ApiResult<HttpResponse> {
+    let token = courier_token(&req)?;
+    let list = app_state.run(move |repo| {
+        let courier = repo.get_courier_by_token(&token)?.ok_or(ApiError::Unauthorized)?;
+        let filter = OrderFilter {
+            statuses: vec![OrderStatus::Pending, OrderStatus::Preparing, OrderStatus::Delivering],
+            courier_id: Some(courier.id),
+            limit: u32::MAX,
+            ..Default::default()
+        };
+        let mut orders = repo.get_orders(&filter)?;
+        orders.reverse();
+        Ok(CourierOrderList { courier, orders })
+    }).await?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/**
+ * 配送员上报位置的处理器
+ * 位置保存到该配送员所有配送中的订单上，客户查询订单时可以看到
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param req - HTTP请求（携带配送员访问令牌）
+ * @param location - 配送员当前坐标
+ * @return ApiResult<HttpResponse> - 包含更新订单数的HTTP响应
+ */
+pub async fn update_courier_location(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    location: web::Json<Location>,
+) -> ApiResult<HttpResponse> {
+    let location = location.into_inner();
+    location.validate().map_err(ApiError::Validation)?;
+
+    let token = courier_token(&req)?;
+    let now = Utc::now().trunc_subsecs(0);
+    let orders_updated = app_state.run(move |repo| {
+        let courier = repo.get_courier_by_token(&token)?.ok_or(ApiError::Unauthorized)?;
+        repo.update_courier_location(courier.id, location, now)
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(CourierLocationResponse {
+        success: true,
+        orders_updated,
+    }))
+}
+
+/**
+ * 处理配送员上报的事件
+ * 订单必须指派给当前配送员且处于允许的状态，状态变化写入审计记录并重新估算预计时间
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param req - HTTP请求（携带配送员访问令牌）
+ * @param reference - 订单编号或短码
+ * @param from - 允许的当前状态
+ * @param to - 新状态
+ * @param action - 审计记录的操作类型
+ * @return ApiResult<HttpResponse> - 包含更新后订单的HTTP响应
+ */
+async fn courier_event(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    reference: String,
+    from: &'static [OrderStatus],
+    to: OrderStatus,
+    action: &'static str,
+) -> ApiResult<HttpResponse> {
+    let token = courier_token(&req)?;
+    let now = Utc::now().trunc_subsecs(0);
+    let estimator = Arc::clone(&app_state.estimator);
+    let order = app_state.run(move |repo| {
+        let courier = repo.get_courier_by_token(&token)?.ok_or(ApiError::Unauthorized)?;
+        // 未指派给该配送员的订单视为不存在
+        let order = repo.get_order_by_reference(&reference)?
+            .filter(|o| o.courier_id == Some(courier.id))
+            .ok_or(ApiError::OrderNotFound)?;
+        if !from.contains(&order.status) {
+            return Err(ApiError::InvalidOrderState(format!("Order is {}", order.status)));
+        }
+        let entry = OrderAuditEntry {
+            action: action.to_string(),
+            detail: serde_json::json!({ "courier_id": courier.id, "before": order.status, "after": to }),
+            created_at: now,
+        };
+        if !repo.update_courier_order_status(&order.order_number, courier.id, from, &to, &entry)? {
+            return Err(ApiError::InvalidOrderState("Order status changed, please try again".to_string()));
+        }
+        eta::refresh_estimates(repo, &estimator, now)?;
+        // 送达的订单坐标写入地址坐标缓存
+        address::cache_delivered_location(repo, &order.order_number, now)?;
+        repo.get_order_by_reference(&order.order_number)?.ok_or(ApiError::OrderNotFound)
+    }).await?;
+
+    log::info!("Order {} {} by courier", order.order_number, action);
+    Ok(HttpResponse::Ok().json(OrderResponse {
+        success: true,
+        order,
+    }))
+}
+
+/**
+ * 配送员取餐的处理器
+ * 待处理或制作中的订单被取走后进入配送中状态
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param req - HTTP请求（携带配送员访问令牌）
+ * @param reference - 订单编号或短码
+ * @return ApiResult<HttpResponse> - 包含更新后订单的HTTP响应
+ */
+pub async fn courier_pickup(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    reference: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    const FROM: &[OrderStatus] = &[OrderStatus::Pending, OrderStatus::Preparing];
+    courier_event(app_state, req, reference.into_inner(), FROM, OrderStatus::Delivering, AUDIT_COURIER_PICKED_UP).await
+}
+
+/**
+ * 配送员送达的处理器
+ * 配送中的订单送达后完成
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param req - HTTP请求（携带配送员访问令牌）
+ * @param reference - 订单编号或短码
+ * @return ApiResult<HttpResponse> - 包含更新后订单的HTTP响应
+ */
+pub async fn courier_delivered(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    reference: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    const FROM: &[OrderStatus] = &[OrderStatus::Delivering];
+    courier_event(app_state, req, reference.into_inner(), FROM, OrderStatus::Completed, AUDIT_COURIER_DELIVERED).await
+}
+
+/**
+ * 计算配送到某个坐标的外送费
+ * 坐标须在配送区域内且不超过最远收费档位，未配置外送费规则时不收费
+ * 
+ * @param app_state - 应用状态（包含配送区域和外送费规则）
+ * @param location - 配送地址坐标
+ * @return Option<Money> - 外送费，不能配送时返回None
+ */
+fn quote_delivery_fee(app_state: &AppState, location: Location) -> Option<Money> {
+    if app_state.zones.as_ref().is_some_and(|zones| zones.zone_for(location.lat, location.lng).is_none()) {
+        return None;
+    }
+    match &app_state.fees {
+        Some(fees) => fees.quote(location).map(|quote| quote.fee),
+        None => Some(Money::ZERO),
+    }
+}
+
+/**
+ * 规范化地址并查询缓存坐标的处理器
+ * 结账页面在地图组件未返回坐标时使用缓存的坐标
+ * 
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param query - 查询参数中的地址文本
+ * @return ApiResult<HttpResponse> - 包含结构化地址和缓存坐标的HTTP响应
+ */
+pub async fn geocode_address(
+    app_state: web::Data<AppState>,
+    query: web::Query<GeocodeQuery>,
+) -> ApiResult<HttpResponse> {
+    let address = Address::parse(&query.address);
+    if address.is_empty() {
+        return Err(ApiError::Validation(vec![FieldError::new("address", REQUIRED)]));
+    }
+    let key = address.cache_key();
+    let location = app_state.run(move |repo| repo.lookup_geocode(&key)).await?;
+    Ok(HttpResponse::Ok().json(GeocodeResponse {
+        formatted: address.to_string(),
+        address,
+        location,
+    }))
+}
+
+/**
+ * 检查坐标是否可以配送的处理器
+ * 供结账页面在提交订单前确认配送地址
+ * 
+ * @param app_state - 应用状态（包含配送区域）
+ * @param location - 查询参数中的经纬度（lat、lng）
+ * @return ApiResult<HttpResponse> - 包含检查结果的HTTP响应
+ */
+pub async fn check_delivery(
+    app_state: web::Data<AppState>,
+    location: web::Query<Location>,
+) -> ApiResult<HttpResponse> {
+    let location = location.into_inner();
+    location.validate().map_err(ApiError::Validation)?;
+
+    let zone = app_state.zones.as_ref().and_then(|zones| zones.zone_for(location.lat, location.lng));
+    Ok(HttpResponse::Ok().json(DeliveryCheckResponse {
+        deliverable: quote_delivery_fee(&app_state, location).is_some(),
+        zone: zone.and_then(|z| z.name.clone()),
+    }))
+}
+
+/**
+ * 外送费报价的处理器
+ * 结账页面据此显示外送费，并在下单时提交确认的金额
+ * 
+ * @param app_state - 应用状态（包含配送区域和外送费规则）
+ * @param location - 查询参数中的经纬度（lat、lng）
+ * @return ApiResult<HttpResponse> - 包含报价的HTTP响应
+ */
+pub async fn quote_delivery(
+    app_state: web::Data<AppState>,
+    location: web::Query<Location>,
+) -> ApiResult<HttpResponse> {
+    let location = location.into_inner();
+    location.validate().map_err(ApiError::Validation)?;
+
+    let delivery_fee = quote_delivery_fee(&app_state, location);
+    Ok(HttpResponse::Ok().json(DeliveryQuoteResponse {
+        deliverable: delivery_fee.is_some(),
+        distance_km: app_state.fees.as_ref().map(|fees| (fees.distance_km(location) * 100.0).round() / 100.0),
+        delivery_fee,
+    }))
+}
+
+/**
+ * 获取建议配送批次的处理器
+ * 将已发送给设备、尚未指派配送员的外送订单按坐标就近分组，并给出每批的建议配送顺序
+ * 
+ * @param app_state - 应用状态（包含订单仓储和门店坐标）
+ * @param query - 分组参数（每批最多订单数、最大分组距离）
+ * @return ApiResult<HttpResponse> - 包含配送批次列表的HTTP响应
+ */
+pub async fn get_delivery_batches(
+    app_state: web::Data<AppState>,
+    query: web::Query<BatchQuery>,
+) -> ApiResult<HttpResponse> {
+    let query = query.into_inner();
+    query.validate().map_err(ApiError::Validation)?;
+    let defaults = BatchOptions::default();
+    let options = BatchOptions {
+        max_orders: query.max_orders.unwrap_or(defaults.max_orders),
+        max_distance_km: query.max_distance_km.unwrap_or(defaults.max_distance_km),
+    };
+
+    let orders = app_state.run(|repo| repo.get_orders(&OrderFilter {
+        statuses: vec![OrderStatus::Pending, OrderStatus::Preparing],
+        limit: u32::MAX,
+        ..Default::default()
+    })).await?;
+    // 暂存的预约订单尚未开始制作，不参与分组
+    let waiting: Vec<Order> = orders.into_iter()
+        .filter(|o| matches!(o.fulfilment, Fulfilment::Delivery) && o.courier_id.is_none() && o.dispatched_at.is_some())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(routing::plan_batches(&waiting, app_state.estimator.store, &options)))
+}
+
+/**
+ * 汇总门店当前的营业状态
+ * 
+ * @param app_state - 应用状态（包含营业时间表）
+ * @param pause - 暂停接单信息
+ * @param now - 当前时间
+ * @return StoreStatus - 营业状态
+ */
+fn store_status(app_state: &AppState, pause: Option<StorePause>, now: DateTime<Utc>) -> StoreStatus {
+    let window = app_state.hours.current_window(now);
+    StoreStatus {
+        accepting_orders: window.is_some() && pause.is_none(),
+        open: window.is_some(),
+        paused: pause.is_some(),
+        pause_reason: pause.as_ref().and_then(|p| p.reason.clone()),
+        paused_at: pause.map(|p| p.paused_at),
+        closes_at: window.map(|(_, close)| close),
+        opens_at: if window.is_none() { app_state.hours.next_opening(now) } else { None },
+    }
+}
+
+/**
+ * 获取门店营业状态的处理器
+ * 
+ * @param app_state - 应用状态（包含订单仓储和营业时间表）
+ * @return ApiResult<HttpResponse> - 包含营业状态的HTTP响应
+ */
+pub async fn get_store_status(app_state: web::Data<AppState>) -> ApiResult<HttpResponse> {
+    let pause = app_state.run(|repo| repo.get_store_pause()).await?;
+    Ok(HttpResponse::Ok().json(store_status(&app_state, pause, Utc::now())))
+}
+
+/**
+ * 暂停或恢复接单的处理器
+ * 暂停状态保存在数据库中，服务重启后仍然有效
+ * 
+ * @param app_state - 应用状态（包含订单仓储和营业时间表）
+ * @param pause_req - 是否暂停及暂停原因
+ * @return ApiResult<HttpResponse> - 包含更新后营业状态的HTTP响应
+ */
+pub async fn set_store_pause(
+    app_state: web::Data<AppState>,
+    pause_req: web::Json<PauseOrderingRequest>,
+) -> ApiResult<HttpResponse> {
+    let pause_req = pause_req.into_inner();
+    pause_req.validate().map_err(ApiError::Validation)?;
+
+    let now = Utc::now().trunc_subsecs(0);
+    let pause = pause_req.paused.then(|| StorePause {
+        reason: pause_req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string),
+        paused_at: now,
+    });
+    let stored = pause.clone();
+    app_state.run(move |repo| repo.set_store_pause(stored.as_ref())).await?;
+
+    match &pause {
+        Some(p) => log::info!("Ordering paused: {}", p.reason.as_deref().unwrap_or("no reason given")),
+        None => log::info!("Ordering resumed"),
+    }
+    Ok(HttpResponse::Ok().json(store_status(&app_state, pause, now)))
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::Duration;
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use crate::delivery::{DeliveryFees, DeliveryZones};
+    use crate::payment::MockProvider;
+    use crate::repository::memory::InMemoryOrderRepository;
+
+    /**
+     * 使用内存仓储构建测试应用
+     * 默认全天营业，避免测试结果依赖运行时间
+     */
+    macro_rules! test_app {
+        () => {
+            test_app!("00:00-24:00")
+        };
+        ($hours:literal) => {
+            test_app!(state: AppState::new(Arc::new(InMemoryOrderRepository::new()), $hours.parse().unwrap()))
+        };
+        (state: $state:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new($state))
+                    .app_data(web::Data::new(None as OrderSender))
+                    .configure(configure),
+            ).await
+        };
+    }
+
+    fn order_payload(customer_name: &str) -> Value {
+        json!({
+            "customer_name": customer_name,
+            "phone_number": "13800138000",
+            "delivery_address": "北京市海淀区中关村大街1号",
+            "location": { "lat": 39.98, "lng": 116.31 },
+            "total_amount": "45.50",
+            "items": [
+                { "name": "拿铁 (大杯)", "quantity": 3, "price": 3.5 },
+                { "name": "美式", "quantity": 1, "price": 35 }
+            ]
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_create_and_get_order() {
+        let app = test_app!();
+
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["success"], true);
+        // 内部ID不在响应中暴露
+        assert!(resp["order"].get("id").is_none());
+        let order_number = resp["order_number"].as_str().unwrap();
+        let short_code = resp["order"]["short_code"].as_str().unwrap();
+        assert_eq!(short_code.len(), 6);
+
+        let token = resp["lookup_token"].as_str().unwrap();
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}?token={}", order_number, token)).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["customer_name"], "张三");
+        assert_eq!(order["total_amount"], 45.5);
+        assert_eq!(order["status"], "pending");
+        // 创建时间以门店时区（默认+08:00）输出
+        assert!(order["created_at"].as_str().unwrap().ends_with("+08:00"));
+
+        // 短码查询不区分大小写；没有令牌时只返回订单状态
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}", short_code.to_lowercase())).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["order_number"], order_number);
+        assert_eq!(order["status"], "pending");
+        for field in ["customer_name", "phone_number", "delivery_address", "latitude", "items"] {
+            assert!(order.get(field).is_none(), "{} should not be exposed", field);
+        }
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}?token=wrong", short_code)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        // 不能按内部ID查询订单
+        let req = test::TestRequest::get().uri("/api/orders/1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_create_order_validation_error() {
+        let app = test_app!();
+
+        let mut payload = order_payload("");
+        payload["items"] = json!([]);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(payload).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "validation_failed");
+        let fields: Vec<&str> = body["errors"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+        assert!(fields.contains(&"customer_name"));
+        assert!(fields.contains(&"items"));
+    }
+
+    #[actix_web::test]
+    async fn test_geocode_cache() {
+        let app = test_app!();
+        let req = test::TestRequest::get().uri("/api/addresses/geocode?address=%E6%9C%9B%E4%BA%ACSOHO").to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.get("location").is_none());
+
+        // 下单时规范化地址，订单送达前不缓存坐标
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let first: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(first["order"]["address"]["district"], "海淀区");
+        assert_eq!(first["order"]["delivery_address"], "北京市海淀区中关村大街1号");
+        let mut payload = order_payload("李四");
+        payload["delivery_address"] = json!("北京 海淀区 中关村大街１号 502室");
+        payload.as_object_mut().unwrap().remove("location");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        // 送达后缓存坐标，之后其他订单的坐标不会覆盖缓存
+        let mut other = order_payload("王五");
+        other["location"] = json!({ "lat": 39.9, "lng": 116.4 });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&other).to_request();
+        let second: Value = test::call_and_read_body_json(&app, req).await;
+        for order in [&first, &second] {
+            let req = test::TestRequest::put().uri(&format!("/api/orders/{}/status", order["order_number"].as_str().unwrap()))
+                .set_json(json!({ "status": "completed" }))
+                .to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+        }
+
+        // 同一楼栋的其他房间不提供坐标也可以下单
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["latitude"], 39.98);
+        assert_eq!(resp["order"]["address"]["room"], "502室");
+
+        // 未缓存的地址仍需要坐标
+        payload["delivery_address"] = json!("北京市朝阳区望京街10号");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["errors"][0]["field"], "location");
+    }
+
+    #[actix_web::test]
+    async fn test_export_orders_geojson() {
+        let app = test_app!();
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        // 自取订单没有坐标，不在导出结果中
+        let mut payload = order_payload("李四");
+        payload["fulfilment"] = json!({ "type": "pickup" });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(payload).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get().uri("/api/admin/orders.geojson?status=pending").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/geo+json");
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["type"], "FeatureCollection");
+        let features = body["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["geometry"], json!({ "type": "Point", "coordinates": [116.31, 39.98] }));
+        assert_eq!(features[0]["properties"]["order_number"], created["order_number"]);
+        assert_eq!(features[0]["properties"]["status"], "pending");
+        assert_eq!(features[0]["properties"]["total_amount"], 45.5);
+
+        // 按创建时间范围过滤
+        let req = test::TestRequest::get().uri("/api/admin/orders.geojson?to=2000-01-01").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["features"], json!([]));
+        let req = test::TestRequest::get().uri("/api/admin/orders.geojson?from=yesterday").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_order_payment() {
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_payment_provider(Arc::new(MockProvider::new("secret")));
+        let app = test_app!(state: state);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created["order"]["status"], "awaiting_payment");
+        let uri = format!("/api/orders/{}/payment", created["order_number"].as_str().unwrap());
+
+        // 未支付的订单只能通过支付进入待处理状态
+        let status_uri = format!("/api/orders/{}/status", created["order_number"].as_str().unwrap());
+        for status in ["pending", "preparing", "delivering", "completed"] {
+            let req = test::TestRequest::put().uri(&status_uri).set_json(json!({ "status": status })).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        }
+
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": "wrong" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": created["lookup_token"] })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["payment"]["status"], "pending");
+        assert_eq!(resp["payment"]["amount"], 45.5);
+        let reference = resp["payment"]["reference"].as_str().unwrap().to_string();
+
+        // 签名无效的回调被拒绝
+        let body = json!({ "reference": reference, "status": "succeeded", "amount": 45.5 }).to_string();
+        let req = test::TestRequest::post().uri("/api/payments/webhook")
+            .insert_header((payment::SIGNATURE_HEADER, payment::sign(b"other", body.as_bytes())))
+            .set_payload(body.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        // 金额不符时订单仍等待支付，已收取的金额记入审计并自动退还
+        let mismatched = json!({ "reference": reference, "status": "succeeded", "amount": 0.01 }).to_string();
+        let req = test::TestRequest::post().uri("/api/payments/webhook")
+            .insert_header((payment::SIGNATURE_HEADER, payment::sign(b"secret", mismatched.as_bytes())))
+            .set_payload(mismatched)
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["payment"]["amount"], 0.01);
+        assert_eq!(resp["payment"]["refunded_amount"], 0.01);
+        let order_number = created["order_number"].as_str().unwrap();
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/refunds", order_number)).to_request();
+        let refunds: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(refunds[0]["amount"], 0.01);
+        assert_eq!(refunds[0]["reason"], "Payment amount did not match the order");
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/audit", order_number)).to_request();
+        let audit: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(audit[0]["action"], "payment_amount_mismatch");
+        assert_eq!(audit[0]["detail"]["expected_amount"], 45.5);
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}", order_number)).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["status"], "awaiting_payment");
+
+        // 重新发起支付后模拟支付成功
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": created["lookup_token"] })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let reference = resp["payment"]["reference"].as_str().unwrap().to_string();
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", reference)).set_json(json!({})).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["payment"]["status"], "succeeded");
+
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}", created["order_number"].as_str().unwrap())).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["status"], "pending");
+        assert!(order["estimated_ready_at"].is_string());
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": created["lookup_token"] })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+        // 已支付的订单只能做不改变总金额的修改
+        let items_uri = format!("/api/orders/{}/items", created["order_number"].as_str().unwrap());
+        let req = test::TestRequest::put().uri(&items_uri)
+            .set_json(json!({ "items": [{ "name": "美式", "quantity": 1, "price": 35 }] }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::put().uri(&items_uri)
+            .set_json(json!({ "items": [{ "name": "美式", "quantity": 1, "price": 1 }, { "name": "拿铁 (大杯)", "quantity": 3, "price": 1 }] }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_duplicate_payment_refunded() {
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_payment_provider(Arc::new(MockProvider::new("secret")));
+        let app = test_app!(state: state);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = created["order_number"].as_str().unwrap().to_string();
+        let uri = format!("/api/orders/{}/payment", order_number);
+
+        // 重复发起支付返回同一笔等待结果的支付
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": created["lookup_token"] })).to_request();
+        let first: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri(&uri).set_json(json!({ "token": created["lookup_token"] })).to_request();
+        let second: Value = test::call_and_read_body_json(&app, req).await;
+        let reference = first["payment"]["reference"].as_str().unwrap().to_string();
+        assert_eq!(second["payment"]["reference"], reference);
+
+        // 订单在等待支付结果期间被取消，之后支付成功的金额全额退还
+        let req = test::TestRequest::put().uri(&format!("/api/orders/{}/status", order_number))
+            .set_json(json!({ "status": "cancelled" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", reference)).set_json(json!({})).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["payment"]["status"], "succeeded");
+        assert_eq!(resp["payment"]["refunded_amount"], 45.5);
+
+        // 重复的回调不会再次退款
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", reference)).set_json(json!({})).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/refunds", order_number)).to_request();
+        let refunds: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(refunds.as_array().unwrap().len(), 1);
+        assert_eq!(refunds[0]["reason"], "Order cancelled");
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}?token={}", order_number, created["lookup_token"].as_str().unwrap())).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["status"], "cancelled");
+        assert_eq!(order["refund_status"], "refunded");
+    }
+
+    #[actix_web::test]
+    async fn test_order_refunds() {
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_payment_provider(Arc::new(MockProvider::new("secret")));
+        let app = test_app!(state: state);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = created["order_number"].as_str().unwrap().to_string();
+        let refunds_uri = format!("/api/orders/{}/refunds", order_number);
+
+        // 未支付的订单没有可退金额
+        let req = test::TestRequest::post().uri(&refunds_uri).set_json(json!({})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post().uri(&format!("/api/orders/{}/payment", order_number))
+            .set_json(json!({ "token": created["lookup_token"] }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", resp["payment"]["reference"].as_str().unwrap()))
+            .set_json(json!({}))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // 部分退款，超出可退金额的请求被拒绝
+        let req = test::TestRequest::post().uri(&refunds_uri).set_json(json!({ "amount": 10, "reason": "少一杯" })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["refunds"][0]["status"], "succeeded");
+        assert_eq!(resp["order"]["refunded_amount"], 10.0);
+        assert_eq!(resp["order"]["refund_status"], "partially_refunded");
+        let req = test::TestRequest::post().uri(&refunds_uri).set_json(json!({ "amount": 40 })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["errors"][0]["field"], "amount");
+        let req = test::TestRequest::post().uri(&refunds_uri).set_json(json!({ "amount": 0 })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        // 取消订单时自动退还剩余金额
+        let req = test::TestRequest::put().uri(&format!("/api/orders/{}/status", order_number))
+            .set_json(json!({ "status": "cancelled" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}?token={}", order_number, created["lookup_token"].as_str().unwrap())).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["refunded_amount"], 45.5);
+        assert_eq!(order["refund_status"], "refunded");
+
+        let req = test::TestRequest::get().uri(&refunds_uri).to_request();
+        let refunds: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(refunds.as_array().unwrap().len(), 2);
+        assert_eq!(refunds[1]["amount"], 35.5);
+        assert_eq!(refunds[1]["reason"], "Order cancelled");
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_cancel_refunds() {
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_payment_provider(Arc::new(MockProvider::new("secret")));
+        let app = test_app!(state: state);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = created["order_number"].as_str().unwrap().to_string();
+        let req = test::TestRequest::post().uri(&format!("/api/orders/{}/payment", order_number))
+            .set_json(json!({ "token": created["lookup_token"] }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", resp["payment"]["reference"].as_str().unwrap()))
+            .set_json(json!({}))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // 按旧版订单ID取消已支付的订单同样自动退款
+        let req = test::TestRequest::put().uri("/api/orders/1/status").set_json(json!({ "status": "cancelled" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Deprecation").unwrap(), "true");
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/refunds", order_number)).to_request();
+        let refunds: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(refunds[0]["amount"], 45.5);
+        assert_eq!(refunds[0]["status"], "succeeded");
+    }
+
+    #[actix_web::test]
+    async fn test_completed_order_cannot_be_cancelled() {
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_payment_provider(Arc::new(MockProvider::new("secret")));
+        let app = test_app!(state: state);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = created["order_number"].as_str().unwrap().to_string();
+        let req = test::TestRequest::post().uri(&format!("/api/orders/{}/payment", order_number))
+            .set_json(json!({ "token": created["lookup_token"] }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri(&format!("/api/payments/mock/{}", resp["payment"]["reference"].as_str().unwrap()))
+            .set_json(json!({}))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // 已完成的订单不能取消，也不会退款
+        let uri = format!("/api/orders/{}/status", order_number);
+        let req = test::TestRequest::put().uri(&uri).set_json(json!({ "status": "completed" })).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::put().uri(&uri).set_json(json!({ "status": "cancelled" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/refunds", order_number)).to_request();
+        let refunds: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(refunds.as_array().unwrap().is_empty());
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}", order_number)).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["status"], "completed");
+    }
+
+    #[actix_web::test]
+    async fn test_list_and_update_orders() {
+        let app = test_app!();
+        let mut created = Vec::new();
+        for name in ["张三", "李四", "王五"] {
+            let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload(name)).to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            created.push(resp["order"].clone());
+        }
+
+        // 按订单编号和短码更新状态
+        let uri = format!("/api/orders/{}/status", created[1]["order_number"].as_str().unwrap());
+        let req = test::TestRequest::put().uri(&uri).set_json(json!({ "status": "preparing" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(resp.headers().get("Deprecation").is_none());
+        let uri = format!("/api/orders/{}/status", created[1]["short_code"].as_str().unwrap());
+        let req = test::TestRequest::put().uri(&uri).set_json(json!({ "status": "delivering" })).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // 状态不能回退
+        let req = test::TestRequest::put().uri(&uri).set_json(json!({ "status": "preparing" })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["code"], "invalid_order_state");
+
+        // 兼容期内仍可按旧版订单ID更新，响应带Deprecation头
+        let req = test::TestRequest::put().uri("/api/orders/1/status").set_json(json!({ "status": "preparing" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("Deprecation").unwrap(), "true");
+
+        let req = test::TestRequest::get().uri("/api/orders?status=pending&page_size=1").to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["total"], 1);
+        assert_eq!(list["orders"].as_array().unwrap().len(), 1);
+        assert_eq!(list["orders"][0]["customer_name"], "王五");
+
+        let req = test::TestRequest::put().uri("/api/orders/99/status").set_json(json!({ "status": "preparing" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/api/orders?status=unknown").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_customer_cancel_order() {
+        let app = test_app!();
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let token = resp["lookup_token"].as_str().unwrap().to_string();
+        let cancel_uri = format!("/api/orders/{}/cancel", resp["order_number"].as_str().unwrap());
+
+        // 令牌不匹配时拒绝取消
+        let req = test::TestRequest::post().uri(&cancel_uri).set_json(json!({ "token": "wrong" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post().uri(&cancel_uri)
+            .set_json(json!({ "token": token, "reason": " 下错单了 " }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["status"], "cancelled");
+        assert_eq!(resp["order"]["cancel_reason"], "下错单了");
+        assert!(resp["order"]["cancelled_at"].is_string());
+
+        // 已取消的订单不能再次取消
+        let req = test::TestRequest::post().uri(&cancel_uri).set_json(json!({ "token": token })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_order_state");
+    }
+
+    #[actix_web::test]
+    async fn test_order_estimates() {
+        let app = test_app!();
+        let time = |value: &Value| DateTime::parse_from_rfc3339(value.as_str().unwrap()).unwrap();
+        let mut created = Vec::new();
+        for name in ["张三", "李四"] {
+            let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload(name)).to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            created.push(resp);
+        }
+        // 每杯默认制作2分钟，未配置门店坐标时配送20分钟
+        let first = &created[0]["order"];
+        assert_eq!(time(&first["estimated_arrival_at"]) - time(&first["estimated_ready_at"]), Duration::minutes(20));
+
+        // 第二单排在第一单之后；第一单取消后提前
+        let uri = format!("/api/orders/{}", created[1]["order_number"].as_str().unwrap());
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let second: Value = test::call_and_read_body_json(&app, req).await;
+        let first_ready = time(&second["estimated_ready_at"]) - Duration::minutes(8);
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}", created[0]["order_number"].as_str().unwrap())).to_request();
+        let first: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(time(&first["estimated_ready_at"]), first_ready);
+
+        let cancel_uri = format!("/api/orders/{}/cancel", created[0]["order_number"].as_str().unwrap());
+        let req = test::TestRequest::post().uri(&cancel_uri)
+            .set_json(json!({ "token": created[0]["lookup_token"] }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let moved: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(time(&moved["estimated_ready_at"]) < time(&second["estimated_ready_at"]));
+    }
+
+    #[actix_web::test]
+    async fn test_courier_assignment() {
+        let app = test_app!();
+        let req = test::TestRequest::post().uri("/api/couriers")
+            .set_json(json!({ "name": "王师傅", "phone_number": "13900139000" }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let courier_id = resp["courier"]["id"].as_i64().unwrap();
+        let token = resp["token"].as_str().unwrap().to_string();
+        assert!(resp["courier"].get("token").is_none());
+
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = resp["order_number"].as_str().unwrap().to_string();
+        let lookup_token = resp["lookup_token"].as_str().unwrap().to_string();
+
+        // 不存在的配送员
+        let courier_uri = format!("/api/orders/{}/courier", order_number);
+        let req = test::TestRequest::put().uri(&courier_uri).set_json(json!({ "courier_id": 99 })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::put().uri(&courier_uri).set_json(json!({ "courier_id": courier_id })).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["courier_id"], courier_id);
+
+        // 配送员接口需要令牌
+        let req = test::TestRequest::get().uri("/api/courier/orders").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+        let bearer = ("Authorization", format!("Bearer {}", token));
+        let req = test::TestRequest::get().uri("/api/courier/orders").insert_header(bearer.clone()).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["orders"][0]["order_number"], order_number.as_str());
+        assert_eq!(resp["orders"][0]["latitude"], 39.98);
+
+        // 送达前必须先取餐
+        let delivered_uri = format!("/api/courier/orders/{}/delivered", order_number);
+        let req = test::TestRequest::post().uri(&delivered_uri).insert_header(bearer.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::post().uri(&format!("/api/courier/orders/{}/pickup", order_number))
+            .insert_header(bearer.clone())
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["status"], "delivering");
+
+        // 配送中的订单可以查询配送员位置，送达后清除
+        let req = test::TestRequest::post().uri("/api/courier/location")
+            .insert_header(bearer.clone())
+            .set_json(json!({ "lat": 39.99, "lng": 116.32 }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["orders_updated"], 1);
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}?token={}", order_number, lookup_token)).to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(order["courier_location"]["lat"], 39.99);
+        let req = test::TestRequest::post().uri(&delivered_uri).insert_header(bearer.clone()).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["status"], "completed");
+        assert!(resp["order"].get("courier_location").is_none());
+
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/audit", order_number)).to_request();
+        let audit: Value = test::call_and_read_body_json(&app, req).await;
+        let actions: Vec<&str> = audit.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, ["courier_assigned", "courier_picked_up", "courier_delivered"]);
+    }
+
+    #[actix_web::test]
+    async fn test_delivery_batches() {
+        let app = test_app!();
+        for name in ["张三", "李四"] {
+            let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload(name)).to_request();
+            test::call_service(&app, req).await;
+        }
+        let mut far = order_payload("王五");
+        far["location"] = json!({ "lat": 40.08, "lng": 116.31 });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(far).to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/api/delivery/batches").to_request();
+        let batches: Value = test::call_and_read_body_json(&app, req).await;
+        let sizes: Vec<usize> = batches.as_array().unwrap().iter().map(|b| b["stops"].as_array().unwrap().len()).collect();
+        assert_eq!(sizes, [2, 1]);
+
+        let req = test::TestRequest::get().uri("/api/delivery/batches?max_orders=0").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_amend_pending_order() {
+        let app = test_app!();
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let order_number = resp["order_number"].as_str().unwrap().to_string();
+        let amend_uri = format!("/api/orders/{}/items", order_number);
+
+        // 不在订单和菜单中的商品被拒绝
+        let req = test::TestRequest::put().uri(&amend_uri)
+            .set_json(json!({ "items": [{ "name": "美式", "quantity": 1, "price": 1 }, { "name": "摩卡 (中杯)", "quantity": 1, "price": 1 }] }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["errors"], json!([{ "field": "items[1].name", "code": "unknown_item" }]));
+
+        // 单价由服务器确定：新增商品按菜单价格，已有商品沿用原单价，客户端提交的价格被忽略
+        let payload = json!({
+            "items": [{ "name": "拿铁 (中杯)", "quantity": 2, "price": "3.20" }, { "name": "美式", "quantity": 1, "price": 1 }],
+            "reason": "顾客来电改杯型"
+        });
+        let req = test::TestRequest::put().uri(&amend_uri).set_json(&payload).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["total_amount"], 115.0);
+        assert_eq!(resp["order"]["items"][0]["price"], 40.0);
+        assert_eq!(resp["order"]["items"][1]["price"], 35.0);
+
+        let req = test::TestRequest::get().uri(&format!("/api/orders/{}/audit", order_number)).to_request();
+        let audit: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(audit[0]["action"], "items_amended");
+        assert_eq!(audit[0]["detail"]["before"]["total_amount"], 45.5);
+        assert_eq!(audit[0]["detail"]["after"]["total_amount"], 115.0);
+        assert_eq!(audit[0]["detail"]["reason"], "顾客来电改杯型");
+
+        // 开始制作后不能再修改
+        let req = test::TestRequest::put().uri(&format!("/api/orders/{}/status", order_number))
+            .set_json(json!({ "status": "preparing" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::put().uri(&amend_uri).set_json(&payload).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
     }
-} 
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_create_scheduled_order() {
+        let app = test_app!("08:00-22:00");
+        let tomorrow = Utc::now().with_timezone(&config::store_timezone()).date_naive() + chrono::Days::new(1);
+
+        // 预约时间按门店时区解析（默认营业时间08:00-22:00）
+        let mut payload = order_payload("张三");
+        payload["requested_time"] = json!(format!("{}T10:30:00", tomorrow));
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["requested_time"], format!("{}T10:30:00+08:00", tomorrow));
+
+        for (requested_time, code) in [
+            (format!("{}T23:00:00", tomorrow), "outside_opening_hours"),
+            ("2020-01-01T10:00:00".to_string(), "out_of_range"),
+            ("明天上午".to_string(), "invalid_format"),
+        ] {
+            payload["requested_time"] = json!(requested_time);
+            let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["errors"][0], json!({ "field": "requested_time", "code": code }));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_create_pickup_and_dine_in_orders() {
+        let app = test_app!();
+
+        // 自取订单无需地址，即使提供了也不保存
+        let mut payload = order_payload("张三");
+        payload["fulfilment"] = json!({ "type": "pickup" });
+        payload.as_object_mut().unwrap().remove("location");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["fulfilment"], json!({ "type": "pickup" }));
+        assert!(resp["order"]["delivery_address"].is_null());
+
+        let mut payload = order_payload("李四");
+        payload["fulfilment"] = json!({ "type": "dine_in", "table_number": "A3" });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get().uri("/api/orders").to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["orders"][0]["fulfilment"], json!({ "type": "dine_in", "table_number": "A3" }));
+
+        // 外送订单仍然需要坐标
+        let mut payload = order_payload("王五");
+        payload.as_object_mut().unwrap().remove("location");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_reject_orders_when_closed() {
+        let app = test_app!("mon-sun=closed");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "store_closed");
+
+        let req = test::TestRequest::get().uri("/api/store/status").to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["open"], false);
+        assert_eq!(status["accepting_orders"], false);
+        assert!(status.get("opens_at").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_pause_ordering() {
+        let app = test_app!();
+        let req = test::TestRequest::get().uri("/api/store/status").to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["accepting_orders"], true);
+        assert!(status["closes_at"].is_string());
+
+        let req = test::TestRequest::put().uri("/api/store/pause")
+            .set_json(json!({ "paused": true, "reason": "设备维护" }))
+            .to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["accepting_orders"], false);
+        assert_eq!(status["paused"], true);
+        assert_eq!(status["pause_reason"], "设备维护");
+
+        // 暂停期间预约订单也不接受
+        let tomorrow = Utc::now().date_naive() + chrono::Days::new(1);
+        let mut payload = order_payload("张三");
+        payload["requested_time"] = json!(format!("{}T12:00:00", tomorrow));
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "Ordering is paused: 设备维护");
+
+        let req = test::TestRequest::put().uri("/api/store/pause").set_json(json!({ "paused": false })).to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["accepting_orders"], true);
+        assert!(status.get("pause_reason").is_none());
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(order_payload("张三")).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_delivery_zone_check() {
+        let zones = DeliveryZones::from_geojson(r#"{
+            "type": "Feature", "properties": { "name": "中关村" },
+            "geometry": { "type": "Polygon", "coordinates": [[[116.28, 39.96], [116.34, 39.96], [116.34, 40.00], [116.28, 40.00], [116.28, 39.96]]] }
+        }"#).unwrap();
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_delivery_zones(zones);
+        let app = test_app!(state: state);
+
+        let req = test::TestRequest::get().uri("/api/delivery/check?lat=39.98&lng=116.31").to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp, json!({ "deliverable": true, "zone": "中关村" }));
+        let req = test::TestRequest::get().uri("/api/delivery/check?lat=39.90&lng=116.40").to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp, json!({ "deliverable": false }));
+        let req = test::TestRequest::get().uri("/api/delivery/check?lat=91&lng=116.40").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        // 配送范围外的外送订单被拒绝，自取订单不受影响
+        let mut payload = order_payload("张三");
+        payload["location"] = json!({ "lat": 39.90, "lng": 116.40 });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["errors"][0], json!({ "field": "location", "code": "outside_delivery_zone" }));
+
+        payload["fulfilment"] = json!({ "type": "pickup" });
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_delivery_fee_quote() {
+        // 门店在中关村，3公里内收5元，8公里内收8元
+        let fees = DeliveryFees::new(Location { lat: 39.98, lng: 116.31 }, "3=5;8=8").unwrap();
+        let state = AppState::new(Arc::new(InMemoryOrderRepository::new()), "00:00-24:00".parse().unwrap())
+            .with_delivery_fees(fees);
+        let app = test_app!(state: state);
+
+        let req = test::TestRequest::get().uri("/api/delivery/quote?lat=39.98&lng=116.35").to_request();
+        let quote: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(quote["deliverable"], true);
+        assert_eq!(quote["delivery_fee"], 8.0);
+        assert!((quote["distance_km"].as_f64().unwrap() - 3.41).abs() < 0.01);
+        let req = test::TestRequest::get().uri("/api/delivery/quote?lat=40.20&lng=116.31").to_request();
+        let quote: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(quote["deliverable"], false);
+        assert!(quote.get("delivery_fee").is_none());
+
+        // 未确认外送费的订单被拒绝
+        let mut payload = order_payload("张三");
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+        assert_eq!(body["errors"][0], json!({ "field": "delivery_fee", "code": "mismatch" }));
+
+        payload["delivery_fee"] = json!(5);
+        payload["total_amount"] = json!(50.5);
+        let req = test::TestRequest::post().uri("/api/orders/create").set_json(&payload).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["delivery_fee"], 5.0);
+        assert_eq!(resp["order"]["total_amount"], 50.5);
+
+        // 修改商品后总金额仍包含外送费
+        let req = test::TestRequest::put().uri(&format!("/api/orders/{}/items", resp["order_number"].as_str().unwrap()))
+            .set_json(json!({ "items": [{ "name": "柠檬水 (小杯)", "quantity": 1, "price": 1 }] }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["order"]["total_amount"], 25.0);
+    }
+}
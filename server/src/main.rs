@@ -1,24 +1,40 @@
-/**
+/*!
  * 饮品订购系统后端服务器入口文件
  * 负责初始化数据库、串口通信和HTTP服务器
  * 提供API路由和静态文件服务
  */
 
 // 导入自定义模块
+mod address;    // 地址模块
+mod config;     // 门店配置模块
 mod db;         // 数据库操作模块
+mod delivery;   // 配送范围模块
+mod error;      // 错误处理模块
+mod eta;        // 订单预计时间模块
 mod handlers;   // HTTP请求处理器模块
+mod menu;       // 菜单价格模块
 mod models;     // 数据模型模块
+mod payment;    // 支付模块
+mod repository; // 订单仓储模块
+mod routing;    // 配送批次模块
+mod schedule;   // 营业时间与预约订单调度模块
 mod serial_comm; // 串口通信模块
+mod validation;  // 请求校验模块
 
 // 导入外部依赖
 use actix_cors::Cors;
 use actix_files::{Files, NamedFile};
 use actix_web::{middleware::Logger, web, App, HttpServer, Result};
-use rusqlite::Connection;
-use std::sync::Mutex;
+use chrono::{SubsecRound, Utc};
+use std::sync::{Arc, Mutex};
 use serial_comm::SerialComm;
+use repository::{AppState, OrderRepository, SqliteOrderRepository};
 use std::env;
 
+// 数据库配置常量
+const DATABASE_PATH: &str = "orders.db"; // 数据库文件路径
+const DEFAULT_POOL_SIZE: u32 = 8;        // 默认连接池大小
+
 /**
  * 处理前端路由的回退路由处理器
  * 返回前端应用的index.html文件
@@ -48,24 +64,59 @@ async fn main() -> std::io::Result<()> {
     // 优先使用环境变量中的串口配置，如果未设置则尝试自动检测
     let port_name = env::var("SERIAL_PORT").ok().or_else(|| {
         let ports = SerialComm::list_ports();
-        ports.first().map(|p| p.clone())
+        ports.first().cloned()
     });
 
-    // 初始化数据库连接
-    let conn = Connection::open("orders.db").unwrap();
-    db::init_db(&conn).unwrap();
-    let db_conn = web::Data::new(db::AppState {
-        db: Mutex::new(conn),
-    });
+    // 检查门店时区配置，其余模块直接使用检查后的时区
+    config::load_store_timezone().map_err(std::io::Error::other)?;
+
+    // 初始化数据库连接池和订单仓储
+    let pool_size = match env::var("DB_POOL_SIZE") {
+        Ok(value) => value.trim().parse::<u32>().ok().filter(|n| *n > 0).unwrap_or_else(|| {
+            log::warn!("Invalid DB_POOL_SIZE {:?}, using {}", value, DEFAULT_POOL_SIZE);
+            DEFAULT_POOL_SIZE
+        }),
+        Err(_) => DEFAULT_POOL_SIZE,
+    };
+    let pool = db::open_pool(DATABASE_PATH, pool_size)
+        .map_err(|e| std::io::Error::other(format!("Failed to open database: {}", e)))?;
+    let repo: Arc<dyn OrderRepository> = Arc::new(SqliteOrderRepository::new(pool));
+    let mut app_state = AppState::new(repo.clone(), config::store_hours().map_err(std::io::Error::other)?.clone())
+        .with_estimator(config::estimator().clone());
+    if let Some(zones) = config::delivery_zones().map_err(std::io::Error::other)? {
+        app_state = app_state.with_delivery_zones(zones.clone());
+    }
+    if let Some(fees) = config::delivery_fees().map_err(std::io::Error::other)? {
+        app_state = app_state.with_delivery_fees(fees.clone());
+    }
+    let payment_provider = config::payment_provider().map_err(std::io::Error::other)?;
+    if let Some(provider) = payment_provider {
+        log::info!("Payments enabled with provider {}", provider.name());
+        app_state = app_state.with_payment_provider(provider.clone());
+    }
+    let app_state = web::Data::new(app_state);
 
     // 初始化串口通信
     // 创建一个回调函数用于处理订单状态更新
-    let db_clone = db_conn.clone();
-    let serial_comm = port_name.clone().and_then(|pn| SerialComm::new(&pn, Box::new(move |order_number, status| {
-        if let Ok(conn) = db_clone.db.lock() {
-            if let Err(e) = db::update_order_status_by_number(&conn, &order_number, &status.to_string()) {
-                log::error!("Failed to update order status: {}", e);
+    let serial_comm = port_name.clone().and_then(|pn| SerialComm::new(&pn, Box::new(move |reference, status| {
+        // 串口回调运行在独立线程上，可以直接同步访问仓储
+        // 状态变化后重新估算排队中订单的预计时间
+        let now = Utc::now().trunc_subsecs(0);
+        let result = repo.update_order_status_by_reference(&reference, &status)
+            .and_then(|updated| eta::refresh_estimates(repo.as_ref(), config::estimator(), now).map(|_| updated));
+        match result {
+            // 设备取消待支付、待处理或制作中的已支付订单时自动退款
+            Ok(Some((order_number, previous))) if status == models::OrderStatus::Cancelled && previous.refunds_on_cancel() => {
+                payment::refund_cancelled_order(repo.as_ref(), payment_provider.map(|p| p.as_ref()), &order_number, now);
+            }
+            // 设备确认送达的外送订单坐标写入地址坐标缓存
+            Ok(Some((order_number, _))) if status == models::OrderStatus::Completed => {
+                if let Err(e) = address::cache_delivered_location(repo.as_ref(), &order_number, now) {
+                    log::error!("Failed to cache delivery location: {}", e);
+                }
             }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to update order status: {}", e),
         }
     })).ok());
 
@@ -75,9 +126,12 @@ async fn main() -> std::io::Result<()> {
 
     // 创建串口通信共享状态
     let order_sender = web::Data::new(
-        serial_comm.map(|sc| Mutex::new(sc))
+        serial_comm.map(Mutex::new)
     );
 
+    // 启动预约订单调度任务
+    actix_web::rt::spawn(schedule::run_dispatcher(app_state.clone(), order_sender.clone()));
+
     // 获取服务器监听地址，默认为127.0.0.1:3001
     let listen_addr = env::var("LISTEN_ADDR").unwrap_or("127.0.0.1:3001".to_string());
     log::info!("Server running at http://{}", listen_addr);
@@ -99,16 +153,10 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(Logger::default()) // 启用请求日志记录
             .wrap(cors)             // 启用CORS
-            .app_data(db_conn.clone()) // 注入数据库连接
+            .app_data(app_state.clone()) // 注入订单仓储
             .app_data(order_sender.clone()) // 注入串口通信实例
             // API路由配置
-            .service(
-                web::scope("/api")
-                    .route("/orders/create", web::post().to(handlers::create_order))
-                    .route("/orders", web::get().to(handlers::get_orders))
-                    .route("/orders/{order_number}", web::get().to(handlers::get_order))
-                    .route("/orders/{order_id}/status", web::put().to(handlers::update_order_status)),
-            )
+            .configure(handlers::configure)
             // 静态文件服务
             .service(Files::new("/", "../build").index_file("index.html"))
             // 管理后台路由
@@ -0,0 +1,96 @@
+/*!
+ * 菜单价格模块
+ * 服务器端的饮品、规格和配料价格表，与前端src/data/drinks.js保持一致；
+ * 用于按商品名称计算单价，而不是信任客户端提交的价格
+ */
+
+use crate::models::Money;
+
+// 饮品基础价格（分）
+const DRINKS: [(&str, i64); 9] = [
+    ("浓缩咖啡", 2800),
+    ("卡布奇诺", 3200),
+    ("拿铁", 3500),
+    ("绿茶", 2200),
+    ("伯爵红茶", 2500),
+    ("印度奶茶", 3000),
+    ("冰咖啡", 2800),
+    ("冷萃咖啡", 3500),
+    ("柠檬水", 2000),
+];
+
+// 规格加价（分）
+const SIZES: [(&str, i64); 3] = [
+    ("小杯", 0),
+    ("中杯", 500),
+    ("大杯", 1000),
+];
+
+// 配料价格（分）
+const OPTIONS: [(&str, i64); 8] = [
+    ("加浓咖啡", 500),
+    ("香草糖浆", 350),
+    ("焦糖糖浆", 350),
+    ("榛果糖浆", 350),
+    ("杏仁奶", 500),
+    ("燕麦奶", 500),
+    ("鲜奶油", 350),
+    ("冰块", 0),
+];
+
+/**
+ * 在价格表中查找名称对应的价格
+ */
+fn lookup(table: &[(&str, i64)], name: &str) -> Option<i64> {
+    table.iter().find(|(n, _)| *n == name).map(|(_, cents)| *cents)
+}
+
+/**
+ * 按商品名称计算单价
+ * 名称格式与前端下单时一致："饮品 (规格)"，有配料时追加" - 配料, 配料"
+ *
+ * @param name - 商品名称
+ * @return Option<Money> - 单价，饮品、规格或配料不在菜单中（或配料重复）时返回None
+ */
+pub fn unit_price(name: &str) -> Option<Money> {
+    let (drink_part, options_part) = match name.split_once(" - ") {
+        Some((drink_part, options_part)) => (drink_part, Some(options_part)),
+        None => (name, None),
+    };
+    let (drink, size) = drink_part.strip_suffix(')')?.rsplit_once(" (")?;
+    let mut cents = lookup(&DRINKS, drink)? + lookup(&SIZES, size)?;
+    if let Some(options_part) = options_part {
+        let mut seen = Vec::new();
+        for option in options_part.split(", ") {
+            if seen.contains(&option) {
+                return None;
+            }
+            cents += lookup(&OPTIONS, option)?;
+            seen.push(option);
+        }
+    }
+    Some(Money::from_cents(cents))
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_price() {
+        assert_eq!(unit_price("拿铁 (小杯)"), Some(Money::from_cents(3500)));
+        assert_eq!(unit_price("拿铁 (大杯) - 燕麦奶, 香草糖浆"), Some(Money::from_cents(5350)));
+        assert_eq!(unit_price("柠檬水 (中杯) - 冰块"), Some(Money::from_cents(2500)));
+
+        // 不在菜单中的饮品、规格或配料
+        assert_eq!(unit_price("美式 (小杯)"), None);
+        assert_eq!(unit_price("拿铁 (超大杯)"), None);
+        assert_eq!(unit_price("拿铁 (小杯) - 巧克力"), None);
+        assert_eq!(unit_price("拿铁"), None);
+        // 同一配料不能重复计价
+        assert_eq!(unit_price("拿铁 (小杯) - 燕麦奶, 燕麦奶"), None);
+    }
+}
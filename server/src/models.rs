@@ -1,12 +1,208 @@
-/**
+/*!
  * 数据模型定义模块
  * 包含系统中所有的数据结构定义和相关实现
  * 主要包括订单、订单项、状态等模型
  */
 
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Sub};
 use std::str::FromStr;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use crate::address::Address;
+use crate::config;
+
+// 订单短码常量
+const SHORT_CODE_LEN: usize = 6; // 短码长度（旧订单迁移生成的短码为8位，两者不会冲突）
+const SHORT_CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ"; // 短码字母表
+const LOOKUP_TOKEN_LEN: usize = 32; // 订单查询令牌长度
+
+/**
+ * 金额类型
+ * 以整数"分"存储，避免浮点运算累积误差
+ * JSON中序列化为以元为单位的数字，反序列化时同时接受数字和十进制字符串
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    /// 允许的最大金额绝对值（分），防止运算溢出
+    pub const MAX_CENTS: i64 = 1_000_000_000_000;
+
+    /**
+     * 从分创建金额
+     */
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    /**
+     * 获取以分为单位的金额
+     */
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    /**
+     * 从以元为单位的浮点数创建金额，四舍五入到分
+     * 
+     * @param yuan - 金额（元）
+     * @return Option<Money> - 数值无效或超出范围时返回None
+     */
+    pub fn from_yuan(yuan: f64) -> Option<Self> {
+        let cents = (yuan * 100.0).round();
+        if cents.is_finite() && cents.abs() <= Self::MAX_CENTS as f64 {
+            Some(Money(cents as i64))
+        } else {
+            None
+        }
+    }
+
+    /**
+     * 转换为以元为单位的浮点数，仅用于展示和序列化
+     */
+    pub fn to_yuan(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0.saturating_sub(other.0))
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<i32> for Money {
+    type Output = Money;
+
+    fn mul(self, quantity: i32) -> Money {
+        Money(self.0.saturating_mul(quantity as i64))
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+/**
+ * 实现Money的字符串表示，固定保留两位小数，如"12.50"
+ */
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+}
+
+/**
+ * 实现从十进制字符串到Money的精确转换
+ * 最多允许两位小数，如"12"、"12.5"、"-0.35"
+ */
+impl FromStr for Money {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid amount: {}", s);
+        let trimmed = s.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let (whole, frac) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() || frac.len() > 2
+            || !whole.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| invalid())?;
+        let frac: i64 = format!("{:0<2}", frac).parse().map_err(|_| invalid())?;
+        let cents = whole.checked_mul(100).and_then(|c| c.checked_add(frac)).ok_or_else(invalid)?;
+        if cents > Self::MAX_CENTS {
+            return Err(invalid());
+        }
+        Ok(Money(if negative { -cents } else { cents }))
+    }
+}
+
+/**
+ * 实现Money的序列化
+ * 输出以元为单位的数字，与原有的浮点金额字段保持兼容
+ */
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_f64(self.to_yuan())
+    }
+}
+
+/**
+ * 实现Money的反序列化
+ * 接受以元为单位的数字或十进制字符串
+ */
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MoneyVisitor;
+
+        impl serde::de::Visitor<'_> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an amount in yuan as a number or decimal string")
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Money, E> {
+                Money::from_yuan(v).ok_or_else(|| E::custom(format!("Invalid amount: {}", v)))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Money, E> {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Money, E> {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Money, E> {
+                Money::from_str(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
 
 /**
  * 订单项模型
@@ -16,7 +212,7 @@ use std::str::FromStr;
 pub struct OrderItem {
     pub name: String,     // 商品名称
     pub quantity: i32,    // 商品数量
-    pub price: f64,       // 商品单价
+    pub price: Money,     // 商品单价
 }
 
 /**
@@ -27,7 +223,7 @@ pub struct OrderItem {
 pub struct OrderItemRequest {
     pub name: String,     // 商品名称
     pub quantity: i32,    // 商品数量
-    pub price: f64,       // 商品单价
+    pub price: Money,     // 商品单价
 }
 
 /**
@@ -43,24 +239,93 @@ impl From<OrderItemRequest> for OrderItem {
     }
 }
 
+/**
+ * 计算商品列表的总金额
+ * 
+ * @param items - 商品列表
+ * @return Money - 各商品单价与数量乘积之和
+ */
+pub fn items_total(items: &[OrderItem]) -> Money {
+    items.iter().map(|item| item.price * item.quantity).sum()
+}
+
 /**
  * 订单查询参数模型
+ * 所有参数均为可选，未指定分页参数时返回第一页
  */
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct OrderQuery {
-    pub status: Option<String>, // 可选的订单状态过滤条件
+    pub status: Option<String>,  // 订单状态过滤条件，多个状态用逗号分隔
+    pub from: Option<String>,    // 创建时间起点（包含），格式为YYYY-MM-DD或YYYY-MM-DD HH:MM:SS
+    pub to: Option<String>,      // 创建时间终点（仅日期时包含当天），格式同上
+    pub q: Option<String>,       // 按客户姓名、电话或订单编号搜索
+    pub courier_id: Option<i64>, // 按指派的配送员过滤
+    pub page: Option<u32>,       // 页码，从1开始
+    pub page_size: Option<u32>,  // 每页数量
 }
 
 /**
  * 地理位置模型
  * 用于存储配送地址的经纬度信息
  */
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     pub lat: f64,  // 纬度
     pub lng: f64,  // 经度
 }
 
+/**
+ * 履约方式枚举
+ * JSON中表示为{"type": "dine_in", "table_number": "A3"}
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fulfilment {
+    #[default]
+    Delivery,                        // 外送
+    Pickup,                          // 到店自取
+    DineIn { table_number: String }, // 堂食
+}
+
+impl Fulfilment {
+    /**
+     * 获取履约方式代码，用于存储和设备消息
+     */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fulfilment::Delivery => "delivery",
+            Fulfilment::Pickup => "pickup",
+            Fulfilment::DineIn { .. } => "dine_in",
+        }
+    }
+
+    /**
+     * 获取堂食桌号
+     */
+    pub fn table_number(&self) -> Option<&str> {
+        match self {
+            Fulfilment::DineIn { table_number } => Some(table_number),
+            _ => None,
+        }
+    }
+
+    /**
+     * 由履约方式代码和桌号还原履约方式
+     *
+     * @param kind - 履约方式代码
+     * @param table_number - 堂食桌号
+     * @return Result<Fulfilment, String> - 履约方式，代码无效时返回错误
+     */
+    pub fn from_parts(kind: &str, table_number: Option<String>) -> Result<Self, String> {
+        match kind {
+            "delivery" => Ok(Fulfilment::Delivery),
+            "pickup" => Ok(Fulfilment::Pickup),
+            "dine_in" => Ok(Fulfilment::DineIn { table_number: table_number.unwrap_or_default() }),
+            _ => Err(format!("Invalid fulfilment: {}", kind)),
+        }
+    }
+}
+
 /**
  * 创建订单请求模型
  * 包含创建新订单所需的所有信息
@@ -69,11 +334,18 @@ pub struct Location {
 pub struct CreateOrderRequest {
     pub customer_name: String,      // 客户姓名
     pub phone_number: String,       // 联系电话
-    pub delivery_address: String,   // 配送地址
-    pub location: Location,         // 地址坐标
+    #[serde(default)]
+    pub fulfilment: Fulfilment,     // 履约方式（未提供时为外送）
+    pub delivery_address: Option<String>, // 配送地址文本（外送订单未提供结构化地址时必填）
+    #[serde(default)]
+    pub address: Option<Address>,   // 结构化配送地址（优先于配送地址文本）
+    pub location: Option<Location>, // 地址坐标（外送订单的地址不在坐标缓存中时必填）
     pub notes: Option<String>,      // 订单备注
-    pub total_amount: f64,         // 订单总金额
+    #[serde(default)]
+    pub delivery_fee: Option<Money>, // 客户确认的外送费（须与服务器报价一致）
+    pub total_amount: Money,       // 订单总金额（商品小计加外送费）
     pub items: Vec<OrderItemRequest>, // 订单商品列表
+    pub requested_time: Option<String>, // 预约送达时间（不带时区时按门店时区处理），为空表示尽快送达
 }
 
 /**
@@ -83,14 +355,17 @@ pub struct CreateOrderRequest {
 pub struct CreateOrderResponse {
     pub success: bool,        // 是否创建成功
     pub order_number: String, // 订单编号
+    pub lookup_token: String, // 订单查询令牌，客户需保存以便自助操作
+    pub order: Order,         // 已保存的订单
 }
 
 /**
  * 订单状态枚举
  * 定义订单的所有可能状态
  */
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OrderStatus {
+    AwaitingPayment, // 待支付（支付成功后才发送给设备）
     Pending,    // 待处理
     Preparing,  // 制作中
     Delivering, // 配送中
@@ -98,6 +373,34 @@ pub enum OrderStatus {
     Cancelled,  // 已取消
 }
 
+impl OrderStatus {
+    /**
+     * 获取管理员或设备可以将订单改为该状态的当前状态
+     * 待支付的订单只能由支付流程改为待处理，管理员和设备只能取消；已完成和已取消的订单不能再改变状态
+     *
+     * @return &[OrderStatus] - 允许的当前状态
+     */
+    pub fn allowed_previous(&self) -> &'static [OrderStatus] {
+        match self {
+            OrderStatus::AwaitingPayment | OrderStatus::Pending => &[],
+            OrderStatus::Preparing => &[OrderStatus::Pending],
+            OrderStatus::Delivering => &[OrderStatus::Pending, OrderStatus::Preparing],
+            OrderStatus::Completed => &[OrderStatus::Pending, OrderStatus::Preparing, OrderStatus::Delivering],
+            OrderStatus::Cancelled => &[OrderStatus::AwaitingPayment, OrderStatus::Pending, OrderStatus::Preparing, OrderStatus::Delivering],
+        }
+    }
+
+    /**
+     * 判断从该状态取消的订单是否自动退款
+     * 配送中的订单取消后由管理员决定是否退款
+     *
+     * @return bool - 是否自动退款
+     */
+    pub fn refunds_on_cancel(&self) -> bool {
+        matches!(self, OrderStatus::AwaitingPayment | OrderStatus::Pending | OrderStatus::Preparing)
+    }
+}
+
 /**
  * 实现从字符串到OrderStatus的转换
  * 允许将状态字符串解析为对应的枚举值
@@ -107,6 +410,7 @@ impl FromStr for OrderStatus {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
+            "awaiting_payment" => Ok(OrderStatus::AwaitingPayment),
             "pending" => Ok(OrderStatus::Pending),
             "preparing" => Ok(OrderStatus::Preparing),
             "delivering" => Ok(OrderStatus::Delivering),
@@ -124,6 +428,7 @@ impl FromStr for OrderStatus {
 impl fmt::Display for OrderStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            OrderStatus::AwaitingPayment => write!(f, "awaiting_payment"),
             OrderStatus::Pending => write!(f, "pending"),
             OrderStatus::Preparing => write!(f, "preparing"),
             OrderStatus::Delivering => write!(f, "delivering"),
@@ -156,28 +461,291 @@ impl<'de> Deserialize<'de> for OrderStatus {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        Ok(OrderStatus::from_str(&s).unwrap())
+        OrderStatus::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/**
+ * 将UTC时间序列化为门店时区的RFC 3339字符串
+ * 如"2024-01-01T16:00:00+08:00"
+ */
+pub fn serialize_store_time<S>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let local = time.with_timezone(&config::store_timezone());
+    serializer.serialize_str(&local.to_rfc3339_opts(SecondsFormat::Secs, false))
+}
+
+/**
+ * 以门店时区序列化可选时间
+ */
+pub fn serialize_optional_store_time<S>(time: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match time {
+        Some(time) => serialize_store_time(time, serializer),
+        None => serializer.serialize_none(),
     }
 }
 
+/**
+ * 生成订单短码
+ * 使用去掉易混淆字符（I、L、O、U）的Crockford Base32字母表
+ * 
+ * @return String - 如"7K3QXM"
+ */
+pub fn generate_short_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..SHORT_CODE_LEN)
+        .map(|_| SHORT_CODE_ALPHABET[rng.gen_range(0..SHORT_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/**
+ * 生成订单查询令牌
+ * 客户凭该令牌对订单进行自助操作（如取消订单）
+ * 
+ * @return String - 随机字母数字串
+ */
+pub fn generate_lookup_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(LOOKUP_TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
 /**
  * 订单模型
  * 包含订单的完整信息
  */
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Order {
-    pub id: i64,                  // 订单ID
+    #[serde(skip)]
+    pub id: i64,                  // 订单ID（仅内部使用，不在API中暴露）
     pub order_number: String,     // 订单编号
+    pub short_code: String,       // 取餐短码，便于口头报号和输入
     pub customer_name: String,    // 客户姓名
     pub phone_number: String,     // 联系电话
-    pub delivery_address: String, // 配送地址
-    pub latitude: f64,           // 配送地址纬度
-    pub longitude: f64,          // 配送地址经度
+    pub fulfilment: Fulfilment,   // 履约方式
+    pub delivery_address: Option<String>, // 配送地址（仅外送订单，规范化后的完整地址）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>, // 结构化配送地址（仅外送订单）
+    pub latitude: Option<f64>,    // 配送地址纬度（仅外送订单）
+    pub longitude: Option<f64>,   // 配送地址经度（仅外送订单）
     pub notes: Option<String>,    // 订单备注
-    pub created_at: String,       // 创建时间
-    pub total_amount: f64,       // 订单总金额
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 创建时间（UTC，输出时转换为门店时区）
+    #[serde(default)]
+    pub delivery_fee: Money,      // 外送费（非外送订单为0）
+    pub total_amount: Money,     // 订单总金额（商品小计加外送费）
     pub status: OrderStatus,      // 订单状态
     pub items: Vec<OrderItem>,    // 订单商品列表
+    #[serde(skip)]
+    pub lookup_token: Option<String>, // 订单查询令牌（仅在创建时返回给客户）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_reason: Option<String>, // 取消原因
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub cancelled_at: Option<DateTime<Utc>>, // 取消时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub requested_time: Option<DateTime<Utc>>, // 预约送达时间
+    #[serde(skip)]
+    pub dispatched_at: Option<DateTime<Utc>>, // 发送给设备的时间（预约订单在此之前暂存）
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub estimated_ready_at: Option<DateTime<Utc>>, // 预计出餐时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub estimated_arrival_at: Option<DateTime<Utc>>, // 预计送达（自取和堂食为可取餐）时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub courier_id: Option<i64>, // 负责配送的配送员ID（仅外送订单）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub courier_location: Option<CourierPosition>, // 配送员最新位置（仅配送中的订单）
+    #[serde(default)]
+    pub refunded_amount: Money,   // 已退款金额
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refund_status: Option<RefundStatus>, // 退款状态（没有退款时为None）
+}
+
+impl Order {
+    /**
+     * 获取配送坐标
+     *
+     * @return Option<Location> - 外送订单的坐标，没有坐标时为None
+     */
+    pub fn location(&self) -> Option<Location> {
+        Some(Location { lat: self.latitude?, lng: self.longitude? })
+    }
+
+    /**
+     * 校验订单查询令牌
+     * 使用定长比较，避免通过响应时间猜测令牌
+     * 
+     * @param token - 客户提交的令牌
+     * @return bool - 令牌是否匹配
+     */
+    pub fn verify_lookup_token(&self, token: &str) -> bool {
+        let Some(expected) = &self.lookup_token else {
+            return false;
+        };
+        let (expected, token) = (expected.as_bytes(), token.as_bytes());
+        expected.len() == token.len()
+            && expected.iter().zip(token).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
+    /**
+     * 判断客户是否还能自助取消订单
+     * 待支付和待处理的订单可以取消；制作中的订单仅在下单后的宽限期内可以取消
+     * 
+     * @param now - 当前时间
+     * @param grace_period - 下单后的取消宽限期
+     * @return bool - 是否允许取消
+     */
+    pub fn can_customer_cancel(&self, now: DateTime<Utc>, grace_period: Duration) -> bool {
+        match self.status {
+            OrderStatus::AwaitingPayment | OrderStatus::Pending => true,
+            OrderStatus::Preparing => now - self.created_at <= grace_period,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Order {
+    /**
+     * 构造测试用订单
+     * 已发送给设备的待处理自取订单，没有商品，创建于2024-05-01T02:00:00Z；
+     * 测试按需用结构体更新语法覆盖字段
+     *
+     * @param order_number - 订单编号（同时作为短码）
+     * @return Order - 测试订单
+     */
+    pub fn sample(order_number: &str) -> Order {
+        let created_at: DateTime<Utc> = "2024-05-01T02:00:00Z".parse().unwrap();
+        Order {
+            id: 0,
+            order_number: order_number.to_string(),
+            short_code: order_number.to_string(),
+            customer_name: "张三".to_string(),
+            phone_number: "13800138000".to_string(),
+            fulfilment: Fulfilment::Pickup,
+            delivery_address: None,
+            address: None,
+            latitude: None,
+            longitude: None,
+            notes: None,
+            created_at,
+            delivery_fee: Money::ZERO,
+            total_amount: Money::ZERO,
+            status: OrderStatus::Pending,
+            items: Vec::new(),
+            lookup_token: None,
+            cancel_reason: None,
+            cancelled_at: None,
+            requested_time: None,
+            dispatched_at: Some(created_at),
+            estimated_ready_at: None,
+            estimated_arrival_at: None,
+            courier_id: None,
+            courier_location: None,
+            refunded_amount: Money::ZERO,
+            refund_status: None,
+        }
+    }
+}
+
+/**
+ * 订单查询参数
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct OrderLookupQuery {
+    pub token: Option<String>, // 订单查询令牌，提供时返回完整订单
+}
+
+/**
+ * 订单状态视图
+ * 未提供查询令牌时返回，不包含客户姓名、电话、地址和配送员位置等个人信息
+ */
+#[derive(Debug, Serialize)]
+pub struct OrderStatusView {
+    pub order_number: String,   // 订单编号
+    pub short_code: String,     // 取餐短码
+    pub fulfilment: Fulfilment, // 履约方式
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 创建时间
+    pub status: OrderStatus,    // 订单状态
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub requested_time: Option<DateTime<Utc>>, // 预约送达时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub estimated_ready_at: Option<DateTime<Utc>>, // 预计出餐时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub estimated_arrival_at: Option<DateTime<Utc>>, // 预计送达（自取和堂食为可取餐）时间
+}
+
+impl From<&Order> for OrderStatusView {
+    fn from(order: &Order) -> Self {
+        OrderStatusView {
+            order_number: order.order_number.clone(),
+            short_code: order.short_code.clone(),
+            fulfilment: order.fulfilment.clone(),
+            created_at: order.created_at,
+            status: order.status.clone(),
+            requested_time: order.requested_time,
+            estimated_ready_at: order.estimated_ready_at,
+            estimated_arrival_at: order.estimated_arrival_at,
+        }
+    }
+}
+
+/**
+ * 取消订单请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelOrderRequest {
+    pub token: String,          // 订单查询令牌
+    pub reason: Option<String>, // 取消原因
+}
+
+/**
+ * 修改订单商品请求模型
+ * 用新的商品列表整体替换原有商品，总金额由服务器重新计算
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AmendOrderRequest {
+    pub items: Vec<OrderItemRequest>, // 新的商品列表
+    pub reason: Option<String>,       // 修改原因
+}
+
+/**
+ * 订单操作响应模型
+ * 用于取消、修改等返回更新后订单的操作
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderResponse {
+    pub success: bool, // 是否操作成功
+    pub order: Order,  // 操作后的订单
+}
+
+/**
+ * 订单预计时间
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderEstimate {
+    pub order_number: String,      // 订单编号
+    pub ready_at: DateTime<Utc>,   // 预计出餐时间
+    pub arrival_at: DateTime<Utc>, // 预计送达时间
+}
+
+/**
+ * 订单审计记录
+ * 记录订单创建后发生的修改，便于事后核对
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderAuditEntry {
+    pub action: String,            // 操作类型，如"items_amended"
+    pub detail: serde_json::Value, // 变更内容
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 操作时间
 }
 
 /**
@@ -185,7 +753,85 @@ pub struct Order {
  */
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrderList {
-    pub orders: Vec<Order>, // 订单列表
+    pub orders: Vec<Order>, // 当前页的订单列表
+    pub total: i64,         // 符合条件的订单总数
+    pub page: u32,          // 当前页码
+    pub page_size: u32,     // 每页数量
+}
+
+/**
+ * 订单地图导出模型（GeoJSON FeatureCollection）
+ * 只包含有配送坐标的订单
+ */
+#[derive(Debug, Serialize)]
+pub struct OrderFeatureCollection {
+    #[serde(rename = "type")]
+    pub kind: &'static str,         // 固定为"FeatureCollection"
+    pub features: Vec<OrderFeature>, // 每个订单一个点要素
+}
+
+/**
+ * 订单点要素（GeoJSON Feature）
+ */
+#[derive(Debug, Serialize)]
+pub struct OrderFeature {
+    #[serde(rename = "type")]
+    pub kind: &'static str,              // 固定为"Feature"
+    pub geometry: PointGeometry,         // 配送坐标
+    pub properties: OrderFeatureProperties, // 订单属性
+}
+
+/**
+ * 点几何（GeoJSON Point），坐标顺序为[经度, 纬度]
+ */
+#[derive(Debug, Serialize)]
+pub struct PointGeometry {
+    #[serde(rename = "type")]
+    pub kind: &'static str, // 固定为"Point"
+    pub coordinates: [f64; 2], // [经度, 纬度]
+}
+
+/**
+ * 订单点要素的属性
+ */
+#[derive(Debug, Serialize)]
+pub struct OrderFeatureProperties {
+    pub order_number: String,  // 订单编号
+    pub short_code: String,    // 取餐短码
+    pub status: OrderStatus,   // 订单状态
+    pub total_amount: Money,   // 订单总金额
+    pub delivery_fee: Money,   // 外送费
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 创建时间（门店时区）
+}
+
+impl OrderFeatureCollection {
+    /**
+     * 由订单列表构建要素集合，跳过没有配送坐标的订单
+     *
+     * @param orders - 订单列表
+     * @return OrderFeatureCollection - 要素集合
+     */
+    pub fn from_orders(orders: &[Order]) -> Self {
+        let features = orders.iter()
+            .filter_map(|order| {
+                let location = order.location()?;
+                Some(OrderFeature {
+                    kind: "Feature",
+                    geometry: PointGeometry { kind: "Point", coordinates: [location.lng, location.lat] },
+                    properties: OrderFeatureProperties {
+                        order_number: order.order_number.clone(),
+                        short_code: order.short_code.clone(),
+                        status: order.status.clone(),
+                        total_amount: order.total_amount,
+                        delivery_fee: order.delivery_fee,
+                        created_at: order.created_at,
+                    },
+                })
+            })
+            .collect();
+        OrderFeatureCollection { kind: "FeatureCollection", features }
+    }
 }
 
 /**
@@ -203,4 +849,422 @@ pub struct UpdateOrderStatusRequest {
 pub struct UpdateOrderStatusResponse {
     pub success: bool,           // 是否更新成功
     pub message: Option<String>, // 可选的响应消息
-} 
\ No newline at end of file
+} 
+
+/**
+ * 外送费报价响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct DeliveryQuoteResponse {
+    pub deliverable: bool, // 是否可以配送
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>, // 门店到配送地址的距离（公里，保留两位小数）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery_fee: Option<Money>, // 外送费
+}
+
+/**
+ * 配送范围检查响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct DeliveryCheckResponse {
+    pub deliverable: bool, // 是否可以配送
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>, // 所在配送区域名称
+}
+
+/**
+ * 配送批次查询参数
+ */
+#[derive(Debug, Deserialize)]
+pub struct BatchQuery {
+    pub max_orders: Option<usize>,    // 每批最多订单数
+    pub max_distance_km: Option<f64>, // 同批订单距首单的最大距离（公里）
+}
+
+/**
+ * 配送批次中的停靠点
+ */
+#[derive(Debug, Serialize)]
+pub struct DeliveryStop {
+    pub order_number: String,             // 订单编号
+    pub short_code: String,               // 取餐短码
+    pub customer_name: String,            // 客户姓名
+    pub phone_number: String,             // 联系电话
+    pub delivery_address: Option<String>, // 配送地址
+    pub location: Location,               // 配送坐标
+    pub leg_km: f64,                      // 距上一停靠点（首站为门店）的直线距离（公里）
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub estimated_ready_at: Option<DateTime<Utc>>, // 预计出餐时间
+}
+
+/**
+ * 建议的配送批次
+ */
+#[derive(Debug, Serialize)]
+pub struct DeliveryBatch {
+    pub stops: Vec<DeliveryStop>, // 按建议配送顺序排列的停靠点
+    pub total_distance_km: f64,   // 路线总直线距离（公里）
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub ready_at: Option<DateTime<Utc>>, // 批次内订单全部出餐的预计时间
+}
+
+/**
+ * 配送员模型
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Courier {
+    pub id: i64,                   // 配送员ID
+    pub name: String,              // 姓名
+    pub phone_number: String,      // 联系电话
+    #[serde(skip)]
+    pub token: String,             // 配送员接口访问令牌（仅在创建时返回）
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 创建时间
+}
+
+/**
+ * 支付状态枚举
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Pending,   // 等待支付结果
+    Succeeded, // 支付成功
+    Failed,    // 支付失败
+}
+
+impl FromStr for PaymentStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(PaymentStatus::Pending),
+            "succeeded" => Ok(PaymentStatus::Succeeded),
+            "failed" => Ok(PaymentStatus::Failed),
+            _ => Err(format!("Invalid payment status: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentStatus::Pending => write!(f, "pending"),
+            PaymentStatus::Succeeded => write!(f, "succeeded"),
+            PaymentStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/**
+ * 支付记录模型
+ * 每次发起支付生成一条记录，支付结果由服务商回调更新
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Payment {
+    pub order_number: String,      // 订单编号
+    pub provider: String,          // 支付服务商
+    pub reference: String,         // 服务商的支付单号
+    pub amount: Money,             // 支付金额
+    pub status: PaymentStatus,     // 支付状态
+    #[serde(default)]
+    pub refunded_amount: Money,    // 已退款和退款中的金额
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 发起时间
+    #[serde(serialize_with = "serialize_store_time")]
+    pub updated_at: DateTime<Utc>, // 最后更新时间
+}
+
+/**
+ * 发起支付请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePaymentRequest {
+    pub token: String, // 订单查询令牌
+}
+
+/**
+ * 发起支付响应模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentResponse {
+    pub success: bool,    // 是否成功
+    pub payment: Payment, // 支付记录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pay_url: Option<String>, // 支付页面地址（模拟支付网关没有支付页面）
+}
+
+/**
+ * 订单退款状态枚举
+ * 根据订单的退款记录汇总，最近一次退款失败时为退款失败
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundStatus {
+    PartiallyRefunded, // 部分退款
+    Refunded,          // 已全额退款
+    Failed,            // 退款失败
+}
+
+impl RefundStatus {
+    /**
+     * 计算新增一笔退款记录后的订单退款状态
+     *
+     * @param result - 本次退款结果
+     * @param refunded - 本次退款后的已退款金额
+     * @param paid - 订单已支付金额
+     * @return RefundStatus - 订单退款状态
+     */
+    pub fn after_refund(result: PaymentStatus, refunded: Money, paid: Money) -> Self {
+        match result {
+            PaymentStatus::Failed => RefundStatus::Failed,
+            _ if refunded >= paid => RefundStatus::Refunded,
+            _ => RefundStatus::PartiallyRefunded,
+        }
+    }
+}
+
+impl FromStr for RefundStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "partially_refunded" => Ok(RefundStatus::PartiallyRefunded),
+            "refunded" => Ok(RefundStatus::Refunded),
+            "failed" => Ok(RefundStatus::Failed),
+            _ => Err(format!("Invalid refund status: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RefundStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RefundStatus::PartiallyRefunded => write!(f, "partially_refunded"),
+            RefundStatus::Refunded => write!(f, "refunded"),
+            RefundStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/**
+ * 退款记录模型
+ * 向服务商申请退款前先保存退款中的记录以预留金额，再按服务商的结果更新，退款失败的记录也会保留
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Refund {
+    pub order_number: String,      // 订单编号
+    pub payment_reference: String, // 原支付单号
+    pub reference: Option<String>, // 服务商的退款单号（退款中或退款失败时为None）
+    pub amount: Money,             // 退款金额
+    pub status: PaymentStatus,     // 退款状态（退款中、成功或失败）
+    pub reason: Option<String>,    // 退款原因
+    #[serde(serialize_with = "serialize_store_time")]
+    pub created_at: DateTime<Utc>, // 申请时间
+}
+
+/**
+ * 退款请求模型
+ * 未指定金额时退还全部可退金额
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefundOrderRequest {
+    pub amount: Option<Money>,  // 退款金额
+    pub reason: Option<String>, // 退款原因
+}
+
+/**
+ * 退款响应模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefundResponse {
+    pub success: bool,        // 是否成功
+    pub refunds: Vec<Refund>, // 本次产生的退款记录
+    pub order: Order,         // 退款后的订单
+}
+
+/**
+ * 模拟支付请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MockPaymentRequest {
+    #[serde(default = "default_true")]
+    pub succeeded: bool, // 模拟支付成功或失败
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/**
+ * 地址坐标查询参数
+ */
+#[derive(Debug, Deserialize)]
+pub struct GeocodeQuery {
+    pub address: String, // 地址文本
+}
+
+/**
+ * 地址坐标查询响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct GeocodeResponse {
+    pub address: Address,   // 解析后的结构化地址
+    pub formatted: String,  // 规范化后的完整地址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>, // 缓存的坐标（未缓存时为空）
+}
+
+/**
+ * 配送员位置
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct CourierPosition {
+    pub lat: f64, // 纬度
+    pub lng: f64, // 经度
+    #[serde(serialize_with = "serialize_store_time")]
+    pub updated_at: DateTime<Utc>, // 上报时间
+}
+
+/**
+ * 配送员上报位置响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct CourierLocationResponse {
+    pub success: bool,         // 是否上报成功
+    pub orders_updated: usize, // 更新了位置的配送中订单数
+}
+
+/**
+ * 创建配送员请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCourierRequest {
+    pub name: String,         // 姓名
+    pub phone_number: String, // 联系电话
+}
+
+/**
+ * 创建配送员响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct CreateCourierResponse {
+    pub success: bool,    // 是否创建成功
+    pub courier: Courier, // 创建的配送员
+    pub token: String,    // 配送员接口访问令牌，仅此一次返回
+}
+
+/**
+ * 指派配送员请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignCourierRequest {
+    pub courier_id: Option<i64>, // 配送员ID（为空时取消指派）
+}
+
+/**
+ * 配送员的订单列表响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct CourierOrderList {
+    pub courier: Courier,   // 当前配送员
+    pub orders: Vec<Order>, // 指派给该配送员的进行中订单（按下单先后排列）
+}
+
+/**
+ * 门店暂停接单信息
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StorePause {
+    pub reason: Option<String>, // 暂停原因
+    #[serde(serialize_with = "serialize_store_time")]
+    pub paused_at: DateTime<Utc>, // 暂停时间
+}
+
+/**
+ * 暂停或恢复接单请求模型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PauseOrderingRequest {
+    pub paused: bool,           // 是否暂停接单
+    pub reason: Option<String>, // 可选的暂停原因
+}
+
+/**
+ * 门店营业状态响应模型
+ */
+#[derive(Debug, Serialize)]
+pub struct StoreStatus {
+    pub accepting_orders: bool, // 当前是否接受立即制作的订单
+    pub open: bool,             // 当前是否在营业时间内
+    pub paused: bool,           // 是否已暂停接单
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause_reason: Option<String>, // 暂停原因
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub paused_at: Option<DateTime<Utc>>, // 暂停时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub closes_at: Option<DateTime<Utc>>, // 营业中时的本次关门时间
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_store_time")]
+    pub opens_at: Option<DateTime<Utc>>, // 休息中时的下次开门时间
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_money_arithmetic_is_exact() {
+        let total = Money::from_str("3.5").unwrap() * 3 + Money::from_str("35").unwrap();
+        assert_eq!(total, Money::from_cents(4550));
+        assert_eq!(total.to_string(), "45.50");
+    }
+
+    #[test]
+    fn test_money_parse() {
+        assert_eq!(Money::from_str("0.05"), Ok(Money::from_cents(5)));
+        assert_eq!(Money::from_str("-1.2"), Ok(Money::from_cents(-120)));
+        assert!(Money::from_str("1.234").is_err());
+        assert!(Money::from_str("1e3").is_err());
+        assert!(Money::from_str(".5").is_err());
+    }
+
+    #[test]
+    fn test_money_json() {
+        let items: Vec<Money> = serde_json::from_str(r#"[12.5, "12.50", 0.1, 3]"#).unwrap();
+        assert_eq!(items, vec![
+            Money::from_cents(1250),
+            Money::from_cents(1250),
+            Money::from_cents(10),
+            Money::from_cents(300),
+        ]);
+        assert_eq!(serde_json::to_string(&Money::from_cents(1250)).unwrap(), "12.5");
+    }
+
+    #[test]
+    fn test_customer_cancel_grace_period() {
+        let mut order: Order = serde_json::from_value(serde_json::json!({
+            "order_number": "A001", "short_code": "A001", "customer_name": "张三",
+            "phone_number": "13800138000", "fulfilment": { "type": "delivery" }, "delivery_address": "测试地址",
+            "latitude": 30.0, "longitude": 120.0, "notes": null,
+            "created_at": "2024-05-01T12:00:00Z", "total_amount": 10, "status": "preparing", "items": []
+        })).unwrap();
+        let grace = Duration::minutes(2);
+        assert!(order.can_customer_cancel(order.created_at + Duration::minutes(1), grace));
+        assert!(!order.can_customer_cancel(order.created_at + Duration::minutes(3), grace));
+
+        order.status = OrderStatus::Pending;
+        assert!(order.can_customer_cancel(order.created_at + Duration::hours(1), grace));
+        order.status = OrderStatus::Delivering;
+        assert!(!order.can_customer_cancel(order.created_at, grace));
+
+        // 没有令牌的旧订单不能自助操作
+        assert!(!order.verify_lookup_token(""));
+        order.lookup_token = Some("secret".to_string());
+        assert!(order.verify_lookup_token("secret"));
+        assert!(!order.verify_lookup_token("secreT"));
+    }
+}
@@ -0,0 +1,384 @@
+/*!
+ * 支付模块
+ * 定义支付服务商接口和开发用的模拟支付网关
+ * 支付结果由服务商通过带签名的回调通知，签名校验通过后才会更新订单
+ * 已支付的订单被取消时自动退还全部可退金额，管理员也可以手动全额或部分退款
+ */
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{generate_lookup_token, Money, Order, OrderAuditEntry, OrderStatus, Payment, PaymentStatus, Refund};
+use crate::repository::OrderRepository;
+use crate::validation::{FieldError, OUT_OF_RANGE};
+
+/// 支付回调签名所在的请求头
+pub const SIGNATURE_HEADER: &str = "X-Payment-Signature";
+
+// 自动退款的原因
+const CANCELLED_REFUND_REASON: &str = "Order cancelled"; // 订单已取消
+const UNUSED_REFUND_REASON: &str = "Order already paid"; // 订单已由其他方式支付
+const MISMATCH_REFUND_REASON: &str = "Payment amount did not match the order"; // 支付金额与订单不符
+
+// 审计记录的操作类型
+const AUDIT_REFUND_SUCCEEDED: &str = "refund_succeeded"; // 退款成功
+const AUDIT_REFUND_FAILED: &str = "refund_failed"; // 退款失败
+
+type HmacSha256 = Hmac<Sha256>;
+
+/**
+ * 服务商创建的支付会话
+ */
+#[derive(Debug, Clone)]
+pub struct PaymentSession {
+    pub reference: String,       // 服务商的支付单号
+    pub pay_url: Option<String>, // 客户完成支付的页面地址
+}
+
+/**
+ * 服务商回调通知的支付结果
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentEvent {
+    pub reference: String,     // 服务商的支付单号
+    pub status: PaymentStatus, // 支付结果（成功或失败）
+    pub amount: Money,         // 实际支付金额
+}
+
+/**
+ * 支付服务商接口
+ * 接入新的服务商时实现该接口，并在配置中按名称选择
+ */
+pub trait PaymentProvider: Send + Sync {
+    /**
+     * 服务商名称，保存在支付记录中
+     */
+    fn name(&self) -> &str;
+
+    /**
+     * 为订单创建支付会话
+     *
+     * @param order - 待支付的订单
+     * @return ApiResult<PaymentSession> - 支付会话
+     */
+    fn create_payment(&self, order: &Order) -> ApiResult<PaymentSession>;
+
+    /**
+     * 校验回调签名并解析支付结果
+     *
+     * @param body - 回调请求体原文
+     * @param signature - 请求头中的签名
+     * @return ApiResult<PaymentEvent> - 支付结果，签名无效时返回InvalidSignature
+     */
+    fn parse_webhook(&self, body: &[u8], signature: &str) -> ApiResult<PaymentEvent>;
+
+    /**
+     * 构造带签名的模拟回调，仅开发用的模拟网关支持
+     *
+     * @param event - 模拟的支付结果
+     * @return Option<(Vec<u8>, String)> - 回调请求体和签名，不支持模拟时为None
+     */
+    fn simulate_webhook(&self, _event: &PaymentEvent) -> Option<(Vec<u8>, String)> {
+        None
+    }
+
+    /**
+     * 向服务商申请退款
+     *
+     * @param payment - 原支付记录
+     * @param amount - 退款金额
+     * @return ApiResult<String> - 服务商的退款单号，服务商拒绝退款时返回错误
+     */
+    fn refund(&self, payment: &Payment, amount: Money) -> ApiResult<String>;
+}
+
+/**
+ * 计算回调请求体的签名
+ * 使用HMAC-SHA256，以小写十六进制表示
+ *
+ * @param secret - 回调签名密钥
+ * @param body - 请求体
+ * @return String - 签名
+ */
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/**
+ * 校验回调请求体的签名
+ * 使用定长比较，避免通过响应时间猜测签名
+ *
+ * @param secret - 回调签名密钥
+ * @param body - 请求体
+ * @param signature - 十六进制签名
+ * @return bool - 签名是否有效
+ */
+pub fn verify(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/**
+ * 模拟支付网关
+ * 用于本地开发和测试，不产生真实扣款
+ * 支付结果通过模拟支付接口触发，与真实服务商一样经过签名回调处理
+ */
+pub struct MockProvider {
+    secret: Vec<u8>, // 回调签名密钥
+}
+
+impl MockProvider {
+    /**
+     * 创建模拟支付网关
+     *
+     * @param secret - 回调签名密钥
+     */
+    pub fn new(secret: &str) -> Self {
+        MockProvider { secret: secret.as_bytes().to_vec() }
+    }
+}
+
+impl PaymentProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn create_payment(&self, _order: &Order) -> ApiResult<PaymentSession> {
+        Ok(PaymentSession {
+            reference: format!("mock_{}", generate_lookup_token()),
+            pay_url: None,
+        })
+    }
+
+    fn parse_webhook(&self, body: &[u8], signature: &str) -> ApiResult<PaymentEvent> {
+        if !verify(&self.secret, body, signature) {
+            return Err(ApiError::InvalidSignature);
+        }
+        serde_json::from_slice(body).map_err(|e| ApiError::BadRequest(format!("Invalid payment event: {}", e)))
+    }
+
+    fn simulate_webhook(&self, event: &PaymentEvent) -> Option<(Vec<u8>, String)> {
+        let body = serde_json::to_vec(event).ok()?;
+        let signature = sign(&self.secret, &body);
+        Some((body, signature))
+    }
+
+    fn refund(&self, _payment: &Payment, _amount: Money) -> ApiResult<String> {
+        Ok(format!("mock_refund_{}", generate_lookup_token()))
+    }
+}
+
+/**
+ * 退还一笔支付成功的记录中的金额
+ * 先预留退款金额再向服务商申请，并发的退款超出可退金额时在申请前返回Conflict
+ * 服务商拒绝退款时保存失败的退款记录，由管理员稍后重试
+ *
+ * @param repo - 订单仓储
+ * @param provider - 支付服务商
+ * @param payment - 原支付记录
+ * @param amount - 退款金额
+ * @param reason - 退款原因
+ * @param now - 当前时间
+ * @return ApiResult<Refund> - 退款记录
+ */
+pub fn refund_payment(
+    repo: &dyn OrderRepository,
+    provider: &dyn PaymentProvider,
+    payment: &Payment,
+    amount: Money,
+    reason: Option<&str>,
+    now: DateTime<Utc>,
+) -> ApiResult<Refund> {
+    if payment.provider != provider.name() {
+        return Err(ApiError::InvalidOrderState(format!("Payment {} was made with provider {}", payment.reference, payment.provider)));
+    }
+    let mut refund = Refund {
+        order_number: payment.order_number.clone(),
+        payment_reference: payment.reference.clone(),
+        reference: None,
+        amount,
+        status: PaymentStatus::Pending,
+        reason: reason.map(str::to_string),
+        created_at: now,
+    };
+    let refund_id = repo.reserve_refund(&refund)?;
+
+    let error = match provider.refund(payment, amount) {
+        Ok(reference) => {
+            refund.reference = Some(reference);
+            refund.status = PaymentStatus::Succeeded;
+            None
+        }
+        Err(e) => {
+            log::error!("Refund of {} for payment {} failed: {}", amount, payment.reference, e);
+            refund.status = PaymentStatus::Failed;
+            Some(e.to_string())
+        }
+    };
+    let entry = OrderAuditEntry {
+        action: if refund.status == PaymentStatus::Succeeded { AUDIT_REFUND_SUCCEEDED } else { AUDIT_REFUND_FAILED }.to_string(),
+        detail: serde_json::json!({
+            "provider": provider.name(),
+            "payment_reference": refund.payment_reference,
+            "reference": refund.reference,
+            "amount": amount,
+            "reason": refund.reason,
+            "error": error,
+        }),
+        created_at: now,
+    };
+    repo.finish_refund(refund_id, refund.status, refund.reference.as_deref(), &entry)?;
+    Ok(refund)
+}
+
+/**
+ * 退还订单已支付的金额
+ * 按支付顺序从各笔支付成功的记录中扣减，未指定金额时退还全部可退金额
+ * 某笔退款失败时停止退款，由管理员稍后重试
+ *
+ * @param repo - 订单仓储
+ * @param provider - 支付服务商
+ * @param order_number - 订单编号
+ * @param amount - 退款金额
+ * @param reason - 退款原因
+ * @param now - 当前时间
+ * @return ApiResult<Vec<Refund>> - 本次产生的退款记录，没有可退金额时为空
+ */
+pub fn refund_order(
+    repo: &dyn OrderRepository,
+    provider: &dyn PaymentProvider,
+    order_number: &str,
+    amount: Option<Money>,
+    reason: Option<&str>,
+    now: DateTime<Utc>,
+) -> ApiResult<Vec<Refund>> {
+    let payments: Vec<Payment> = repo.get_order_payments(order_number)?
+        .into_iter()
+        .filter(|p| p.status == PaymentStatus::Succeeded && p.refunded_amount < p.amount)
+        .collect();
+    let refundable: Money = payments.iter().map(|p| p.amount - p.refunded_amount).sum();
+    let mut remaining = amount.unwrap_or(refundable);
+    if remaining > refundable {
+        return Err(ApiError::Validation(vec![FieldError::new("amount", OUT_OF_RANGE)]));
+    }
+
+    let mut refunds = Vec::new();
+    for payment in payments {
+        if remaining <= Money::ZERO {
+            break;
+        }
+        let part = remaining.min(payment.amount - payment.refunded_amount);
+        let refund = refund_payment(repo, provider, &payment, part, reason, now)?;
+        let failed = refund.status == PaymentStatus::Failed;
+        refunds.push(refund);
+        if failed {
+            break;
+        }
+        remaining = remaining - part;
+    }
+    Ok(refunds)
+}
+
+/**
+ * 订单取消后自动退还全部可退金额
+ * 订单未取消或没有可退金额时不做处理；退款出错时只记录日志，不影响取消操作
+ *
+ * @param repo - 订单仓储
+ * @param provider - 支付服务商（未启用支付时为None）
+ * @param reference - 订单编号或短码
+ * @param now - 当前时间
+ */
+pub fn refund_cancelled_order(repo: &dyn OrderRepository, provider: Option<&dyn PaymentProvider>, reference: &str, now: DateTime<Utc>) {
+    let Some(provider) = provider else {
+        return;
+    };
+    let result = repo.get_order_by_reference(reference).and_then(|order| match order {
+        Some(order) if order.status == OrderStatus::Cancelled => {
+            refund_order(repo, provider, &order.order_number, None, Some(CANCELLED_REFUND_REASON), now)
+        }
+        _ => Ok(Vec::new()),
+    });
+    log_automatic_refunds(result, reference);
+}
+
+/**
+ * 退还支付成功但未用于订单的支付
+ * 订单已取消时退还全部可退金额；订单仍在等待支付时本次支付金额与订单不符，
+ * 否则订单已由其他支付完成，两种情况都全额退还本次支付；
+ * 退款出错时只记录日志，服务商拒绝的退款保存为失败的退款记录，由管理员重试
+ *
+ * @param repo - 订单仓储
+ * @param provider - 支付服务商（未启用支付时为None）
+ * @param reference - 服务商的支付单号
+ * @param now - 当前时间
+ */
+pub fn refund_unused_payment(repo: &dyn OrderRepository, provider: Option<&dyn PaymentProvider>, reference: &str, now: DateTime<Utc>) {
+    let Some(provider) = provider else {
+        return;
+    };
+    let result = (|| {
+        let payment = repo.get_payment(reference)?.ok_or(ApiError::PaymentNotFound)?;
+        let order = repo.get_order_by_reference(&payment.order_number)?.ok_or(ApiError::OrderNotFound)?;
+        if order.status == OrderStatus::Cancelled {
+            return refund_order(repo, provider, &order.order_number, None, Some(CANCELLED_REFUND_REASON), now);
+        }
+        if payment.status != PaymentStatus::Succeeded || payment.refunded_amount >= payment.amount {
+            return Ok(Vec::new());
+        }
+        let amount = payment.amount - payment.refunded_amount;
+        let reason = if order.status == OrderStatus::AwaitingPayment { MISMATCH_REFUND_REASON } else { UNUSED_REFUND_REASON };
+        Ok(vec![refund_payment(repo, provider, &payment, amount, Some(reason), now)?])
+    })();
+    log_automatic_refunds(result, reference);
+}
+
+/**
+ * 记录自动退款的结果
+ *
+ * @param result - 退款结果
+ * @param reference - 订单编号、短码或支付单号
+ */
+fn log_automatic_refunds(result: ApiResult<Vec<Refund>>, reference: &str) {
+    match result {
+        Ok(refunds) => {
+            for refund in refunds {
+                log::info!("Automatic refund of {} for order {} is {}", refund.amount, refund.order_number, refund.status);
+            }
+        }
+        Err(e) => log::error!("Failed to issue automatic refund for {}: {}", reference, e),
+    }
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_webhook_signature() {
+        let provider = MockProvider::new("secret");
+        let event = PaymentEvent {
+            reference: "mock_1".to_string(),
+            status: PaymentStatus::Succeeded,
+            amount: Money::from_cents(4550),
+        };
+        let (body, signature) = provider.simulate_webhook(&event).unwrap();
+        assert_eq!(provider.parse_webhook(&body, &signature).unwrap(), event);
+
+        // 请求体被篡改或密钥不同时签名无效
+        let tampered = String::from_utf8(body.clone()).unwrap().replace("45.5", "0.01");
+        assert!(matches!(provider.parse_webhook(tampered.as_bytes(), &signature), Err(ApiError::InvalidSignature)));
+        assert!(matches!(MockProvider::new("other").parse_webhook(&body, &signature), Err(ApiError::InvalidSignature)));
+        assert!(matches!(provider.parse_webhook(&body, "not hex"), Err(ApiError::InvalidSignature)));
+    }
+}
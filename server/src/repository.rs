@@ -0,0 +1,963 @@
+/*!
+ * 订单仓储模块
+ * 定义订单数据访问的抽象接口及其实现
+ * 处理器通过该接口访问数据，便于替换存储实现和编写测试
+ */
+
+use actix_web::web;
+use rusqlite::Connection;
+use std::sync::Arc;
+use crate::db::{self, DbPool, OrderFilter};
+use crate::error::{ApiError, ApiResult};
+use chrono::{DateTime, Utc};
+use crate::models::{Courier, Location, Money, Order, OrderAuditEntry, OrderEstimate, OrderItem, OrderStatus, Payment, PaymentStatus, Refund, StorePause};
+use crate::delivery::{DeliveryFees, DeliveryZones};
+use crate::eta::Estimator;
+use crate::payment::PaymentProvider;
+use crate::schedule::StoreHours;
+
+/**
+ * 订单仓储接口
+ * 所有方法均为同步调用，异步处理器应通过AppState::run执行
+ */
+pub trait OrderRepository: Send + Sync {
+    /**
+     * 保存新订单
+     *
+     * @param order - 订单信息（id由存储生成）
+     * @return ApiResult<Order> - 创建的订单
+     */
+    fn create_order(&self, order: &Order) -> ApiResult<Order>;
+
+    /**
+     * 根据订单编号或短码查询订单
+     *
+     * @param reference - 订单编号或短码（短码不区分大小写）
+     * @return ApiResult<Option<Order>> - 查询结果
+     */
+    fn get_order_by_reference(&self, reference: &str) -> ApiResult<Option<Order>>;
+
+    /**
+     * 按过滤条件分页查询订单，按创建时间倒序排列
+     *
+     * @param filter - 过滤和分页条件
+     * @return ApiResult<Vec<Order>> - 订单列表
+     */
+    fn get_orders(&self, filter: &OrderFilter) -> ApiResult<Vec<Order>>;
+
+    /**
+     * 统计符合过滤条件的订单总数
+     *
+     * @param filter - 过滤条件（忽略分页参数）
+     * @return ApiResult<i64> - 订单总数
+     */
+    fn count_orders(&self, filter: &OrderFilter) -> ApiResult<i64>;
+
+    /**
+     * 根据订单ID更新订单状态
+     * 仅用于兼容旧版按ID更新状态的接口；当前状态不允许改为新状态时返回InvalidOrderState
+     *
+     * @param order_id - 订单ID
+     * @param status - 新状态
+     * @return ApiResult<Option<(String, OrderStatus)>> - 更新成功时返回订单编号和更新前的状态，订单不存在时为None
+     */
+    fn update_order_status(&self, order_id: i64, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>>;
+
+    /**
+     * 根据订单编号或短码更新订单状态
+     * 当前状态不允许改为新状态时返回InvalidOrderState
+     *
+     * @param reference - 订单编号或短码（短码不区分大小写）
+     * @param status - 新状态
+     * @return ApiResult<Option<(String, OrderStatus)>> - 更新成功时返回订单编号和更新前的状态，订单不存在时为None
+     */
+    fn update_order_status_by_reference(&self, reference: &str, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>>;
+
+    /**
+     * 取消订单并记录取消原因
+     * 仅当订单仍处于expected_status时才会取消
+     *
+     * @param order_number - 订单编号
+     * @param expected_status - 订单当前应处的状态
+     * @param reason - 取消原因
+     * @param cancelled_at - 取消时间
+     * @return ApiResult<bool> - 是否取消成功
+     */
+    fn cancel_order(
+        &self,
+        order_number: &str,
+        expected_status: &OrderStatus,
+        reason: Option<&str>,
+        cancelled_at: DateTime<Utc>,
+    ) -> ApiResult<bool>;
+
+    /**
+     * 替换待处理订单的商品并记录审计
+     *
+     * @param order_number - 订单编号
+     * @param items - 新的商品列表
+     * @param total_amount - 重新计算的订单总金额
+     * @param entry - 审计记录
+     * @return ApiResult<Option<Order>> - 修改后的订单，订单已不处于待处理状态时返回None
+     */
+    fn amend_order_items(
+        &self,
+        order_number: &str,
+        items: &[OrderItem],
+        total_amount: Money,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<Option<Order>>;
+
+    /**
+     * 查询订单的审计记录，按时间先后排列
+     *
+     * @param order_number - 订单编号
+     * @return ApiResult<Vec<OrderAuditEntry>> - 审计记录列表
+     */
+    fn get_order_audit(&self, order_number: &str) -> ApiResult<Vec<OrderAuditEntry>>;
+
+    /**
+     * 取出到期的暂存预约订单并标记为已发送
+     *
+     * @param due_before - 预约送达时间不晚于该时间的订单视为到期
+     * @param dispatched_at - 发送时间
+     * @return ApiResult<Vec<Order>> - 需要发送给设备的订单
+     */
+    fn release_held_orders(&self, due_before: DateTime<Utc>, dispatched_at: DateTime<Utc>) -> ApiResult<Vec<Order>>;
+
+    /**
+     * 保存订单预计时间
+     *
+     * @param estimates - 订单预计时间列表
+     * @return ApiResult<()> - 操作结果
+     */
+    fn update_order_estimates(&self, estimates: &[OrderEstimate]) -> ApiResult<()>;
+
+    /**
+     * 查询门店是否暂停接单
+     *
+     * @return ApiResult<Option<StorePause>> - 暂停信息，正常接单时返回None
+     */
+    fn get_store_pause(&self) -> ApiResult<Option<StorePause>>;
+
+    /**
+     * 设置或解除门店暂停接单
+     *
+     * @param pause - 暂停信息，None表示恢复接单
+     * @return ApiResult<()> - 操作结果
+     */
+    fn set_store_pause(&self, pause: Option<&StorePause>) -> ApiResult<()>;
+
+    /**
+     * 创建配送员
+     *
+     * @param courier - 配送员信息（ID由仓储生成）
+     * @return ApiResult<Courier> - 创建的配送员
+     */
+    fn create_courier(&self, courier: &Courier) -> ApiResult<Courier>;
+
+    /**
+     * 获取所有配送员
+     *
+     * @return ApiResult<Vec<Courier>> - 按创建顺序排列的配送员
+     */
+    fn get_couriers(&self) -> ApiResult<Vec<Courier>>;
+
+    /**
+     * 根据ID查询配送员
+     *
+     * @param courier_id - 配送员ID
+     * @return ApiResult<Option<Courier>> - 查询结果
+     */
+    fn get_courier(&self, courier_id: i64) -> ApiResult<Option<Courier>>;
+
+    /**
+     * 根据访问令牌查询配送员
+     *
+     * @param token - 配送员接口访问令牌
+     * @return ApiResult<Option<Courier>> - 查询结果
+     */
+    fn get_courier_by_token(&self, token: &str) -> ApiResult<Option<Courier>>;
+
+    /**
+     * 指派或取消指派订单的配送员，并写入审计记录
+     *
+     * @param order_number - 订单编号
+     * @param courier_id - 配送员ID（None表示取消指派）
+     * @param entry - 审计记录
+     * @return ApiResult<Option<Order>> - 指派后的订单；订单已结束时为None
+     */
+    fn assign_courier(&self, order_number: &str, courier_id: Option<i64>, entry: &OrderAuditEntry) -> ApiResult<Option<Order>>;
+
+    /**
+     * 按配送员上报的事件更新订单状态，并写入审计记录
+     *
+     * @param order_number - 订单编号
+     * @param courier_id - 上报事件的配送员ID
+     * @param from - 允许的当前状态
+     * @param to - 新状态
+     * @param entry - 审计记录
+     * @return ApiResult<bool> - 是否更新成功（订单未指派给该配送员或状态不符时为false）
+     */
+    fn update_courier_order_status(
+        &self,
+        order_number: &str,
+        courier_id: i64,
+        from: &[OrderStatus],
+        to: &OrderStatus,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<bool>;
+
+    /**
+     * 更新配送员所有配送中订单的最新位置
+     * 订单离开配送中状态时位置被清除
+     *
+     * @param courier_id - 配送员ID
+     * @param location - 配送员位置
+     * @param updated_at - 上报时间
+     * @return ApiResult<usize> - 更新的订单数
+     */
+    fn update_courier_location(&self, courier_id: i64, location: Location, updated_at: DateTime<Utc>) -> ApiResult<usize>;
+
+    /**
+     * 从地址坐标缓存中查询坐标
+     *
+     * @param address_key - 规范化地址键
+     * @return ApiResult<Option<Location>> - 缓存的坐标
+     */
+    fn lookup_geocode(&self, address_key: &str) -> ApiResult<Option<Location>>;
+
+    /**
+     * 保存地址坐标到缓存，已缓存的地址保持原有坐标
+     *
+     * @param address_key - 规范化地址键
+     * @param location - 坐标
+     * @param updated_at - 更新时间
+     * @return ApiResult<()> - 操作结果
+     */
+    fn save_geocode(&self, address_key: &str, location: Location, updated_at: DateTime<Utc>) -> ApiResult<()>;
+
+    /**
+     * 保存新的支付记录
+     *
+     * @param payment - 支付记录
+     * @return ApiResult<()> - 操作结果，订单不存在时返回OrderNotFound，已有等待结果的支付时返回Conflict
+     */
+    fn create_payment(&self, payment: &Payment) -> ApiResult<()>;
+
+    /**
+     * 根据服务商的支付单号查询支付记录
+     *
+     * @param reference - 服务商的支付单号
+     * @return ApiResult<Option<Payment>> - 查询结果
+     */
+    fn get_payment(&self, reference: &str) -> ApiResult<Option<Payment>>;
+
+    /**
+     * 记录支付结果并写入审计，支付成功且金额与订单总金额一致时将待支付的订单转为待处理
+     * 只处理仍在等待结果的支付记录
+     *
+     * @param reference - 服务商的支付单号
+     * @param status - 支付结果
+     * @param amount - 支付金额（支付成功时为服务商实际收取的金额）
+     * @param updated_at - 处理时间
+     * @param dispatched_at - 订单发送给设备的时间（预约订单未到期时为None）
+     * @param entry - 审计记录
+     * @return ApiResult<Option<Order>> - 订单因本次支付成功转为待处理时返回订单，否则为None
+     */
+    fn complete_payment(
+        &self,
+        reference: &str,
+        status: PaymentStatus,
+        amount: Money,
+        updated_at: DateTime<Utc>,
+        dispatched_at: Option<DateTime<Utc>>,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<Option<Order>>;
+
+    /**
+     * 查询订单的所有支付记录
+     *
+     * @param order_number - 订单编号
+     * @return ApiResult<Vec<Payment>> - 按发起顺序排列的支付记录
+     */
+    fn get_order_payments(&self, order_number: &str) -> ApiResult<Vec<Payment>>;
+
+    /**
+     * 预留退款金额，保存一条退款中的记录
+     * 退款中和成功的金额合计不能超过原支付金额
+     *
+     * @param refund - 退款记录（状态为退款中）
+     * @return ApiResult<i64> - 退款记录ID，原支付记录不存在或未支付成功时返回PaymentNotFound，
+     *                          超出剩余可退金额时返回Conflict
+     */
+    fn reserve_refund(&self, refund: &Refund) -> ApiResult<i64>;
+
+    /**
+     * 记录服务商的退款结果并写入审计，同时更新订单的已退款金额和退款状态
+     *
+     * @param refund_id - 退款记录ID
+     * @param status - 退款结果（成功或失败）
+     * @param reference - 服务商的退款单号（退款失败时为None）
+     * @param entry - 审计记录
+     * @return ApiResult<()> - 操作结果，退款记录不存在或已有结果时返回Conflict
+     */
+    fn finish_refund(&self, refund_id: i64, status: PaymentStatus, reference: Option<&str>, entry: &OrderAuditEntry) -> ApiResult<()>;
+
+    /**
+     * 查询订单的所有退款记录
+     *
+     * @param order_number - 订单编号
+     * @return ApiResult<Vec<Refund>> - 按申请顺序排列的退款记录
+     */
+    fn get_order_refunds(&self, order_number: &str) -> ApiResult<Vec<Refund>>;
+}
+
+/**
+ * 基于SQLite连接池的订单仓储
+ */
+pub struct SqliteOrderRepository {
+    pool: DbPool, // 数据库连接池
+}
+
+impl SqliteOrderRepository {
+    pub fn new(pool: DbPool) -> Self {
+        SqliteOrderRepository { pool }
+    }
+
+    /**
+     * 从连接池取出连接并执行数据库操作
+     */
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Connection) -> ApiResult<T>) -> ApiResult<T> {
+        let mut conn = self.pool.get()?;
+        f(&mut conn)
+    }
+}
+
+impl OrderRepository for SqliteOrderRepository {
+    fn create_order(&self, order: &Order) -> ApiResult<Order> {
+        self.with_conn(|conn| db::create_order(conn, order))
+    }
+
+    fn get_order_by_reference(&self, reference: &str) -> ApiResult<Option<Order>> {
+        self.with_conn(|conn| db::get_order_by_reference(conn, reference))
+    }
+
+    fn get_orders(&self, filter: &OrderFilter) -> ApiResult<Vec<Order>> {
+        self.with_conn(|conn| db::get_orders(conn, filter))
+    }
+
+    fn count_orders(&self, filter: &OrderFilter) -> ApiResult<i64> {
+        self.with_conn(|conn| db::count_orders(conn, filter))
+    }
+
+    fn update_order_status(&self, order_id: i64, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>> {
+        self.with_conn(|conn| db::update_order_status(conn, order_id, status))
+    }
+
+    fn update_order_status_by_reference(&self, reference: &str, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>> {
+        self.with_conn(|conn| db::update_order_status_by_reference(conn, reference, status))
+    }
+
+    fn cancel_order(
+        &self,
+        order_number: &str,
+        expected_status: &OrderStatus,
+        reason: Option<&str>,
+        cancelled_at: DateTime<Utc>,
+    ) -> ApiResult<bool> {
+        self.with_conn(|conn| db::cancel_order(conn, order_number, &expected_status.to_string(), reason, &cancelled_at))
+    }
+
+    fn amend_order_items(
+        &self,
+        order_number: &str,
+        items: &[OrderItem],
+        total_amount: Money,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<Option<Order>> {
+        self.with_conn(|conn| db::amend_order_items(conn, order_number, items, total_amount, entry))
+    }
+
+    fn get_order_audit(&self, order_number: &str) -> ApiResult<Vec<OrderAuditEntry>> {
+        self.with_conn(|conn| db::get_order_audit(conn, order_number))
+    }
+
+    fn release_held_orders(&self, due_before: DateTime<Utc>, dispatched_at: DateTime<Utc>) -> ApiResult<Vec<Order>> {
+        self.with_conn(|conn| db::release_held_orders(conn, &due_before, &dispatched_at))
+    }
+
+    fn update_order_estimates(&self, estimates: &[OrderEstimate]) -> ApiResult<()> {
+        self.with_conn(|conn| db::update_order_estimates(conn, estimates))
+    }
+
+    fn get_store_pause(&self) -> ApiResult<Option<StorePause>> {
+        self.with_conn(|conn| db::get_store_pause(conn))
+    }
+
+    fn set_store_pause(&self, pause: Option<&StorePause>) -> ApiResult<()> {
+        self.with_conn(|conn| db::set_store_pause(conn, pause))
+    }
+
+    fn create_courier(&self, courier: &Courier) -> ApiResult<Courier> {
+        self.with_conn(|conn| db::create_courier(conn, courier))
+    }
+
+    fn get_couriers(&self) -> ApiResult<Vec<Courier>> {
+        self.with_conn(|conn| db::get_couriers(conn))
+    }
+
+    fn get_courier(&self, courier_id: i64) -> ApiResult<Option<Courier>> {
+        self.with_conn(|conn| db::get_courier(conn, courier_id))
+    }
+
+    fn get_courier_by_token(&self, token: &str) -> ApiResult<Option<Courier>> {
+        self.with_conn(|conn| db::get_courier_by_token(conn, token))
+    }
+
+    fn assign_courier(&self, order_number: &str, courier_id: Option<i64>, entry: &OrderAuditEntry) -> ApiResult<Option<Order>> {
+        self.with_conn(|conn| db::assign_courier(conn, order_number, courier_id, entry))
+    }
+
+    fn update_courier_order_status(
+        &self,
+        order_number: &str,
+        courier_id: i64,
+        from: &[OrderStatus],
+        to: &OrderStatus,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<bool> {
+        self.with_conn(|conn| db::update_courier_order_status(conn, order_number, courier_id, from, to, entry))
+    }
+
+    fn update_courier_location(&self, courier_id: i64, location: Location, updated_at: DateTime<Utc>) -> ApiResult<usize> {
+        self.with_conn(|conn| db::update_courier_location(conn, courier_id, location, &updated_at))
+    }
+
+    fn lookup_geocode(&self, address_key: &str) -> ApiResult<Option<Location>> {
+        self.with_conn(|conn| db::lookup_geocode(conn, address_key))
+    }
+
+    fn save_geocode(&self, address_key: &str, location: Location, updated_at: DateTime<Utc>) -> ApiResult<()> {
+        self.with_conn(|conn| db::save_geocode(conn, address_key, location, &updated_at))
+    }
+
+    fn create_payment(&self, payment: &Payment) -> ApiResult<()> {
+        self.with_conn(|conn| db::create_payment(conn, payment))
+    }
+
+    fn get_payment(&self, reference: &str) -> ApiResult<Option<Payment>> {
+        self.with_conn(|conn| db::get_payment(conn, reference))
+    }
+
+    fn complete_payment(
+        &self,
+        reference: &str,
+        status: PaymentStatus,
+        amount: Money,
+        updated_at: DateTime<Utc>,
+        dispatched_at: Option<DateTime<Utc>>,
+        entry: &OrderAuditEntry,
+    ) -> ApiResult<Option<Order>> {
+        self.with_conn(|conn| db::complete_payment(conn, reference, status, amount, &updated_at, dispatched_at.as_ref(), entry))
+    }
+
+    fn get_order_payments(&self, order_number: &str) -> ApiResult<Vec<Payment>> {
+        self.with_conn(|conn| db::get_order_payments(conn, order_number))
+    }
+
+    fn reserve_refund(&self, refund: &Refund) -> ApiResult<i64> {
+        self.with_conn(|conn| db::reserve_refund(conn, refund))
+    }
+
+    fn finish_refund(&self, refund_id: i64, status: PaymentStatus, reference: Option<&str>, entry: &OrderAuditEntry) -> ApiResult<()> {
+        self.with_conn(|conn| db::finish_refund(conn, refund_id, status, reference, entry))
+    }
+
+    fn get_order_refunds(&self, order_number: &str) -> ApiResult<Vec<Refund>> {
+        self.with_conn(|conn| db::get_order_refunds(conn, order_number))
+    }
+}
+
+/**
+ * 应用状态结构体
+ * 包含处理器共享的订单仓储、门店营业时间表和配送规则
+ */
+#[derive(Clone)]
+pub struct AppState {
+    pub repo: Arc<dyn OrderRepository>, // 订单仓储
+    pub hours: Arc<StoreHours>,         // 门店营业时间表
+    pub zones: Option<Arc<DeliveryZones>>, // 配送区域（None表示不限制配送范围）
+    pub fees: Option<Arc<DeliveryFees>>,   // 外送费规则（None表示不收外送费）
+    pub estimator: Arc<Estimator>,      // 订单预计时间估算器
+    pub payments: Option<Arc<dyn PaymentProvider>>, // 支付服务商（None表示订单无需支付）
+}
+
+impl AppState {
+    /**
+     * 创建应用状态
+     *
+     * @param repo - 订单仓储实现
+     * @param hours - 门店营业时间表
+     */
+    pub fn new(repo: Arc<dyn OrderRepository>, hours: StoreHours) -> Self {
+        AppState { repo, hours: Arc::new(hours), zones: None, fees: None, estimator: Arc::default(), payments: None }
+    }
+
+    /**
+     * 设置配送区域，外送订单的坐标必须在区域内
+     *
+     * @param zones - 配送区域
+     */
+    pub fn with_delivery_zones(mut self, zones: DeliveryZones) -> Self {
+        self.zones = Some(Arc::new(zones));
+        self
+    }
+
+    /**
+     * 设置外送费规则，超过最远收费档位的地址不配送
+     *
+     * @param fees - 外送费规则
+     */
+    pub fn with_delivery_fees(mut self, fees: DeliveryFees) -> Self {
+        self.fees = Some(Arc::new(fees));
+        self
+    }
+
+    /**
+     * 设置订单预计时间估算器
+     *
+     * @param estimator - 估算器
+     */
+    pub fn with_estimator(mut self, estimator: Estimator) -> Self {
+        self.estimator = Arc::new(estimator);
+        self
+    }
+
+    /**
+     * 设置支付服务商，新订单支付成功后才发送给设备
+     *
+     * @param provider - 支付服务商
+     */
+    pub fn with_payment_provider(mut self, provider: Arc<dyn PaymentProvider>) -> Self {
+        self.payments = Some(provider);
+        self
+    }
+
+    /**
+     * 在阻塞线程池中执行仓储操作
+     * 避免数据库查询阻塞异步执行器线程
+     *
+     * @param f - 使用订单仓储的操作
+     * @return ApiResult<T> - 操作结果
+     */
+    pub async fn run<F, T>(&self, f: F) -> ApiResult<T>
+    where
+        F: FnOnce(&dyn OrderRepository) -> ApiResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repo = Arc::clone(&self.repo);
+        web::block(move || f(repo.as_ref()))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+    }
+}
+
+/**
+ * 基于内存的订单仓储
+ * 仅用于测试，不依赖数据库文件
+ */
+#[cfg(test)]
+pub mod memory {
+    use super::*;
+    use chrono::SubsecRound;
+    use crate::models::{CourierPosition, RefundStatus};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct InMemoryOrderRepository {
+        orders: Mutex<Vec<Order>>, // 按创建顺序保存的订单
+        audit: Mutex<Vec<(String, OrderAuditEntry)>>, // 审计记录（订单编号，记录）
+        pause: Mutex<Option<StorePause>>, // 暂停接单信息
+        couriers: Mutex<Vec<Courier>>,    // 按创建顺序保存的配送员
+        geocodes: Mutex<HashMap<String, Location>>, // 地址坐标缓存
+        payments: Mutex<Vec<Payment>>,    // 按创建顺序保存的支付记录
+        refunds: Mutex<Vec<Refund>>,      // 按创建顺序保存的退款记录
+    }
+
+    impl InMemoryOrderRepository {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /**
+         * 判断订单是否符合过滤条件
+         */
+        fn matches(order: &Order, filter: &OrderFilter) -> bool {
+            if !filter.statuses.is_empty()
+                && !filter.statuses.contains(&order.status) {
+                return false;
+            }
+            if filter.from.is_some_and(|from| order.created_at < from)
+                || filter.to.is_some_and(|to| order.created_at >= to) {
+                return false;
+            }
+            if let Some(search) = &filter.search
+                && ![&order.customer_name, &order.phone_number, &order.order_number]
+                    .iter()
+                    .any(|field| field.contains(search.as_str()))
+                && !order.short_code.contains(&search.to_uppercase()) {
+                return false;
+            }
+            if filter.courier_id.is_some() && order.courier_id != filter.courier_id {
+                return false;
+            }
+            true
+        }
+
+        /**
+         * 判断订单编号或短码是否指向该订单
+         */
+        fn is_reference(order: &Order, reference: &str) -> bool {
+            let reference = reference.trim();
+            order.order_number == reference || order.short_code == reference.to_uppercase()
+        }
+
+        fn lock(&self) -> ApiResult<std::sync::MutexGuard<'_, Vec<Order>>> {
+            self.orders.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))
+        }
+
+        fn lock_couriers(&self) -> ApiResult<std::sync::MutexGuard<'_, Vec<Courier>>> {
+            self.couriers.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))
+        }
+
+        fn lock_payments(&self) -> ApiResult<std::sync::MutexGuard<'_, Vec<Payment>>> {
+            self.payments.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))
+        }
+
+        fn lock_refunds(&self) -> ApiResult<std::sync::MutexGuard<'_, Vec<Refund>>> {
+            self.refunds.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))
+        }
+
+        fn push_audit(&self, order_number: &str, entry: &OrderAuditEntry) -> ApiResult<()> {
+            self.audit.lock()
+                .map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))?
+                .push((order_number.to_string(), entry.clone()));
+            Ok(())
+        }
+
+        fn set_status(&self, status: &OrderStatus, pred: impl Fn(&Order) -> bool) -> ApiResult<Option<(String, OrderStatus)>> {
+            let mut orders = self.lock()?;
+            let Some(order) = orders.iter_mut().find(|o| pred(o)) else {
+                return Ok(None);
+            };
+            if !status.allowed_previous().contains(&order.status) {
+                return Err(ApiError::InvalidOrderState(format!("Order is {} and cannot become {}", order.status, status)));
+            }
+            let previous = std::mem::replace(&mut order.status, status.clone());
+            if order.status != OrderStatus::Delivering {
+                order.courier_location = None;
+            }
+            Ok(Some((order.order_number.clone(), previous)))
+        }
+    }
+
+    impl OrderRepository for InMemoryOrderRepository {
+        fn create_order(&self, order: &Order) -> ApiResult<Order> {
+            let mut orders = self.lock()?;
+            if orders.iter().any(|o| o.order_number == order.order_number || o.short_code == order.short_code) {
+                return Err(ApiError::Conflict(format!("Order {} already exists", order.order_number)));
+            }
+            let mut order = order.clone();
+            order.id = orders.len() as i64 + 1;
+            order.created_at = order.created_at.trunc_subsecs(0);
+            orders.push(order.clone());
+            Ok(order)
+        }
+
+        fn get_order_by_reference(&self, reference: &str) -> ApiResult<Option<Order>> {
+            Ok(self.lock()?.iter().find(|o| Self::is_reference(o, reference)).cloned())
+        }
+
+        fn get_orders(&self, filter: &OrderFilter) -> ApiResult<Vec<Order>> {
+            let mut orders: Vec<Order> = self.lock()?
+                .iter()
+                .filter(|o| Self::matches(o, filter))
+                .cloned()
+                .collect();
+            orders.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+            Ok(orders.into_iter().skip(filter.offset as usize).take(filter.limit as usize).collect())
+        }
+
+        fn count_orders(&self, filter: &OrderFilter) -> ApiResult<i64> {
+            Ok(self.lock()?.iter().filter(|o| Self::matches(o, filter)).count() as i64)
+        }
+
+        fn update_order_status(&self, order_id: i64, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>> {
+            self.set_status(status, |o| o.id == order_id)
+        }
+
+        fn update_order_status_by_reference(&self, reference: &str, status: &OrderStatus) -> ApiResult<Option<(String, OrderStatus)>> {
+            self.set_status(status, |o| Self::is_reference(o, reference))
+        }
+
+        fn cancel_order(
+            &self,
+            order_number: &str,
+            expected_status: &OrderStatus,
+            reason: Option<&str>,
+            cancelled_at: DateTime<Utc>,
+        ) -> ApiResult<bool> {
+            let mut orders = self.lock()?;
+            match orders.iter_mut().find(|o| o.order_number == order_number && o.status == *expected_status) {
+                Some(order) => {
+                    order.status = OrderStatus::Cancelled;
+                    order.cancel_reason = reason.map(str::to_string);
+                    order.cancelled_at = Some(cancelled_at);
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+
+        fn amend_order_items(
+            &self,
+            order_number: &str,
+            items: &[OrderItem],
+            total_amount: Money,
+            entry: &OrderAuditEntry,
+        ) -> ApiResult<Option<Order>> {
+            let mut orders = self.lock()?;
+            let Some(order) = orders.iter_mut()
+                .find(|o| o.order_number == order_number && o.status == OrderStatus::Pending) else {
+                return Ok(None);
+            };
+            order.items = items.to_vec();
+            order.total_amount = total_amount;
+            self.push_audit(order_number, entry)?;
+            Ok(Some(order.clone()))
+        }
+
+        fn get_order_audit(&self, order_number: &str) -> ApiResult<Vec<OrderAuditEntry>> {
+            let audit = self.audit.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))?;
+            Ok(audit.iter().filter(|(number, _)| number == order_number).map(|(_, entry)| entry.clone()).collect())
+        }
+
+        fn release_held_orders(&self, due_before: DateTime<Utc>, dispatched_at: DateTime<Utc>) -> ApiResult<Vec<Order>> {
+            let mut orders = self.lock()?;
+            Ok(orders.iter_mut()
+                .filter(|o| o.dispatched_at.is_none()
+                    && o.status == OrderStatus::Pending
+                    && o.requested_time.is_some_and(|time| time <= due_before))
+                .map(|o| {
+                    o.dispatched_at = Some(dispatched_at);
+                    o.clone()
+                })
+                .collect())
+        }
+
+        fn update_order_estimates(&self, estimates: &[OrderEstimate]) -> ApiResult<()> {
+            let mut orders = self.lock()?;
+            for estimate in estimates {
+                if let Some(order) = orders.iter_mut().find(|o| o.order_number == estimate.order_number) {
+                    order.estimated_ready_at = Some(estimate.ready_at);
+                    order.estimated_arrival_at = Some(estimate.arrival_at);
+                }
+            }
+            Ok(())
+        }
+
+        fn get_store_pause(&self) -> ApiResult<Option<StorePause>> {
+            let pause = self.pause.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))?;
+            Ok(pause.clone())
+        }
+
+        fn set_store_pause(&self, pause: Option<&StorePause>) -> ApiResult<()> {
+            *self.pause.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))? = pause.cloned();
+            Ok(())
+        }
+
+        fn create_courier(&self, courier: &Courier) -> ApiResult<Courier> {
+            let mut couriers = self.lock_couriers()?;
+            if couriers.iter().any(|c| c.token == courier.token) {
+                return Err(ApiError::Conflict("Courier token already in use".to_string()));
+            }
+            let created = Courier { id: couriers.len() as i64 + 1, ..courier.clone() };
+            couriers.push(created.clone());
+            Ok(created)
+        }
+
+        fn get_couriers(&self) -> ApiResult<Vec<Courier>> {
+            Ok(self.lock_couriers()?.clone())
+        }
+
+        fn get_courier(&self, courier_id: i64) -> ApiResult<Option<Courier>> {
+            Ok(self.lock_couriers()?.iter().find(|c| c.id == courier_id).cloned())
+        }
+
+        fn get_courier_by_token(&self, token: &str) -> ApiResult<Option<Courier>> {
+            Ok(self.lock_couriers()?.iter().find(|c| c.token == token).cloned())
+        }
+
+        fn assign_courier(&self, order_number: &str, courier_id: Option<i64>, entry: &OrderAuditEntry) -> ApiResult<Option<Order>> {
+            let mut orders = self.lock()?;
+            let Some(order) = orders.iter_mut().find(|o| o.order_number == order_number
+                && !matches!(o.status, OrderStatus::Completed | OrderStatus::Cancelled)) else {
+                return Ok(None);
+            };
+            order.courier_id = courier_id;
+            self.push_audit(order_number, entry)?;
+            Ok(Some(order.clone()))
+        }
+
+        fn update_courier_order_status(
+            &self,
+            order_number: &str,
+            courier_id: i64,
+            from: &[OrderStatus],
+            to: &OrderStatus,
+            entry: &OrderAuditEntry,
+        ) -> ApiResult<bool> {
+            let mut orders = self.lock()?;
+            let Some(order) = orders.iter_mut().find(|o| o.order_number == order_number
+                && o.courier_id == Some(courier_id)
+                && from.contains(&o.status)) else {
+                return Ok(false);
+            };
+            order.status = to.clone();
+            if order.status != OrderStatus::Delivering {
+                order.courier_location = None;
+            }
+            self.push_audit(order_number, entry)?;
+            Ok(true)
+        }
+
+        fn update_courier_location(&self, courier_id: i64, location: Location, updated_at: DateTime<Utc>) -> ApiResult<usize> {
+            let mut orders = self.lock()?;
+            let mut updated = 0;
+            for order in orders.iter_mut()
+                .filter(|o| o.courier_id == Some(courier_id) && o.status == OrderStatus::Delivering) {
+                order.courier_location = Some(CourierPosition { lat: location.lat, lng: location.lng, updated_at });
+                updated += 1;
+            }
+            Ok(updated)
+        }
+
+        fn lookup_geocode(&self, address_key: &str) -> ApiResult<Option<Location>> {
+            let geocodes = self.geocodes.lock().map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))?;
+            Ok(geocodes.get(address_key).copied())
+        }
+
+        fn save_geocode(&self, address_key: &str, location: Location, _updated_at: DateTime<Utc>) -> ApiResult<()> {
+            self.geocodes.lock()
+                .map_err(|_| ApiError::Internal("Repository lock poisoned".to_string()))?
+                .entry(address_key.to_string())
+                .or_insert(location);
+            Ok(())
+        }
+
+        fn create_payment(&self, payment: &Payment) -> ApiResult<()> {
+            if !self.lock()?.iter().any(|o| o.order_number == payment.order_number) {
+                return Err(ApiError::OrderNotFound);
+            }
+            let mut payments = self.lock_payments()?;
+            if payments.iter().any(|p| p.reference == payment.reference) {
+                return Err(ApiError::Conflict("Payment reference already in use".to_string()));
+            }
+            if payments.iter().any(|p| p.order_number == payment.order_number && p.status == PaymentStatus::Pending) {
+                return Err(ApiError::Conflict("A payment for this order is already in progress".to_string()));
+            }
+            payments.push(payment.clone());
+            Ok(())
+        }
+
+        fn get_payment(&self, reference: &str) -> ApiResult<Option<Payment>> {
+            Ok(self.lock_payments()?.iter().find(|p| p.reference == reference).cloned())
+        }
+
+        fn complete_payment(
+            &self,
+            reference: &str,
+            status: PaymentStatus,
+            amount: Money,
+            updated_at: DateTime<Utc>,
+            dispatched_at: Option<DateTime<Utc>>,
+            entry: &OrderAuditEntry,
+        ) -> ApiResult<Option<Order>> {
+            let mut orders = self.lock()?;
+            let mut payments = self.lock_payments()?;
+            let Some(payment) = payments.iter_mut()
+                .find(|p| p.reference == reference && p.status == PaymentStatus::Pending) else {
+                return Ok(None);
+            };
+            payment.status = status;
+            payment.amount = amount;
+            payment.updated_at = updated_at;
+            self.push_audit(&payment.order_number, entry)?;
+
+            let order = orders.iter_mut()
+                .find(|o| o.order_number == payment.order_number && o.status == OrderStatus::AwaitingPayment && o.total_amount == amount)
+                .filter(|_| status == PaymentStatus::Succeeded);
+            Ok(order.map(|order| {
+                order.status = OrderStatus::Pending;
+                order.dispatched_at = dispatched_at;
+                order.clone()
+            }))
+        }
+
+        fn get_order_payments(&self, order_number: &str) -> ApiResult<Vec<Payment>> {
+            Ok(self.lock_payments()?.iter().filter(|p| p.order_number == order_number).cloned().collect())
+        }
+
+        fn reserve_refund(&self, refund: &Refund) -> ApiResult<i64> {
+            let mut payments = self.lock_payments()?;
+            let mut refunds = self.lock_refunds()?;
+            let payment = payments.iter_mut()
+                .find(|p| p.reference == refund.payment_reference && p.status == PaymentStatus::Succeeded)
+                .ok_or(ApiError::PaymentNotFound)?;
+            if payment.refunded_amount + refund.amount > payment.amount {
+                return Err(ApiError::Conflict("Refund exceeds the refundable amount".to_string()));
+            }
+            payment.refunded_amount += refund.amount;
+            refunds.push(Refund { reference: None, status: PaymentStatus::Pending, ..refund.clone() });
+            Ok(refunds.len() as i64)
+        }
+
+        fn finish_refund(&self, refund_id: i64, status: PaymentStatus, reference: Option<&str>, entry: &OrderAuditEntry) -> ApiResult<()> {
+            let mut orders = self.lock()?;
+            let mut payments = self.lock_payments()?;
+            let mut refunds = self.lock_refunds()?;
+            if reference.is_some() && refunds.iter().any(|r| r.reference.as_deref() == reference) {
+                return Err(ApiError::Conflict("Refund reference already in use".to_string()));
+            }
+            let refund = refunds.get_mut(refund_id as usize - 1)
+                .filter(|r| r.status == PaymentStatus::Pending)
+                .ok_or_else(|| ApiError::Conflict("Refund has already been completed".to_string()))?;
+            refund.status = status;
+            refund.reference = reference.map(str::to_string);
+
+            // 失败的退款释放预留的金额
+            if status == PaymentStatus::Failed
+                && let Some(payment) = payments.iter_mut().find(|p| p.reference == refund.payment_reference) {
+                payment.refunded_amount = payment.refunded_amount - refund.amount;
+            }
+            let paid: Money = payments.iter()
+                .filter(|p| p.order_number == refund.order_number && p.status == PaymentStatus::Succeeded)
+                .map(|p| p.amount)
+                .sum();
+            if let Some(order) = orders.iter_mut().find(|o| o.order_number == refund.order_number) {
+                if status == PaymentStatus::Succeeded {
+                    order.refunded_amount += refund.amount;
+                }
+                order.refund_status = Some(RefundStatus::after_refund(status, order.refunded_amount, paid));
+            }
+            self.push_audit(&refund.order_number, entry)
+        }
+
+        fn get_order_refunds(&self, order_number: &str) -> ApiResult<Vec<Refund>> {
+            let refunds = self.lock_refunds()?;
+            Ok(refunds.iter().filter(|r| r.order_number == order_number).cloned().collect())
+        }
+    }
+}
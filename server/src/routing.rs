@@ -0,0 +1,168 @@
+/*!
+ * 配送批次模块
+ * 将等待配送的外送订单按坐标就近分组
+ * 并用最近邻法建议每批订单的配送顺序
+ */
+
+use crate::delivery::haversine_km;
+use crate::models::{DeliveryBatch, DeliveryStop, Location, Order};
+
+// 默认分组参数
+pub const DEFAULT_MAX_ORDERS: usize = 3;      // 每批最多订单数
+pub const DEFAULT_MAX_DISTANCE_KM: f64 = 2.0; // 同批订单距首单的最大距离（公里）
+
+/**
+ * 分组参数
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchOptions {
+    pub max_orders: usize,    // 每批最多订单数
+    pub max_distance_km: f64, // 同批订单距首单的最大距离（公里）
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_orders: DEFAULT_MAX_ORDERS,
+            max_distance_km: DEFAULT_MAX_DISTANCE_KM,
+        }
+    }
+}
+
+/**
+ * 将订单分组为配送批次
+ * 按预计出餐时间依次取尚未分组的订单作为首单，再加入距首单最近且在范围内的订单
+ * 没有坐标的订单不参与分组
+ *
+ * @param orders - 等待配送的外送订单
+ * @param store - 门店坐标（路线起点，未配置时从首个停靠点出发）
+ * @param options - 分组参数
+ * @return Vec<DeliveryBatch> - 配送批次，按首单的预计出餐时间排列
+ */
+pub fn plan_batches(orders: &[Order], store: Option<Location>, options: &BatchOptions) -> Vec<DeliveryBatch> {
+    let mut remaining: Vec<(&Order, Location)> = orders.iter()
+        .filter_map(|order| Some((order, order.location()?)))
+        .collect();
+    // 没有预计时间的订单排在最后
+    remaining.sort_by_key(|(o, _)| (o.estimated_ready_at.is_none(), o.estimated_ready_at, o.created_at, o.id));
+
+    let mut batches = Vec::new();
+    while !remaining.is_empty() {
+        let (first, origin) = remaining.remove(0);
+        let mut members = vec![(first, origin)];
+        while members.len() < options.max_orders {
+            let nearest = remaining.iter()
+                .enumerate()
+                .map(|(index, (_, location))| (index, haversine_km(origin, *location)))
+                .filter(|(_, distance)| *distance <= options.max_distance_km)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            match nearest {
+                Some((index, _)) => members.push(remaining.remove(index)),
+                None => break,
+            }
+        }
+        batches.push(route(store, members));
+    }
+    batches
+}
+
+/**
+ * 用最近邻法排列批次内的停靠顺序
+ * 从起点出发，每次前往最近的未到达地址
+ *
+ * @param start - 起点
+ * @param members - 批次内的订单及其坐标
+ * @return DeliveryBatch - 排好顺序的配送批次
+ */
+fn route(start: Option<Location>, mut members: Vec<(&Order, Location)>) -> DeliveryBatch {
+    let ready_at = members.iter().filter_map(|(o, _)| o.estimated_ready_at).max();
+    let mut current = start;
+    let mut total_km = 0.0;
+    let mut stops = Vec::with_capacity(members.len());
+    while !members.is_empty() {
+        let index = current.map_or(0, |from| {
+            members.iter()
+                .enumerate()
+                .min_by(|a, b| haversine_km(from, a.1.1).total_cmp(&haversine_km(from, b.1.1)))
+                .map_or(0, |(index, _)| index)
+        });
+        let (order, location) = members.remove(index);
+        let leg_km = current.map_or(0.0, |from| haversine_km(from, location));
+        total_km += leg_km;
+        current = Some(location);
+        stops.push(DeliveryStop {
+            order_number: order.order_number.clone(),
+            short_code: order.short_code.clone(),
+            customer_name: order.customer_name.clone(),
+            phone_number: order.phone_number.clone(),
+            delivery_address: order.delivery_address.clone(),
+            location,
+            leg_km: round_km(leg_km),
+            estimated_ready_at: order.estimated_ready_at,
+        });
+    }
+    DeliveryBatch {
+        stops,
+        total_distance_km: round_km(total_km),
+        ready_at,
+    }
+}
+
+/**
+ * 距离保留两位小数
+ */
+fn round_km(km: f64) -> f64 {
+    (km * 100.0).round() / 100.0
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+    use crate::models::{Fulfilment, OrderStatus};
+
+    fn order(order_number: &str, lat: f64, lng: f64, ready_mins: i64) -> Order {
+        let created_at: DateTime<Utc> = "2024-05-01T04:00:00Z".parse().unwrap();
+        Order {
+            id: ready_mins,
+            fulfilment: Fulfilment::Delivery,
+            delivery_address: Some("测试地址".to_string()),
+            latitude: Some(lat),
+            longitude: Some(lng),
+            created_at,
+            status: OrderStatus::Preparing,
+            dispatched_at: Some(created_at),
+            estimated_ready_at: Some(created_at + Duration::minutes(ready_mins)),
+            ..Order::sample(order_number)
+        }
+    }
+
+    #[test]
+    fn test_plan_batches() {
+        let store = Location { lat: 39.98, lng: 116.31 };
+        // A、B、D在门店东侧约1公里范围内，C在约10公里外
+        let orders = [
+            order("A", 39.98, 116.33, 5),
+            order("B", 39.98, 116.32, 6),
+            order("C", 40.07, 116.31, 7),
+            order("D", 39.985, 116.325, 8),
+        ];
+        let batches = plan_batches(&orders, Some(store), &BatchOptions::default());
+        assert_eq!(batches.len(), 2);
+
+        // 首单为最先出餐的A，路线从门店出发先到最近的B
+        let route: Vec<&str> = batches[0].stops.iter().map(|s| s.order_number.as_str()).collect();
+        assert_eq!(route, ["B", "D", "A"]);
+        assert_eq!(batches[0].ready_at, orders[3].estimated_ready_at);
+        let legs: f64 = batches[0].stops.iter().map(|s| s.leg_km).sum();
+        assert!((legs - batches[0].total_distance_km).abs() < 0.02);
+        assert_eq!(batches[1].stops[0].order_number, "C");
+
+        // 每批最多一单时不分组
+        let options = BatchOptions { max_orders: 1, ..Default::default() };
+        assert_eq!(plan_batches(&orders, None, &options).len(), 4);
+    }
+}
@@ -0,0 +1,355 @@
+/*!
+ * 营业时间与预约订单调度模块
+ * 校验预约送达时间是否在营业时间内
+ * 并在预约时间临近时将暂存的订单发送给设备
+ */
+
+use actix_web::web;
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::config;
+use crate::eta;
+use crate::handlers::{notify_device, OrderSender};
+use crate::repository::AppState;
+use crate::serial_comm::DeviceEvent;
+use crate::validation::{OUTSIDE_OPENING_HOURS, OUT_OF_RANGE};
+
+// 预约订单调度常量
+const MAX_SCHEDULE_AHEAD_DAYS: i64 = 7; // 最多可提前预约的天数
+const DISPATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30); // 检查暂存订单的间隔
+const MAX_OPENING_LOOKAHEAD_DAYS: u64 = 31; // 查找下次营业时间时最多向后查找的天数
+
+/**
+ * 每日营业时段
+ * 关门时间早于开门时间时表示营业到次日（如"18:00-02:00"）
+ * "00:00-24:00"表示全天营业
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpeningHours {
+    pub open: NaiveTime,  // 开门时间（门店时区）
+    pub close: NaiveTime, // 关门时间（门店时区）
+}
+
+impl OpeningHours {
+    /**
+     * 获取某天营业时段对应的时间区间
+     * 跨午夜的时段在次日关门
+     *
+     * @param date - 营业日期（门店时区）
+     * @return (DateTime<Utc>, DateTime<Utc>) - 开门和关门时间
+     */
+    fn window_on(&self, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let close_date = if self.close <= self.open { date + Days::new(1) } else { date };
+        let to_utc = |date: NaiveDate, time: NaiveTime| {
+            config::store_timezone().from_local_datetime(&date.and_time(time)).unwrap().with_timezone(&Utc)
+        };
+        (to_utc(date, self.open), to_utc(close_date, self.close))
+    }
+}
+
+impl FromStr for OpeningHours {
+    type Err = String;
+
+    /**
+     * 解析"HH:MM-HH:MM"格式的营业时段
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid opening hours: {}", s);
+        let (open, close) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |value: &str| NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| invalid());
+        let open = parse(open)?;
+        // 24:00只能作为关门时间，表示营业到午夜
+        let until_midnight = close.trim() == "24:00";
+        let close = if until_midnight { NaiveTime::MIN } else { parse(close)? };
+        if open == close && !until_midnight {
+            return Err(invalid());
+        }
+        Ok(OpeningHours { open, close })
+    }
+}
+
+impl fmt::Display for OpeningHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.open.format("%H:%M"), self.close.format("%H:%M"))
+    }
+}
+
+/**
+ * 门店营业时间表
+ * 包含每周各天的营业时段和节假日例外
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreHours {
+    weekly: [Option<OpeningHours>; 7],                    // 周一至周日的营业时段，None表示休息
+    holidays: BTreeMap<NaiveDate, Option<OpeningHours>>, // 节假日例外，None表示全天休息
+}
+
+impl StoreHours {
+    /**
+     * 解析节假日例外并加入营业时间表
+     * 格式为以分号分隔的"YYYY-MM-DD"（全天休息）或"YYYY-MM-DD=HH:MM-HH:MM"（特殊营业时段）
+     *
+     * @param spec - 节假日配置
+     * @return Result<StoreHours, String> - 解析结果
+     */
+    pub fn with_holidays(mut self, spec: &str) -> Result<Self, String> {
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (date, hours) = match entry.split_once('=') {
+                Some((date, hours)) => (date, parse_day_hours(hours)?),
+                None => (entry, None),
+            };
+            let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid holiday: {}", entry))?;
+            self.holidays.insert(date, hours);
+        }
+        Ok(self)
+    }
+
+    /**
+     * 获取某天的营业时段，节假日例外优先
+     */
+    fn hours_on(&self, date: NaiveDate) -> Option<OpeningHours> {
+        match self.holidays.get(&date) {
+            Some(hours) => *hours,
+            None => self.weekly[date.weekday().num_days_from_monday() as usize],
+        }
+    }
+
+    /**
+     * 获取某一时刻所在的营业区间
+     * 需要同时检查前一天跨午夜的营业时段
+     *
+     * @param time - 时间
+     * @return Option<(DateTime<Utc>, DateTime<Utc>)> - 营业区间，不在营业时间内时返回None
+     */
+    pub fn current_window(&self, time: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let today = time.with_timezone(&config::store_timezone()).date_naive();
+        [today - Days::new(1), today].into_iter()
+            .filter_map(|date| self.hours_on(date).map(|hours| hours.window_on(date)))
+            .find(|(open, close)| *open <= time && time < *close)
+    }
+
+    /**
+     * 判断某一时刻门店是否营业
+     *
+     * @param time - 时间
+     * @return bool - 是否在营业时间内
+     */
+    pub fn is_open_at(&self, time: DateTime<Utc>) -> bool {
+        self.current_window(time).is_some()
+    }
+
+    /**
+     * 获取某一时刻之后的下一次开门时间
+     *
+     * @param time - 时间
+     * @return Option<DateTime<Utc>> - 开门时间，近期没有营业安排时返回None
+     */
+    pub fn next_opening(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = time.with_timezone(&config::store_timezone()).date_naive();
+        (0..=MAX_OPENING_LOOKAHEAD_DAYS)
+            .map(|offset| today + Days::new(offset))
+            .filter_map(|date| self.hours_on(date).map(|hours| hours.window_on(date).0))
+            .find(|open| *open > time)
+    }
+}
+
+impl FromStr for StoreHours {
+    type Err = String;
+
+    /**
+     * 解析每周营业时间
+     * 格式为以分号分隔的"HH:MM-HH:MM"（每天）或"星期=HH:MM-HH:MM"，星期可写成"mon-fri"或"sat,sun"
+     * 营业时段写"closed"表示休息，后面的配置覆盖前面的配置，未配置的日期休息
+     * 如"mon-fri=08:00-22:00;sat,sun=10:00-20:00"
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weekly = [None; 7];
+        let entries: Vec<&str> = s.split(';').map(str::trim).filter(|e| !e.is_empty()).collect();
+        if entries.is_empty() {
+            return Err(format!("Invalid opening hours: {}", s));
+        }
+        for entry in entries {
+            match entry.split_once('=') {
+                Some((days, hours)) => {
+                    let hours = parse_day_hours(hours)?;
+                    for day in parse_weekdays(days)? {
+                        weekly[day] = hours;
+                    }
+                }
+                None => weekly = [Some(entry.parse()?); 7],
+            }
+        }
+        Ok(StoreHours { weekly, holidays: BTreeMap::new() })
+    }
+}
+
+impl fmt::Display for StoreHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = self.weekly.iter().zip(WEEKDAYS).map(|(hours, day)| match hours {
+            Some(hours) => format!("{}={}", day, hours),
+            None => format!("{}=closed", day),
+        });
+        write!(f, "{}", days.collect::<Vec<_>>().join(";"))
+    }
+}
+
+// 星期缩写，按周一至周日排列
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/**
+ * 解析某天的营业时段，"closed"表示休息
+ */
+fn parse_day_hours(s: &str) -> Result<Option<OpeningHours>, String> {
+    if s.trim().eq_ignore_ascii_case("closed") {
+        Ok(None)
+    } else {
+        s.parse().map(Some)
+    }
+}
+
+/**
+ * 解析星期列表，返回从周一开始的序号
+ * 支持逗号分隔和"fri-mon"这样跨周末的范围
+ */
+fn parse_weekdays(s: &str) -> Result<Vec<usize>, String> {
+    let index = |day: &str| WEEKDAYS.iter()
+        .position(|d| d.eq_ignore_ascii_case(day.trim()))
+        .ok_or_else(|| format!("Invalid weekday: {}", day.trim()));
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (index(first)?, index(last)?);
+                let len = (last + 7 - first) % 7 + 1;
+                days.extend((0..len).map(|i| (first + i) % 7));
+            }
+            None => days.push(index(part)?),
+        }
+    }
+    Ok(days)
+}
+
+/**
+ * 校验预约时间
+ * 预约时间必须晚于当前时间、不超过最多可提前的天数，并且在营业时间内
+ *
+ * @param hours - 门店营业时间表
+ * @param requested_time - 预约时间
+ * @param now - 当前时间
+ * @return Option<&'static str> - 校验失败时返回错误代码
+ */
+pub fn check_requested_time(hours: &StoreHours, requested_time: DateTime<Utc>, now: DateTime<Utc>) -> Option<&'static str> {
+    if requested_time <= now || requested_time > now + Duration::days(MAX_SCHEDULE_AHEAD_DAYS) {
+        Some(OUT_OF_RANGE)
+    } else if !hours.is_open_at(requested_time) {
+        Some(OUTSIDE_OPENING_HOURS)
+    } else {
+        None
+    }
+}
+
+/**
+ * 判断订单是否应立即发送给设备
+ * 未预约的订单立即发送；预约订单在送达时间前的提前量之内才发送
+ *
+ * @param requested_time - 预约送达时间
+ * @param now - 当前时间
+ * @return bool - 是否立即发送
+ */
+pub fn is_due(requested_time: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    requested_time.is_none_or(|time| time <= now + config::schedule_lead_time())
+}
+
+/**
+ * 预约订单调度任务
+ * 定期取出到达提前量的暂存订单并发送给设备
+ *
+ * @param app_state - 应用状态（包含订单仓储）
+ * @param order_sender - 设备事件发送器
+ */
+pub async fn run_dispatcher(app_state: web::Data<AppState>, order_sender: web::Data<OrderSender>) {
+    let mut interval = actix_web::rt::time::interval(DISPATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        let due_before = now + config::schedule_lead_time();
+        let estimator = Arc::clone(&app_state.estimator);
+        let released = app_state.run(move |repo| {
+            let orders = repo.release_held_orders(due_before, now)?;
+            // 定时刷新预计时间，使超时的订单顺延
+            eta::refresh_estimates(repo, &estimator, now)?;
+            Ok(orders)
+        }).await;
+        match released {
+            Ok(orders) => {
+                for order in orders {
+                    log::info!("Releasing scheduled order {} to device", order.order_number);
+                    notify_device(&order_sender, DeviceEvent::NewOrder(Box::new(order)));
+                }
+            }
+            Err(e) => log::error!("Failed to release scheduled orders: {}", e),
+        }
+    }
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_opening_hours() {
+        // 门店默认时区为+08:00
+        let hours: StoreHours = "08:00-22:00".parse().unwrap();
+        assert!(hours.is_open_at(at("2024-05-01T02:30:00Z")));  // 10:30
+        assert!(!hours.is_open_at(at("2024-05-01T14:00:00Z"))); // 22:00
+        assert!(!hours.is_open_at(at("2024-04-30T23:59:00Z"))); // 07:59
+
+        // 跨午夜营业
+        let hours: StoreHours = "18:00-02:00".parse().unwrap();
+        assert!(hours.is_open_at(at("2024-05-01T17:00:00Z")));  // 01:00
+        assert!(!hours.is_open_at(at("2024-05-01T04:00:00Z"))); // 12:00
+
+        let hours: StoreHours = "00:00-24:00".parse().unwrap();
+        assert!(hours.is_open_at(at("2024-05-01T15:59:00Z"))); // 23:59
+
+        assert!("08:00".parse::<StoreHours>().is_err());
+        assert!("08:00-08:00".parse::<StoreHours>().is_err());
+        assert!("fri=08:00-08:00".parse::<StoreHours>().is_err());
+        assert!("someday=08:00-22:00".parse::<StoreHours>().is_err());
+    }
+
+    #[test]
+    fn test_weekly_hours_and_holidays() {
+        // 2024-05-03是周五，周五营业到次日凌晨，周日休息
+        let hours: StoreHours = "mon-thu=08:00-22:00;fri,sat=08:00-02:00;sun=closed".parse::<StoreHours>().unwrap()
+            .with_holidays("2024-05-01;2024-05-02=12:00-18:00").unwrap();
+        assert!(hours.is_open_at(at("2024-05-04T17:30:00Z")));  // 周六01:30
+        assert!(!hours.is_open_at(at("2024-05-05T04:00:00Z"))); // 周日12:00
+        assert!(!hours.is_open_at(at("2024-05-05T17:30:00Z"))); // 周一01:30，周日休息没有跨午夜的时段
+
+        // 节假日例外覆盖每周营业时间
+        assert!(!hours.is_open_at(at("2024-05-01T02:30:00Z"))); // 周三10:30，全天休息
+        assert!(!hours.is_open_at(at("2024-05-02T02:30:00Z"))); // 周四10:30，12:00才营业
+        assert!(hours.is_open_at(at("2024-05-02T05:00:00Z")));  // 周四13:00
+
+        // 营业中时返回关门时间，休息时返回下一次开门时间
+        let (_, closes_at) = hours.current_window(at("2024-05-03T14:00:00Z")).unwrap();
+        assert_eq!(closes_at, at("2024-05-03T18:00:00Z"));
+        assert_eq!(hours.next_opening(at("2024-04-30T15:00:00Z")), Some(at("2024-05-02T04:00:00Z")));
+        assert_eq!(hours.next_opening(at("2024-05-04T19:00:00Z")), Some(at("2024-05-06T00:00:00Z")));
+
+        assert_eq!("mon-sun=closed".parse::<StoreHours>().unwrap().next_opening(at("2024-05-01T00:00:00Z")), None);
+        assert!(hours.with_holidays("2024-13-01").is_err());
+    }
+}
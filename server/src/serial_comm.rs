@@ -1,4 +1,4 @@
-/**
+/*!
  * 串口通信模块
  * 负责与外部设备（如打印机、制作设备等）进行串口通信
  * 实现订单信息的发送和状态更新的接收
@@ -18,6 +18,9 @@ use std::str::FromStr;
 const BAUD_RATE: u32 = 9600;           // 波特率
 const SERIAL_TIMEOUT: Duration = Duration::from_millis(1000); // 超时时间
 
+/// 订单状态更新回调函数类型
+pub type StatusCallback = Box<dyn Fn(String, OrderStatus) + Send>;
+
 /**
  * 串口通信消息结构
  * 用于序列化和反序列化与设备的通信内容
@@ -26,8 +29,16 @@ const SERIAL_TIMEOUT: Duration = Duration::from_millis(1000); // 超时时间
 pub struct SerialMessage {
     message_type: String,      // 消息类型（如"new_order"或"status_update"）
     order_number: String,      // 订单编号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_code: Option<String>, // 订单短码（仅新订单消息，供设备显示）
     status: Option<String>,    // 可选的订单状态
     items: Option<Vec<SerialOrderItem>>, // 可选的订单项列表
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,    // 取消原因（仅取消订单消息）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fulfilment: Option<String>, // 履约方式（delivery、pickup或dine_in）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table_number: Option<String>, // 堂食桌号
 }
 
 /**
@@ -40,13 +51,64 @@ pub struct SerialOrderItem {
     quantity: i32,    // 商品数量
 }
 
+/**
+ * 发送给设备的事件
+ */
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    NewOrder(Box<Order>),                                     // 新订单
+    OrderAmended(Box<Order>),                                 // 订单商品已修改
+    OrderCancelled { order_number: String, reason: Option<String> }, // 订单已取消
+}
+
+/**
+ * 构建包含订单商品的消息
+ */
+fn order_message(message_type: &str, order: Order) -> SerialMessage {
+    // 转换订单项为设备可识别的格式
+    let items = order.items.iter().map(|item| SerialOrderItem {
+        name: item.name.clone(),
+        quantity: item.quantity,
+    }).collect();
+
+    SerialMessage {
+        message_type: message_type.to_string(),
+        order_number: order.order_number,
+        short_code: Some(order.short_code),
+        status: None,
+        items: Some(items),
+        reason: None,
+        fulfilment: Some(order.fulfilment.kind().to_string()),
+        table_number: order.fulfilment.table_number().map(str::to_string),
+    }
+}
+
+impl From<DeviceEvent> for SerialMessage {
+    fn from(event: DeviceEvent) -> Self {
+        match event {
+            DeviceEvent::NewOrder(order) => order_message("new_order", *order),
+            DeviceEvent::OrderAmended(order) => order_message("update_order", *order),
+            DeviceEvent::OrderCancelled { order_number, reason } => SerialMessage {
+                message_type: "cancel_order".to_string(),
+                order_number,
+                short_code: None,
+                status: Some(OrderStatus::Cancelled.to_string()),
+                items: None,
+                reason,
+                fulfilment: None,
+                table_number: None,
+            },
+        }
+    }
+}
+
 /**
  * 串口通信管理器
  * 处理与外部设备的双向通信
  */
 pub struct SerialComm {
     port: Box<dyn SerialPort>,  // 串口实例
-    status_callback: Arc<Mutex<Box<dyn Fn(String, OrderStatus) + Send>>>, // 状态更新回调函数
+    status_callback: Arc<Mutex<StatusCallback>>, // 状态更新回调函数
 }
 
 impl SerialComm {
@@ -57,7 +119,7 @@ impl SerialComm {
      * @param status_callback - 状态更新回调函数
      * @return Result<SerialComm> - 串口通信实例
      */
-    pub fn new(port_name: &str, status_callback: Box<dyn Fn(String, OrderStatus) + Send>) -> anyhow::Result<Self> {
+    pub fn new(port_name: &str, status_callback: StatusCallback) -> anyhow::Result<Self> {
         // 配置并打开串口
         let port = serialport::new(port_name, BAUD_RATE)
             .timeout(SERIAL_TIMEOUT)
@@ -95,10 +157,10 @@ impl SerialComm {
      * 启动串口通信
      * 创建读写线程处理双向通信
      * 
-     * @return Result<Sender<Order>> - 订单发送通道
+     * @return Result<Sender<DeviceEvent>> - 设备事件发送通道
      */
-    pub fn start(&mut self) -> anyhow::Result<Sender<Order>> {
-        let (tx, rx) = channel::<Order>();
+    pub fn start(&mut self) -> anyhow::Result<Sender<DeviceEvent>> {
+        let (tx, rx) = channel::<DeviceEvent>();
         let mut port_clone = self.port.try_clone()?;
         let callback = Arc::clone(&self.status_callback);
 
@@ -110,16 +172,14 @@ impl SerialComm {
                     Ok(bytes_read) => {
                         if bytes_read > 0 {
                             // 尝试解析接收到的数据
-                            if let Ok(message) = String::from_utf8(serial_buf[..bytes_read].to_vec()) {
-                                if let Ok(serial_msg) = serde_json::from_str::<SerialMessage>(&message) {
-                                    // 处理状态更新消息
-                                    if serial_msg.message_type == "status_update" {
-                                        log::info!("Received status update for order {}", serial_msg.order_number);
-                                        if let (Some(status_str), Some(callback)) = (serial_msg.status, callback.lock().ok()) {
-                                            if let Ok(status) = OrderStatus::from_str(&status_str) {
-                                                callback(serial_msg.order_number, status);
-                                            }
-                                        }
+                            if let Ok(message) = String::from_utf8(serial_buf[..bytes_read].to_vec())
+                                && let Ok(serial_msg) = serde_json::from_str::<SerialMessage>(&message) {
+                                // 处理状态更新消息
+                                if serial_msg.message_type == "status_update" {
+                                    log::info!("Received status update for order {}", serial_msg.order_number);
+                                    if let (Some(status_str), Some(callback)) = (serial_msg.status, callback.lock().ok())
+                                        && let Ok(status) = OrderStatus::from_str(&status_str) {
+                                        callback(serial_msg.order_number, status);
                                     }
                                 }
                             }
@@ -136,23 +196,11 @@ impl SerialComm {
             }
         });
 
-        // 启动写入线程 - 发送新订单到设备
+        // 启动写入线程 - 发送新订单和取消通知到设备
         let mut port_write = self.port.try_clone()?;
         thread::spawn(move || {
-            while let Ok(order) = rx.recv() {
-                // 转换订单项为设备可识别的格式
-                let items = order.items.iter().map(|item| SerialOrderItem {
-                    name: item.name.clone(),
-                    quantity: item.quantity,
-                }).collect();
-
-                // 构建新订单消息
-                let message = SerialMessage {
-                    message_type: "new_order".to_string(),
-                    order_number: order.order_number,
-                    status: None,
-                    items: Some(items),
-                };
+            while let Ok(event) = rx.recv() {
+                let message = SerialMessage::from(event);
 
                 // 序列化并发送消息
                 if let Ok(json) = serde_json::to_string(&message) {
@@ -181,4 +229,17 @@ mod tests {
         let ports = SerialComm::list_ports();
         println!("Available ports: {:?}", ports);
     }
+
+    #[test]
+    fn test_cancel_message_format() {
+        let message = SerialMessage::from(DeviceEvent::OrderCancelled {
+            order_number: "A001".to_string(),
+            reason: Some("下错单了".to_string()),
+        });
+        let json: serde_json::Value = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["message_type"], "cancel_order");
+        assert_eq!(json["status"], "cancelled");
+        assert_eq!(json["reason"], "下错单了");
+        assert!(json.get("short_code").is_none());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,371 @@
+/*!
+ * 请求校验模块
+ * 对客户端提交的请求数据进行字段级校验
+ * 校验失败时返回字段路径和错误代码，供前端展示
+ */
+
+use serde::{Deserialize, Serialize};
+use crate::models::{AmendOrderRequest, BatchQuery, CancelOrderRequest, CreateCourierRequest, CreateOrderRequest, Fulfilment, Location, Money, OrderItemRequest, PauseOrderingRequest, RefundOrderRequest};
+
+// 校验规则常量
+const MAX_NAME_LEN: usize = 50;       // 客户或配送员姓名最大长度
+const MAX_ADDRESS_LEN: usize = 200;   // 配送地址最大长度
+const MAX_NOTES_LEN: usize = 500;     // 订单备注最大长度
+const MAX_REASON_LEN: usize = 200;    // 取消或修改原因最大长度
+const MAX_TABLE_NUMBER_LEN: usize = 10; // 堂食桌号最大长度
+const MAX_ITEM_NAME_LEN: usize = 100; // 商品名称最大长度
+const MAX_QUANTITY: i32 = 99;         // 单个商品最大数量
+const MIN_PHONE_DIGITS: usize = 5;    // 电话号码最少位数
+const MAX_PHONE_DIGITS: usize = 20;   // 电话号码最多位数
+const MAX_BATCH_ORDERS: usize = 10;   // 配送批次最多订单数
+const MAX_BATCH_DISTANCE_KM: f64 = 20.0; // 配送批次最大分组距离（公里）
+
+// 错误代码
+pub const REQUIRED: &str = "required";         // 必填字段为空
+pub const TOO_LONG: &str = "too_long";         // 超出长度限制
+pub const INVALID_FORMAT: &str = "invalid_format"; // 格式不正确
+pub const OUT_OF_RANGE: &str = "out_of_range"; // 数值超出范围
+pub const MISMATCH: &str = "mismatch";         // 与其他字段不一致
+pub const OUTSIDE_OPENING_HOURS: &str = "outside_opening_hours"; // 不在营业时间内
+pub const OUTSIDE_DELIVERY_ZONE: &str = "outside_delivery_zone"; // 不在配送范围内
+pub const UNKNOWN_ITEM: &str = "unknown_item"; // 商品不在菜单中
+
+/**
+ * 字段校验错误
+ * 描述单个字段未通过校验的原因
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String, // 字段路径，如"items[0].quantity"
+    pub code: String,  // 错误代码，如"required"
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: &str) -> Self {
+        FieldError {
+            field: field.into(),
+            code: code.to_string(),
+        }
+    }
+}
+
+/**
+ * 可校验的请求数据
+ * 实现者将发现的错误追加到errors中，字段路径以prefix为前缀
+ */
+pub trait Validate {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>);
+
+    /**
+     * 执行校验
+     * 
+     * @return Result<(), Vec<FieldError>> - 校验通过返回Ok，否则返回所有字段错误
+     */
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        self.validate_into("", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/**
+ * 拼接字段路径
+ */
+fn field_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/**
+ * 校验必填文本字段的非空和长度
+ */
+fn check_text(value: &str, max_len: usize, field: String, errors: &mut Vec<FieldError>) {
+    let value = value.trim();
+    if value.is_empty() {
+        errors.push(FieldError::new(field, REQUIRED));
+    } else if value.chars().count() > max_len {
+        errors.push(FieldError::new(field, TOO_LONG));
+    }
+}
+
+/**
+ * 校验商品列表
+ */
+fn check_items(items: &[OrderItemRequest], field: String, errors: &mut Vec<FieldError>) {
+    if items.is_empty() {
+        errors.push(FieldError::new(field.clone(), REQUIRED));
+    }
+    for (i, item) in items.iter().enumerate() {
+        item.validate_into(&format!("{}[{}]", field, i), errors);
+    }
+}
+
+/**
+ * 校验可选原因字段的长度
+ */
+fn check_reason(reason: &Option<String>, field: String, errors: &mut Vec<FieldError>) {
+    if let Some(reason) = reason
+        && reason.chars().count() > MAX_REASON_LEN {
+        errors.push(FieldError::new(field, TOO_LONG));
+    }
+}
+
+/**
+ * 校验电话号码格式
+ * 允许可选的"+"前缀，以及空格和"-"分隔符
+ */
+fn is_valid_phone(phone: &str) -> bool {
+    let phone = phone.trim();
+    let digits = phone.strip_prefix('+').unwrap_or(phone);
+    let mut count = 0;
+    for c in digits.chars() {
+        match c {
+            '0'..='9' => count += 1,
+            ' ' | '-' => {}
+            _ => return false,
+        }
+    }
+    (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&count)
+}
+
+/**
+ * 校验电话号码
+ */
+fn check_phone(phone: &str, field: String, errors: &mut Vec<FieldError>) {
+    if phone.trim().is_empty() {
+        errors.push(FieldError::new(field, REQUIRED));
+    } else if !is_valid_phone(phone) {
+        errors.push(FieldError::new(field, INVALID_FORMAT));
+    }
+}
+
+impl Validate for Location {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        if !(self.lat.is_finite() && (-90.0..=90.0).contains(&self.lat)) {
+            errors.push(FieldError::new(field_path(prefix, "lat"), OUT_OF_RANGE));
+        }
+        if !(self.lng.is_finite() && (-180.0..=180.0).contains(&self.lng)) {
+            errors.push(FieldError::new(field_path(prefix, "lng"), OUT_OF_RANGE));
+        }
+    }
+}
+
+impl Validate for OrderItemRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        check_text(&self.name, MAX_ITEM_NAME_LEN, field_path(prefix, "name"), errors);
+        if !(1..=MAX_QUANTITY).contains(&self.quantity) {
+            errors.push(FieldError::new(field_path(prefix, "quantity"), OUT_OF_RANGE));
+        }
+        if self.price.is_negative() {
+            errors.push(FieldError::new(field_path(prefix, "price"), OUT_OF_RANGE));
+        }
+    }
+}
+
+impl Validate for CreateOrderRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        check_text(&self.customer_name, MAX_NAME_LEN, field_path(prefix, "customer_name"), errors);
+
+        check_phone(&self.phone_number, field_path(prefix, "phone_number"), errors);
+
+        // 外送订单需要配送地址，堂食订单需要桌号
+        // 坐标在地址不在坐标缓存中时才必填，由创建订单时检查
+        match &self.fulfilment {
+            Fulfilment::Delivery => {
+                match &self.address {
+                    Some(address) => {
+                        let address = address.normalized();
+                        if address.is_empty() {
+                            errors.push(FieldError::new(field_path(prefix, "address.street"), REQUIRED));
+                        } else if address.to_string().chars().count() > MAX_ADDRESS_LEN {
+                            errors.push(FieldError::new(field_path(prefix, "address"), TOO_LONG));
+                        }
+                    }
+                    None => {
+                        let address = self.delivery_address.as_deref().unwrap_or_default();
+                        check_text(address, MAX_ADDRESS_LEN, field_path(prefix, "delivery_address"), errors);
+                    }
+                }
+                if let Some(location) = &self.location {
+                    location.validate_into(&field_path(prefix, "location"), errors);
+                }
+            }
+            Fulfilment::Pickup => {}
+            Fulfilment::DineIn { table_number } => {
+                check_text(table_number, MAX_TABLE_NUMBER_LEN, field_path(prefix, "fulfilment.table_number"), errors);
+            }
+        }
+
+        if let Some(notes) = &self.notes
+            && notes.chars().count() > MAX_NOTES_LEN {
+            errors.push(FieldError::new(field_path(prefix, "notes"), TOO_LONG));
+        }
+
+        // 校验订单商品
+        check_items(&self.items, field_path(prefix, "items"), errors);
+
+        let delivery_fee = self.delivery_fee.unwrap_or_default();
+        if delivery_fee.is_negative() {
+            errors.push(FieldError::new(field_path(prefix, "delivery_fee"), OUT_OF_RANGE));
+        }
+
+        // 校验订单总金额与商品小计加外送费是否一致
+        let total_field = field_path(prefix, "total_amount");
+        if self.total_amount.is_negative() {
+            errors.push(FieldError::new(total_field, OUT_OF_RANGE));
+        } else {
+            let expected: Money = self.items.iter().map(|item| item.price * item.quantity).sum::<Money>() + delivery_fee;
+            if expected != self.total_amount {
+                errors.push(FieldError::new(total_field, MISMATCH));
+            }
+        }
+    }
+}
+
+impl Validate for CancelOrderRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        if self.token.trim().is_empty() {
+            errors.push(FieldError::new(field_path(prefix, "token"), REQUIRED));
+        }
+        check_reason(&self.reason, field_path(prefix, "reason"), errors);
+    }
+}
+
+impl Validate for RefundOrderRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        if let Some(amount) = self.amount
+            && amount <= Money::ZERO {
+            errors.push(FieldError::new(field_path(prefix, "amount"), OUT_OF_RANGE));
+        }
+        check_reason(&self.reason, field_path(prefix, "reason"), errors);
+    }
+}
+
+impl Validate for PauseOrderingRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        check_reason(&self.reason, field_path(prefix, "reason"), errors);
+    }
+}
+
+impl Validate for CreateCourierRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        check_text(&self.name, MAX_NAME_LEN, field_path(prefix, "name"), errors);
+        check_phone(&self.phone_number, field_path(prefix, "phone_number"), errors);
+    }
+}
+
+impl Validate for BatchQuery {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        if self.max_orders.is_some_and(|n| !(1..=MAX_BATCH_ORDERS).contains(&n)) {
+            errors.push(FieldError::new(field_path(prefix, "max_orders"), OUT_OF_RANGE));
+        }
+        if self.max_distance_km.is_some_and(|d| !(d > 0.0 && d <= MAX_BATCH_DISTANCE_KM)) {
+            errors.push(FieldError::new(field_path(prefix, "max_distance_km"), OUT_OF_RANGE));
+        }
+    }
+}
+
+impl Validate for AmendOrderRequest {
+    fn validate_into(&self, prefix: &str, errors: &mut Vec<FieldError>) {
+        check_items(&self.items, field_path(prefix, "items"), errors);
+        check_reason(&self.reason, field_path(prefix, "reason"), errors);
+    }
+}
+
+/**
+ * 单元测试模块
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+
+    fn valid_request() -> CreateOrderRequest {
+        CreateOrderRequest {
+            customer_name: "张三".to_string(),
+            phone_number: "138 0013 8000".to_string(),
+            fulfilment: Fulfilment::Delivery,
+            delivery_address: Some("北京市海淀区中关村大街1号".to_string()),
+            address: None,
+            location: Some(Location { lat: 39.98, lng: 116.31 }),
+            notes: None,
+            delivery_fee: None,
+            total_amount: Money::from_cents(4550),
+            items: vec![
+                OrderItemRequest { name: "拿铁 (大杯)".to_string(), quantity: 3, price: Money::from_cents(350) },
+                OrderItemRequest { name: "美式".to_string(), quantity: 1, price: Money::from_cents(3500) },
+            ],
+            requested_time: None,
+        }
+    }
+
+    #[test]
+    fn test_valid_request() {
+        assert_eq!(valid_request().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_request_reports_each_field() {
+        let mut req = valid_request();
+        req.customer_name = "  ".to_string();
+        req.phone_number = "abc123".to_string();
+        req.location.as_mut().unwrap().lat = 91.0;
+        req.items[1].quantity = 0;
+
+        let errors = req.validate().unwrap_err();
+        assert_eq!(errors, vec![
+            FieldError::new("customer_name", REQUIRED),
+            FieldError::new("phone_number", INVALID_FORMAT),
+            FieldError::new("location.lat", OUT_OF_RANGE),
+            FieldError::new("items[1].quantity", OUT_OF_RANGE),
+            FieldError::new("total_amount", MISMATCH),
+        ]);
+    }
+
+    #[test]
+    fn test_total_includes_delivery_fee() {
+        let mut req = valid_request();
+        req.delivery_fee = Some(Money::from_cents(500));
+        assert_eq!(req.validate().unwrap_err(), vec![FieldError::new("total_amount", MISMATCH)]);
+
+        req.total_amount = Money::from_cents(5050);
+        assert_eq!(req.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_fulfilment_requirements() {
+        // 自取订单不需要地址和坐标
+        let mut req = valid_request();
+        req.fulfilment = Fulfilment::Pickup;
+        req.delivery_address = None;
+        req.location = None;
+        assert_eq!(req.validate(), Ok(()));
+
+        req.fulfilment = Fulfilment::DineIn { table_number: " ".to_string() };
+        assert_eq!(req.validate().unwrap_err(), vec![FieldError::new("fulfilment.table_number", REQUIRED)]);
+
+        req.fulfilment = Fulfilment::Delivery;
+        assert_eq!(req.validate().unwrap_err(), vec![FieldError::new("delivery_address", REQUIRED)]);
+
+        // 结构化地址优先于地址文本，但必须包含街道或楼栋
+        req.address = Some(Address { city: Some("北京市".to_string()), room: Some("1201".to_string()), ..Default::default() });
+        assert_eq!(req.validate().unwrap_err(), vec![FieldError::new("address.street", REQUIRED)]);
+        req.address.as_mut().unwrap().street = Some(" 中关村大街1号 ".to_string());
+        assert_eq!(req.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_items() {
+        let mut req = valid_request();
+        req.items.clear();
+        req.total_amount = Money::ZERO;
+        assert_eq!(req.validate().unwrap_err(), vec![FieldError::new("items", REQUIRED)]);
+    }
+}
@@ -3,12 +3,87 @@
  * 处理订单提交流程，包括收集用户信息、地址选择和订单确认
  */
 
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { useNavigate } from 'react-router-dom';
 import { useDrinkContext } from './DrinkContext';
 import AddressSelector from './AddressSelector';
+import { saveOrderToken } from './orderToken';
 import './Checkout.css';
 
+// 后端校验字段对应的中文名称
+const FIELD_LABELS = {
+  customer_name: '姓名',
+  phone_number: '电话号码',
+  delivery_address: '配送地址',
+  'location.lat': '配送地址纬度',
+  'location.lng': '配送地址经度',
+  notes: '备注',
+  items: '订单商品',
+  total_amount: '订单总金额',
+  requested_time: '预约送达时间',
+  delivery_fee: '外送费',
+  location: '配送地址坐标',
+  'fulfilment.table_number': '桌号',
+};
+
+// 取餐方式选项
+const FULFILMENT_OPTIONS = [
+  { value: 'delivery', label: '外卖配送' },
+  { value: 'pickup', label: '到店自取' },
+  { value: 'dine_in', label: '堂食' },
+];
+
+// 后端校验错误代码对应的提示
+const ERROR_MESSAGES = {
+  required: '不能为空',
+  too_long: '过长',
+  invalid_format: '格式不正确',
+  out_of_range: '超出有效范围',
+  mismatch: '与商品小计不一致',
+  outside_opening_hours: '不在营业时间内',
+  outside_delivery_zone: '不在配送范围内',
+};
+
+/**
+ * 将后端返回的字段错误转换为提示文本
+ * @param {Array} errors - 字段错误列表，每项包含field和code
+ * @returns {string} 提示文本
+ */
+const formatFieldErrors = (errors) => errors.map(({ field, code }) => {
+  const label = FIELD_LABELS[field] || FIELD_LABELS[field.replace(/\[\d+\]\..*$/, '')] || field;
+  return `${label}${ERROR_MESSAGES[code] || '无效'}`;
+}).join('\n');
+
+/**
+ * 获取外送费报价，同时检查坐标是否在配送范围内
+ * @param {Object} location - 包含经纬度的位置对象
+ * @returns {Promise<Object|null>} 报价结果，请求失败时返回null（以提交订单时的校验为准）
+ */
+const quoteDelivery = async (location) => {
+  try {
+    const response = await fetch(`/api/delivery/quote?lat=${location.lat}&lng=${location.lng}`);
+    return response.ok ? await response.json() : null;
+  } catch (err) {
+    console.error('获取外送费报价失败:', err);
+    return null;
+  }
+};
+
+/**
+ * 从服务器的地址坐标缓存中查询地址坐标
+ * @param {string} address - 地址文本
+ * @returns {Promise<Object|null>} 缓存的坐标，未缓存或请求失败时返回null
+ */
+const lookupCachedLocation = async (address) => {
+  try {
+    const response = await fetch(`/api/addresses/geocode?address=${encodeURIComponent(address)}`);
+    return response.ok ? (await response.json()).location || null : null;
+  } catch (err) {
+    console.error('查询地址坐标失败:', err);
+    return null;
+  }
+};
+
 /**
  * 提交订单到后端服务器
  * @param {Object} orderData - 订单数据
@@ -24,6 +99,24 @@ const submitOrder = async (orderData) => {
     body: JSON.stringify(orderData)
   });
 
+  if (response.status === 409) {
+    const data = await response.json().catch(() => ({}));
+    if (data.code === 'store_closed') {
+      const error = new Error('门店暂不接单，请稍后再试或预约营业时间内的订单');
+      error.isValidation = true;
+      throw error;
+    }
+  }
+
+  if (response.status === 400) {
+    const data = await response.json().catch(() => ({}));
+    if (data.errors && data.errors.length) {
+      const error = new Error(formatFieldErrors(data.errors));
+      error.isValidation = true;
+      throw error;
+    }
+  }
+
   if (!response.ok) {
     throw new Error('提交订单失败');
   }
@@ -39,7 +132,6 @@ const Checkout = () => {
   // 从Context中获取购物车相关状态和方法
   const { 
     cart,
-    getCartTotal,
     deliveryAddress,
     deliveryLocation,
     updateDeliveryAddress,
@@ -51,8 +143,50 @@ const Checkout = () => {
   const [customerName, setCustomerName] = useState('');
   const [phoneNumber, setPhoneNumber] = useState('');
   const [notes, setNotes] = useState('');
+  const [requestedTime, setRequestedTime] = useState(''); // 预约送达时间，为空表示尽快送达
+  const [fulfilment, setFulfilment] = useState('delivery'); // 取餐方式
+  const [tableNumber, setTableNumber] = useState(''); // 堂食桌号
   const [isSubmitting, setIsSubmitting] = useState(false);
+  const [storeStatus, setStoreStatus] = useState(null); // 门店营业状态
+  const [deliveryQuote, setDeliveryQuote] = useState(null); // 外送费报价
   const navigate = useNavigate();
+
+  // 获取门店营业状态，休息或暂停接单时提示顾客
+  useEffect(() => {
+    fetch('/api/store/status')
+      .then(response => (response.ok ? response.json() : null))
+      .then(setStoreStatus)
+      .catch(error => console.error('获取门店状态失败:', error));
+  }, []);
+
+  // 地图组件未返回坐标时使用服务器缓存的坐标
+  useEffect(() => {
+    if (!deliveryAddress || (deliveryLocation && deliveryLocation.lat && deliveryLocation.lng)) {
+      return;
+    }
+    let cancelled = false;
+    lookupCachedLocation(deliveryAddress).then(location => {
+      if (!cancelled && location) {
+        updateDeliveryAddress(deliveryAddress, location);
+      }
+    });
+    return () => { cancelled = true; };
+  }, [deliveryAddress, deliveryLocation, updateDeliveryAddress]);
+
+  // 选择地址后获取外送费报价
+  useEffect(() => {
+    setDeliveryQuote(null);
+    if (deliveryLocation && deliveryLocation.lat && deliveryLocation.lng) {
+      quoteDelivery(deliveryLocation).then(setDeliveryQuote);
+    }
+  }, [deliveryLocation]);
+
+  const isPaused = Boolean(storeStatus?.paused);
+  const isOutsideZone = fulfilment === 'delivery' && deliveryQuote?.deliverable === false;
+  // 仅外送订单收取外送费
+  const deliveryFee = fulfilment === 'delivery' ? (deliveryQuote?.delivery_fee || 0) : 0;
+  const itemsTotal = cart.reduce((sum, item) => sum + item.totalPrice * item.quantity, 0);
+  const isClosed = Boolean(storeStatus && !storeStatus.open);
   
   /**
    * 处理地址选择
@@ -80,14 +214,27 @@ const Checkout = () => {
       alert('请输入电话号码');
       return;
     }
-    if (!deliveryAddress) {
+    if (isClosed && !requestedTime) {
+      alert('门店休息中，请选择营业时间内的预约时间');
+      return;
+    }
+    const isDelivery = fulfilment === 'delivery';
+    if (isDelivery && !deliveryAddress) {
       alert('请选择配送地址');
       return;
     }
-    if (!deliveryLocation || !deliveryLocation.lat || !deliveryLocation.lng) {
+    if (isDelivery && (!deliveryLocation || !deliveryLocation.lat || !deliveryLocation.lng)) {
       alert('请选择有效的配送地址');
       return;
     }
+    if (isOutsideZone) {
+      alert('该地址不在配送范围内，请更换地址或选择到店自取');
+      return;
+    }
+    if (fulfilment === 'dine_in' && !tableNumber.trim()) {
+      alert('请输入桌号');
+      return;
+    }
 
     setIsSubmitting(true);
 
@@ -99,28 +246,35 @@ const Checkout = () => {
           quantity: item.quantity,
           price: item.totalPrice
         })),
-        total_amount: cart.reduce((sum, item) => sum + item.totalPrice * item.quantity, 0),
+        delivery_fee: deliveryFee || undefined,
+        total_amount: Math.round((itemsTotal + deliveryFee) * 100) / 100,
         customer_name: customerName,
         phone_number: phoneNumber,
-        delivery_address: deliveryAddress,
-        location: {
+        fulfilment: fulfilment === 'dine_in'
+          ? { type: 'dine_in', table_number: tableNumber.trim() }
+          : { type: fulfilment },
+        // 仅外卖配送需要地址
+        delivery_address: isDelivery ? deliveryAddress : undefined,
+        location: isDelivery ? {
           lat: deliveryLocation.lat,
           lng: deliveryLocation.lng
-        },
-        notes: notes || undefined
+        } : undefined,
+        notes: notes || undefined,
+        requested_time: requestedTime || undefined
       };
 
       // 提交订单并处理响应
       const response = await submitOrder(orderData);
 
       if (response.success) {
+        saveOrderToken(response.order_number, response.lookup_token);
         clearCart();
         setShowCheckout(false);
         navigate(`/order/${response.order_number}`);
       }
     } catch (error) {
       console.error('提交订单失败:', error);
-      alert('提交订单时出错，请重试');
+      alert(error.isValidation ? error.message : '提交订单时出错，请重试');
     } finally {
       setIsSubmitting(false);
     }
@@ -146,27 +300,79 @@ const Checkout = () => {
         </div>
         
         <div className="checkout-content">
+          {/* 门店休息或暂停接单提示 */}
+          {isPaused && (
+            <div className="store-notice">
+              门店已暂停接单{storeStatus.pause_reason ? `：${storeStatus.pause_reason}` : ''}
+            </div>
+          )}
+          {!isPaused && isClosed && (
+            <div className="store-notice">
+              门店休息中
+              {storeStatus.opens_at && `，${new Date(storeStatus.opens_at).toLocaleString('zh-CN')} 开始营业`}
+              ，可预约营业时间内的订单
+            </div>
+          )}
+
           <form onSubmit={handleSubmit}>
             {/* 配送信息部分 */}
             <div className="checkout-section">
               <h3>配送信息</h3>
-              
-              {/* 地址选择器 */}
+
+              {/* 取餐方式选择 */}
               <div className="form-group">
-                <label>配送地址</label>
-                <AddressSelector onSelect={handleAddressSelect} />
-                {deliveryAddress && (
-                  <div className="selected-address">
-                    <div>{deliveryAddress}</div>
-                    {deliveryLocation && (
-                      <div className="address-coordinates">
-                        {formatLocation(deliveryLocation)}
-                      </div>
-                    )}
-                  </div>
-                )}
+                <label>取餐方式</label>
+                <div className="fulfilment-options">
+                  {FULFILMENT_OPTIONS.map(option => (
+                    <label key={option.value} className="fulfilment-option">
+                      <input
+                        type="radio"
+                        name="fulfilment"
+                        value={option.value}
+                        checked={fulfilment === option.value}
+                        onChange={(e) => setFulfilment(e.target.value)}
+                      />
+                      {option.label}
+                    </label>
+                  ))}
+                </div>
               </div>
               
+              {/* 地址选择器，仅外卖配送需要 */}
+              {fulfilment === 'delivery' && (
+                <div className="form-group">
+                  <label>配送地址</label>
+                  <AddressSelector onSelect={handleAddressSelect} />
+                  {deliveryAddress && (
+                    <div className="selected-address">
+                      <div>{deliveryAddress}</div>
+                      {deliveryLocation && (
+                        <div className="address-coordinates">
+                          {formatLocation(deliveryLocation)}
+                        </div>
+                      )}
+                      {isOutsideZone && (
+                        <div className="address-warning">该地址不在配送范围内</div>
+                      )}
+                    </div>
+                  )}
+                </div>
+              )}
+
+              {fulfilment === 'dine_in' && (
+                <div className="form-group">
+                  <label htmlFor="tableNumber">桌号</label>
+                  <input
+                    type="text"
+                    id="tableNumber"
+                    value={tableNumber}
+                    onChange={(e) => setTableNumber(e.target.value)}
+                    placeholder="餐桌上的桌号"
+                    maxLength={10}
+                  />
+                </div>
+              )}
+              
               {/* 用户信息输入 */}
               <div className="form-group">
                 <label htmlFor="name">姓名</label>
@@ -192,6 +398,16 @@ const Checkout = () => {
                 />
               </div>
               
+              <div className="form-group">
+                <label htmlFor="requestedTime">{fulfilment === 'delivery' ? '预约送达时间（可选）' : '预约取餐时间（可选）'}</label>
+                <input
+                  type="datetime-local"
+                  id="requestedTime"
+                  value={requestedTime}
+                  onChange={(e) => setRequestedTime(e.target.value)}
+                />
+              </div>
+
               <div className="form-group">
                 <label htmlFor="notes">备注</label>
                 <textarea
@@ -227,10 +443,23 @@ const Checkout = () => {
                   ))}
                 </div>
                 
+                {/* 外送费 */}
+                {fulfilment === 'delivery' && deliveryQuote?.deliverable && (
+                  <div className="summary-item">
+                    <div className="summary-item-name">
+                      外送费
+                      {deliveryQuote.distance_km !== undefined && (
+                        <div className="summary-item-options">距离约 {deliveryQuote.distance_km} 公里</div>
+                      )}
+                    </div>
+                    <div className="summary-item-price">¥{deliveryFee.toFixed(2)}</div>
+                  </div>
+                )}
+
                 {/* 订单总价 */}
                 <div className="order-total">
                   <span>总计:</span>
-                  <span>¥{getCartTotal()}</span>
+                  <span>¥{(itemsTotal + deliveryFee).toFixed(2)}</span>
                 </div>
               </div>
               
@@ -238,7 +467,7 @@ const Checkout = () => {
               <button
                 type="submit"
                 className="submit-order-btn"
-                disabled={isSubmitting}
+                disabled={isSubmitting || isPaused}
               >
                 {isSubmitting ? '提交中...' : '提交订单'}
               </button>
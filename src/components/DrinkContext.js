@@ -4,7 +4,7 @@
  * 包含购物车、饮品列表、分类、订单等状态的管理
  */
 
-import React, { createContext, useState, useContext } from 'react';
+import React, { createContext, useState, useContext, useCallback } from 'react';
 
 // 创建Context实例
 const DrinkContext = createContext();
@@ -103,13 +103,14 @@ export const DrinkProvider = ({ children }) => {
 
   /**
    * 更新配送地址信息
+   * 使用稳定的函数引用，便于在副作用的依赖中使用
    * @param {string} address - 配送地址
    * @param {Object} location - 地址坐标
    */
-  const updateDeliveryAddress = (address, location) => {
+  const updateDeliveryAddress = useCallback((address, location) => {
     setDeliveryAddress(address);
     setDeliveryLocation(location);
-  };
+  }, []);
   
   /**
    * 开始结账流程
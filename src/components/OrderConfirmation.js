@@ -6,19 +6,27 @@
 
 import React, { useState, useEffect } from 'react';
 import { Link, useParams } from 'react-router-dom';
+import { getOrderToken } from './orderToken';
+import { formatFulfilment } from './fulfilment';
 import './OrderConfirmation.css';
 
 // 订单状态刷新间隔（毫秒）
 const REFRESH_INTERVAL = 10000; // 每10秒刷新一次
 
+// 客户可以尝试自助取消的订单状态（制作中的订单仅在宽限期内可取消，由服务器判断）
+const CANCELLABLE_STATUSES = ['awaiting_payment', 'pending', 'preparing'];
+
 /**
  * 获取订单详情的API调用
+ * 下单的浏览器保存了令牌时返回完整订单，否则只返回订单状态
  * @param {string} orderNumber - 订单号
  * @returns {Promise} 包含订单详情的Promise
  * @throws {Error} 当订单不存在或API调用失败时抛出错误
  */
 const fetchOrderDetails = async (orderNumber) => {
-  const response = await fetch(`/api/orders/${orderNumber}`);
+  const token = getOrderToken(orderNumber);
+  const query = token ? `?token=${encodeURIComponent(token)}` : '';
+  const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}${query}`);
   
   if (!response.ok) {
     if (response.status === 404) {
@@ -30,6 +38,72 @@ const fetchOrderDetails = async (orderNumber) => {
   return response.json();
 };
 
+/**
+ * 取消订单的API调用
+ * @param {string} orderNumber - 订单号
+ * @param {string} token - 下单时返回的查询令牌
+ * @param {string} reason - 取消原因
+ * @returns {Promise} 包含取消后订单的Promise
+ * @throws {Error} 当订单不能取消或API调用失败时抛出错误
+ */
+const cancelOrder = async (orderNumber, token, reason) => {
+  const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/cancel`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({ token, reason: reason || undefined }),
+  });
+
+  if (!response.ok) {
+    const body = await response.json().catch(() => ({}));
+    if (body.code === 'invalid_order_state') {
+      throw new Error('订单已开始制作，无法取消，请联系门店');
+    }
+    throw new Error('取消订单失败，请重试');
+  }
+
+  return response.json();
+};
+
+/**
+ * 发起订单支付的API调用
+ * 有支付页面时跳转到支付页面；模拟支付网关没有支付页面，直接模拟支付成功
+ * @param {string} orderNumber - 订单号
+ * @param {string} token - 下单时返回的查询令牌
+ * @returns {Promise<boolean>} 是否已完成支付（跳转到支付页面时为false）
+ * @throws {Error} 当发起支付失败时抛出错误
+ */
+const payOrder = async (orderNumber, token) => {
+  const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/payment`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({ token }),
+  });
+  if (!response.ok) {
+    throw new Error('发起支付失败，请重试');
+  }
+
+  const { payment, pay_url: payUrl } = await response.json();
+  if (payUrl) {
+    window.location.href = payUrl;
+    return false;
+  }
+  const result = await fetch(`/api/payments/mock/${encodeURIComponent(payment.reference)}`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({ succeeded: true }),
+  });
+  if (!result.ok) {
+    throw new Error('支付失败，请重试');
+  }
+  return true;
+};
+
 const OrderConfirmation = () => {
   // 从URL参数中获取订单号
   const { orderNumber } = useParams();
@@ -38,6 +112,8 @@ const OrderConfirmation = () => {
   const [orderDetails, setOrderDetails] = useState(null); // 订单详情
   const [loading, setLoading] = useState(true); // 加载状态
   const [error, setError] = useState(null); // 错误信息
+  const [cancelling, setCancelling] = useState(false); // 取消请求进行中
+  const [paying, setPaying] = useState(false); // 支付请求进行中
 
   /**
    * 获取订单详情
@@ -79,6 +155,44 @@ const OrderConfirmation = () => {
     return () => clearInterval(intervalId);
   }, [orderNumber, orderDetails?.status]);
 
+  /**
+   * 处理客户取消订单
+   * 确认后提交取消原因，成功后更新订单详情
+   */
+  const handleCancel = async () => {
+    const reason = window.prompt('确定要取消订单吗？请填写取消原因（可选）');
+    if (reason === null) {
+      return;
+    }
+
+    setCancelling(true);
+    try {
+      const result = await cancelOrder(orderDetails.order_number, getOrderToken(orderDetails.order_number), reason.trim());
+      setOrderDetails(result.order);
+    } catch (err) {
+      alert(err.message);
+    } finally {
+      setCancelling(false);
+    }
+  };
+
+  /**
+   * 处理客户支付订单
+   * 支付完成后重新获取订单详情
+   */
+  const handlePay = async () => {
+    setPaying(true);
+    try {
+      if (await payOrder(orderDetails.order_number, getOrderToken(orderDetails.order_number))) {
+        setOrderDetails(await fetchOrderDetails(orderDetails.order_number));
+      }
+    } catch (err) {
+      alert(err.message);
+    } finally {
+      setPaying(false);
+    }
+  };
+
   /**
    * 格式化日期显示
    * @param {string} dateString - ISO格式的日期字符串
@@ -95,6 +209,8 @@ const OrderConfirmation = () => {
    */
   const getStatusMessage = (status) => {
     switch (status) {
+      case 'awaiting_payment':
+        return '您的订单等待支付';
       case 'pending':
         return '您的订单正在排队中';
       case 'preparing':
@@ -110,6 +226,25 @@ const OrderConfirmation = () => {
     }
   };
 
+  /**
+   * 获取订单退款状态的提示文本
+   * @param {Object} order - 订单详情
+   * @returns {string} 退款提示文本
+   */
+  const getRefundMessage = (order) => {
+    const amount = `¥${order.refunded_amount.toFixed(2)}`;
+    switch (order.refund_status) {
+      case 'refunded':
+        return `已全额退款 ${amount}，将原路退回`;
+      case 'partially_refunded':
+        return `已部分退款 ${amount}，将原路退回`;
+      case 'failed':
+        return '退款处理中，如有疑问请联系门店';
+      default:
+        return '';
+    }
+  };
+
   /**
    * 获取订单状态对应的CSS类名
    * @param {string} status - 订单状态
@@ -117,6 +252,7 @@ const OrderConfirmation = () => {
    */
   const getStatusClass = (status) => {
     switch (status) {
+      case 'awaiting_payment':
       case 'pending':
         return 'status-queuing';
       case 'preparing':
@@ -168,14 +304,47 @@ const OrderConfirmation = () => {
           <div className="order-header">
             <p>订单号：</p>
             <h3>{orderDetails.order_number}</h3>
+            <p className="order-short-code">取餐码：<strong>{orderDetails.short_code}</strong></p>
             <p className="order-date">下单时间：{formatDate(orderDetails.created_at)}</p>
+            <p className="order-date">取餐方式：{formatFulfilment(orderDetails.fulfilment)}</p>
+            {orderDetails.requested_time && (
+              <p className="order-date">
+                {orderDetails.fulfilment?.type === 'delivery' ? '预约送达' : '预约取餐'}：{formatDate(orderDetails.requested_time)}
+              </p>
+            )}
+            {/* 进行中订单的预计时间，随订单状态刷新 */}
+            {orderDetails.estimated_arrival_at && !['completed', 'cancelled'].includes(orderDetails.status) && (
+              <p className="order-date">
+                {orderDetails.fulfilment?.type === 'delivery' ? '预计送达' : '预计可取餐'}：{formatDate(orderDetails.estimated_arrival_at)}
+              </p>
+            )}
+            {/* 配送中的订单显示配送员最新位置 */}
+            {orderDetails.status === 'delivering' && orderDetails.courier_location && (
+              <p className="order-date">
+                配送员位置（{formatDate(orderDetails.courier_location.updated_at)} 更新）：
+                <a
+                  href={`https://uri.amap.com/marker?position=${orderDetails.courier_location.lng},${orderDetails.courier_location.lat}&name=${encodeURIComponent('配送员位置')}`}
+                  target="_blank"
+                  rel="noopener noreferrer"
+                >
+                  在地图中查看
+                </a>
+              </p>
+            )}
             {/* 订单状态显示 */}
             <div className={`order-status ${getStatusClass(orderDetails.status)}`}>
               {getStatusMessage(orderDetails.status)}
             </div>
+            {orderDetails.cancel_reason && (
+              <p className="cancel-reason">取消原因：{orderDetails.cancel_reason}</p>
+            )}
+            {orderDetails.refund_status && (
+              <p className="refund-status">{getRefundMessage(orderDetails)}</p>
+            )}
           </div>
           
-          {/* 订单商品列表 */}
+          {/* 订单商品列表，仅返回完整订单时显示 */}
+          {orderDetails.items && (
           <div className="order-items">
             <h4>订单明细</h4>
             {orderDetails.items.map((item, index) => (
@@ -185,13 +354,33 @@ const OrderConfirmation = () => {
                 <span className="item-price">¥{(item.price * item.quantity).toFixed(2)}</span>
               </div>
             ))}
+            {orderDetails.delivery_fee > 0 && (
+              <div className="order-item">
+                <span className="item-name">外送费</span>
+                <span className="item-quantity"></span>
+                <span className="item-price">¥{orderDetails.delivery_fee.toFixed(2)}</span>
+              </div>
+            )}
             {/* 订单总价 */}
             <div className="order-total">
               <strong>总计：</strong>
               <span>¥{orderDetails.total_amount.toFixed(2)}</span>
             </div>
           </div>
+          )}
         </div>
+        {/* 支付按钮，仅下单的浏览器保存了令牌时显示 */}
+        {orderDetails.status === 'awaiting_payment' && getOrderToken(orderDetails.order_number) && (
+          <button className="pay-order-btn" onClick={handlePay} disabled={paying}>
+            {paying ? '支付中...' : '去支付'}
+          </button>
+        )}
+        {/* 取消订单按钮，仅下单的浏览器保存了令牌时显示 */}
+        {CANCELLABLE_STATUSES.includes(orderDetails.status) && getOrderToken(orderDetails.order_number) && (
+          <button className="cancel-order-btn" onClick={handleCancel} disabled={cancelling}>
+            {cancelling ? '取消中...' : '取消订单'}
+          </button>
+        )}
         {/* 返回首页链接 */}
         <Link to="/" className="back-home-btn">
           返回首页
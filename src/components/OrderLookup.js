@@ -28,17 +28,18 @@ const OrderLookup = ({ isOpen, onClose }) => {
     e.preventDefault();
     // 验证订单号是否为空
     if (!orderNumber.trim()) {
-      setError('请输入订单号');
+      setError('请输入订单号或取餐码');
       return;
     }
 
     try {
       // 调用API查询订单
-      const response = await fetch(`/api/orders/${orderNumber.trim()}`);
+      const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber.trim())}`);
       if (response.ok) {
-        // 查询成功，关闭弹窗并跳转到订单详情页
+        // 查询成功，关闭弹窗并按订单号跳转到订单详情页（输入的可能是取餐码）
+        const order = await response.json();
         onClose();
-        navigate(`/order/${orderNumber.trim()}`);
+        navigate(`/order/${order.order_number}`);
       } else {
         // 未找到订单
         setError('未找到该订单');
@@ -62,7 +63,7 @@ const OrderLookup = ({ isOpen, onClose }) => {
         {/* 查询表单 */}
         <form onSubmit={handleSubmit}>
           <div className="form-group">
-            <label htmlFor="orderNumber">订单号</label>
+            <label htmlFor="orderNumber">订单号或取餐码</label>
             <input
               type="text"
               id="orderNumber"
@@ -71,7 +72,7 @@ const OrderLookup = ({ isOpen, onClose }) => {
                 setOrderNumber(e.target.value);
                 setError(''); // 输入时清除错误信息
               }}
-              placeholder="请输入订单号"
+              placeholder="请输入订单号或取餐码"
               autoFocus // 自动获取焦点
             />
             {/* 错误信息显示 */}
@@ -1,12 +1,23 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import OrderList from './OrderList';
 import OrderDetail from './OrderDetail';
+import StoreControl from './StoreControl';
+import CourierControl from './CourierControl';
+import DeliveryBatches from './DeliveryBatches';
 import './AdminPanel.css';
 
 const AdminPanel = () => {
   const [selectedOrder, setSelectedOrder] = useState(null);
   const [filterStatus, setFilterStatus] = useState('all');
   const [refreshTrigger, setRefreshTrigger] = useState(0);
+  const [couriers, setCouriers] = useState([]); // 可指派的配送员
+
+  useEffect(() => {
+    fetch('/api/couriers')
+      .then(response => (response.ok ? response.json() : []))
+      .then(setCouriers)
+      .catch(error => console.error('获取配送员列表失败:', error));
+  }, []);
 
   const handleOrderSelect = (order) => {
     setSelectedOrder(order);
@@ -16,9 +27,9 @@ const AdminPanel = () => {
     setFilterStatus(status);
   };
 
-  const handleStatusUpdate = async (orderId, newStatus) => {
+  const handleStatusUpdate = async (orderNumber, newStatus) => {
     try {
-      const response = await fetch(`/api/orders/${orderId}/status`, {
+      const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/status`, {
         method: 'PUT',
         headers: {
           'Content-Type': 'application/json',
@@ -40,10 +51,107 @@ const AdminPanel = () => {
     }
   };
 
+  const handleItemsAmend = async (orderNumber, items, reason) => {
+    try {
+      const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/items`, {
+        method: 'PUT',
+        headers: {
+          'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ items, reason: reason || undefined }),
+      });
+
+      const body = await response.json().catch(() => ({}));
+      if (!response.ok) {
+        if (body.code === 'invalid_order_state') {
+          alert('订单已开始制作不能再修改，已支付的订单不能改变总金额');
+        } else if (body.code === 'validation_failed') {
+          alert('请检查商品名称（新增商品须为菜单中的饮品）和数量（1-99）');
+        } else {
+          alert('修改订单商品时出错');
+        }
+        return false;
+      }
+
+      // 显示服务器重新计算后的订单并刷新列表
+      setSelectedOrder(body.order);
+      setRefreshTrigger(prev => prev + 1);
+      return true;
+    } catch (error) {
+      console.error('修改订单商品失败:', error);
+      alert('修改订单商品时出错');
+      return false;
+    }
+  };
+
+  const handleRefund = async (orderNumber, amount, reason) => {
+    try {
+      const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/refunds`, {
+        method: 'POST',
+        headers: {
+          'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ amount: amount ?? undefined, reason: reason || undefined }),
+      });
+
+      const body = await response.json().catch(() => ({}));
+      if (!response.ok) {
+        if (body.code === 'payments_disabled') {
+          alert('未启用在线支付，无法退款');
+        } else if (body.code === 'invalid_order_state') {
+          alert('订单没有可退款的支付');
+        } else if (body.code === 'validation_failed') {
+          alert('退款金额需大于0且不超过可退金额');
+        } else {
+          alert('退款时出错');
+        }
+        return false;
+      }
+
+      // 服务商拒绝退款时保留失败记录，可稍后重试
+      if (body.refunds.some(refund => refund.status === 'failed')) {
+        alert('支付服务商拒绝退款，请稍后重试');
+      }
+      setSelectedOrder(body.order);
+      setRefreshTrigger(prev => prev + 1);
+      return true;
+    } catch (error) {
+      console.error('退款失败:', error);
+      alert('退款时出错');
+      return false;
+    }
+  };
+
+  const handleCourierAssign = async (orderNumber, courierId) => {
+    try {
+      const response = await fetch(`/api/orders/${encodeURIComponent(orderNumber)}/courier`, {
+        method: 'PUT',
+        headers: {
+          'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ courier_id: courierId }),
+      });
+
+      const body = await response.json().catch(() => ({}));
+      if (!response.ok) {
+        alert(body.code === 'invalid_order_state' ? '订单已结束，不能再指派配送员' : '指派配送员时出错');
+        return;
+      }
+
+      setSelectedOrder(body.order);
+      setRefreshTrigger(prev => prev + 1);
+    } catch (error) {
+      console.error('指派配送员失败:', error);
+      alert('指派配送员时出错');
+    }
+  };
+
   return (
     <div className="admin-panel">
       <div className="admin-header">
         <h1>订单管理系统</h1>
+        <StoreControl />
+        <CourierControl onCourierCreated={(courier) => setCouriers(prev => [...prev, courier])} />
         <div className="status-filter">
           <label>订单状态：</label>
           <select 
@@ -51,6 +159,7 @@ const AdminPanel = () => {
             onChange={(e) => handleStatusChange(e.target.value)}
           >
             <option value="all">全部</option>
+            <option value="awaiting_payment">待支付</option>
             <option value="pending">待处理</option>
             <option value="preparing">制作中</option>
             <option value="delivering">配送中</option>
@@ -60,17 +169,27 @@ const AdminPanel = () => {
         </div>
       </div>
       
+      <DeliveryBatches
+        couriers={couriers}
+        refreshTrigger={refreshTrigger}
+        onBatchAssigned={() => setRefreshTrigger(prev => prev + 1)}
+      />
+
       <div className="admin-content">
         <OrderList 
           filterStatus={filterStatus}
           onOrderSelect={handleOrderSelect}
-          selectedOrderId={selectedOrder?.id}
+          selectedOrderNumber={selectedOrder?.order_number}
           refreshTrigger={refreshTrigger}
         />
         {selectedOrder && (
           <OrderDetail 
             order={selectedOrder}
             onStatusUpdate={handleStatusUpdate}
+            onItemsAmend={handleItemsAmend}
+            onRefund={handleRefund}
+            couriers={couriers}
+            onCourierAssign={handleCourierAssign}
             onClose={() => setSelectedOrder(null)}
           />
         )}
@@ -0,0 +1,72 @@
+import React, { useState } from 'react';
+
+/**
+ * 配送员管理
+ * 新增配送员，并显示仅返回一次的配送员访问令牌
+ */
+const CourierControl = ({ onCourierCreated }) => {
+  const [name, setName] = useState(''); // 配送员姓名
+  const [phoneNumber, setPhoneNumber] = useState(''); // 配送员电话
+  const [createdToken, setCreatedToken] = useState(null); // 新配送员的访问令牌
+  const [isSaving, setIsSaving] = useState(false);
+
+  const handleSubmit = async (e) => {
+    e.preventDefault();
+    setIsSaving(true);
+    try {
+      const response = await fetch('/api/couriers', {
+        method: 'POST',
+        headers: {
+          'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ name: name.trim(), phone_number: phoneNumber.trim() }),
+      });
+      const body = await response.json().catch(() => ({}));
+      if (!response.ok) {
+        alert(body.code === 'validation_failed' ? '请填写配送员姓名和有效的电话号码' : '新增配送员时出错');
+        return;
+      }
+      setCreatedToken({ name: body.courier.name, token: body.token });
+      setName('');
+      setPhoneNumber('');
+      onCourierCreated(body.courier);
+    } catch (error) {
+      console.error('新增配送员失败:', error);
+      alert('新增配送员时出错');
+    } finally {
+      setIsSaving(false);
+    }
+  };
+
+  return (
+    <div className="courier-control">
+      <form onSubmit={handleSubmit}>
+        <input
+          type="text"
+          value={name}
+          onChange={(e) => setName(e.target.value)}
+          placeholder="配送员姓名"
+          maxLength={50}
+        />
+        <input
+          type="tel"
+          value={phoneNumber}
+          onChange={(e) => setPhoneNumber(e.target.value)}
+          placeholder="电话"
+        />
+        <button type="submit" disabled={isSaving || !name.trim() || !phoneNumber.trim()}>
+          新增配送员
+        </button>
+      </form>
+      {/* 令牌只显示一次，需转交给配送员 */}
+      {createdToken && (
+        <div className="courier-token">
+          {createdToken.name}的访问令牌：<code>{createdToken.token}</code>
+          <button type="button" onClick={() => setCreatedToken(null)}>&times;</button>
+        </div>
+      )}
+    </div>
+  );
+};
+
+export default CourierControl;
@@ -0,0 +1,86 @@
+import React, { useEffect, useState } from 'react';
+
+/**
+ * 配送批次建议
+ * 显示按坐标就近分组的待配送订单及建议配送顺序，可将整批订单指派给同一配送员
+ */
+const DeliveryBatches = ({ couriers, refreshTrigger, onBatchAssigned }) => {
+  const [batches, setBatches] = useState([]); // 建议的配送批次
+  const [selectedCouriers, setSelectedCouriers] = useState({}); // 每批选择的配送员（按批次首单编号）
+  const [isAssigning, setIsAssigning] = useState(false);
+
+  useEffect(() => {
+    fetch('/api/delivery/batches')
+      .then(response => (response.ok ? response.json() : []))
+      .then(setBatches)
+      .catch(error => console.error('获取配送批次失败:', error));
+  }, [refreshTrigger]);
+
+  const assignBatch = async (batch) => {
+    const batchKey = batch.stops[0].order_number;
+    const courierId = Number(selectedCouriers[batchKey]);
+    if (!courierId) return;
+
+    setIsAssigning(true);
+    try {
+      // 按建议顺序逐单指派
+      for (const stop of batch.stops) {
+        const response = await fetch(`/api/orders/${encodeURIComponent(stop.order_number)}/courier`, {
+          method: 'PUT',
+          headers: {
+            'Content-Type': 'application/json',
+          },
+          body: JSON.stringify({ courier_id: courierId }),
+        });
+        if (!response.ok) {
+          throw new Error(`指派订单 ${stop.short_code} 失败`);
+        }
+      }
+      onBatchAssigned();
+    } catch (error) {
+      console.error('指派配送批次失败:', error);
+      alert('指派配送批次时出错，请刷新后重试');
+    } finally {
+      setIsAssigning(false);
+    }
+  };
+
+  if (batches.length === 0) return null;
+
+  return (
+    <div className="delivery-batches">
+      <h3>建议配送批次</h3>
+      {batches.map(batch => {
+        const batchKey = batch.stops[0].order_number;
+        return (
+          <div key={batchKey} className="delivery-batch">
+            <ol>
+              {batch.stops.map(stop => (
+                <li key={stop.order_number}>
+                  #{stop.short_code} {stop.customer_name} · {stop.delivery_address}（{stop.leg_km.toFixed(2)} 公里）
+                </li>
+              ))}
+            </ol>
+            <div className="batch-actions">
+              <span>全程约 {batch.total_distance_km.toFixed(2)} 公里</span>
+              <select
+                value={selectedCouriers[batchKey] || ''}
+                onChange={(e) => setSelectedCouriers(prev => ({ ...prev, [batchKey]: e.target.value }))}
+              >
+                <option value="">选择配送员</option>
+                {couriers.map(courier => (
+                  <option key={courier.id} value={courier.id}>{courier.name}</option>
+                ))}
+              </select>
+              <button onClick={() => assignBatch(batch)} disabled={isAssigning || !selectedCouriers[batchKey]}>
+                整批派送
+              </button>
+            </div>
+          </div>
+        );
+      })}
+    </div>
+  );
+};
+
+export default DeliveryBatches;
@@ -1,8 +1,23 @@
-import React from 'react';
+import React, { useEffect, useState } from 'react';
+import { formatFulfilment } from '../fulfilment';
 import './OrderDetail.css';
 
-const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
+const OrderDetail = ({ order, onStatusUpdate, onItemsAmend, onRefund, couriers, onCourierAssign, onClose }) => {
+  const [editing, setEditing] = useState(false); // 是否正在修改商品
+  const [draftItems, setDraftItems] = useState([]); // 修改中的商品列表
+  const [amendReason, setAmendReason] = useState(''); // 修改原因
+  const [refundAmount, setRefundAmount] = useState(''); // 退款金额（留空为全部可退金额）
+  const [refundReason, setRefundReason] = useState(''); // 退款原因
+
+  // 切换订单时退出修改状态并清空退款表单
+  useEffect(() => {
+    setEditing(false);
+    setRefundAmount('');
+    setRefundReason('');
+  }, [order.order_number]);
+
   const statusOptions = [
+    { value: 'awaiting_payment', label: '待支付' },
     { value: 'pending', label: '待处理' },
     { value: 'preparing', label: '制作中' },
     { value: 'delivering', label: '配送中' },
@@ -10,6 +25,61 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
     { value: 'cancelled', label: '已取消' }
   ];
 
+  // 各状态可以改为的状态，与服务器的状态流转规则一致
+  // 待支付的订单只能通过支付进入待处理，管理端只能取消；已完成和已取消的订单不能再改变状态
+  const nextStatuses = {
+    awaiting_payment: ['cancelled'],
+    pending: ['preparing', 'delivering', 'completed', 'cancelled'],
+    preparing: ['delivering', 'completed', 'cancelled'],
+    delivering: ['completed', 'cancelled'],
+    completed: [],
+    cancelled: []
+  };
+
+  const startEditing = () => {
+    setDraftItems(order.items.map(item => ({ ...item })));
+    setAmendReason('');
+    setEditing(true);
+  };
+
+  const updateDraftItem = (index, field, value) => {
+    setDraftItems(items => items.map((item, i) => (i === index ? { ...item, [field]: value } : item)));
+  };
+
+  const handleAmendSubmit = async () => {
+    const items = draftItems.map(item => ({
+      name: item.name.trim(),
+      quantity: Number(item.quantity),
+      // 单价由服务器确定，此处仅为满足请求格式
+      price: Number(item.price || 0)
+    }));
+    if (await onItemsAmend(order.order_number, items, amendReason.trim())) {
+      setEditing(false);
+    }
+  };
+
+  const refundStatusLabels = {
+    partially_refunded: '部分退款',
+    refunded: '已全额退款',
+    failed: '退款失败'
+  };
+
+  const handleRefundSubmit = async () => {
+    const amount = refundAmount.trim() === '' ? null : Number(refundAmount);
+    const label = amount === null ? '全部可退金额' : `¥${amount.toFixed(2)}`;
+    if (!window.confirm(`确定退款${label}吗？`)) {
+      return;
+    }
+    if (await onRefund(order.order_number, amount, refundReason.trim())) {
+      setRefundAmount('');
+      setRefundReason('');
+    }
+  };
+
+  // 修改中的预估总价（含外送费，不含按菜单计价的新增商品），最终以服务器重新计算的金额为准
+  const draftTotal = draftItems.reduce((sum, item) => sum + Number(item.price || 0) * Number(item.quantity), 0)
+    + (order.delivery_fee || 0);
+
   const formatDate = (dateString) => {
     const date = new Date(dateString);
     return new Intl.DateTimeFormat('zh-CN', {
@@ -25,7 +95,7 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
   return (
     <div className="order-detail">
       <div className="detail-header">
-        <h2>订单详情 #{order.order_number}</h2>
+        <h2>订单详情 #{order.short_code}</h2>
         <button className="close-btn" onClick={onClose}>&times;</button>
       </div>
 
@@ -35,10 +105,14 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
           <div className="status-control">
             <select
               value={order.status}
-              onChange={(e) => onStatusUpdate(order.id, e.target.value)}
+              onChange={(e) => onStatusUpdate(order.order_number, e.target.value)}
             >
               {statusOptions.map(option => (
-                <option key={option.value} value={option.value}>
+                <option
+                  key={option.value}
+                  value={option.value}
+                  disabled={option.value !== order.status && !nextStatuses[order.status]?.includes(option.value)}
+                >
                   {option.label}
                 </option>
               ))}
@@ -46,6 +120,26 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
           </div>
         </div>
 
+        {order.fulfilment?.type === 'delivery' && (
+          <div className="detail-section">
+            <h3>配送员</h3>
+            <div className="status-control">
+              <select
+                value={order.courier_id ?? ''}
+                onChange={(e) => onCourierAssign(order.order_number, e.target.value ? Number(e.target.value) : null)}
+                disabled={['completed', 'cancelled'].includes(order.status)}
+              >
+                <option value="">未指派</option>
+                {couriers.map(courier => (
+                  <option key={courier.id} value={courier.id}>
+                    {courier.name}（{courier.phone_number}）
+                  </option>
+                ))}
+              </select>
+            </div>
+          </div>
+        )}
+
         <div className="detail-section">
           <h3>客户信息</h3>
           <div className="info-grid">
@@ -58,18 +152,110 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
               <span>{order.phone_number}</span>
             </div>
             <div className="info-item">
-              <label>地址：</label>
-              <span>{order.delivery_address}</span>
+              <label>取餐方式：</label>
+              <span>{formatFulfilment(order.fulfilment)}</span>
+            </div>
+            {order.delivery_address && (
+              <div className="info-item">
+                <label>地址：</label>
+                <span>{order.delivery_address}</span>
+              </div>
+            )}
+            <div className="info-item">
+              <label>订单号：</label>
+              <span>{order.order_number}</span>
             </div>
             <div className="info-item">
               <label>下单时间：</label>
               <span>{formatDate(order.created_at)}</span>
             </div>
+            {order.requested_time && (
+              <div className="info-item">
+                <label>{order.fulfilment?.type === 'delivery' ? '预约送达：' : '预约取餐：'}</label>
+                <span>{formatDate(order.requested_time)}</span>
+              </div>
+            )}
+            {order.estimated_ready_at && !['completed', 'cancelled'].includes(order.status) && (
+              <div className="info-item">
+                <label>预计出餐：</label>
+                <span>{formatDate(order.estimated_ready_at)}</span>
+              </div>
+            )}
+            {order.fulfilment?.type === 'delivery' && order.estimated_arrival_at
+              && !['completed', 'cancelled'].includes(order.status) && (
+              <div className="info-item">
+                <label>预计送达：</label>
+                <span>{formatDate(order.estimated_arrival_at)}</span>
+              </div>
+            )}
+            {order.cancelled_at && (
+              <div className="info-item">
+                <label>取消原因：</label>
+                <span>{order.cancel_reason || '未填写'}（{formatDate(order.cancelled_at)}）</span>
+              </div>
+            )}
           </div>
         </div>
 
         <div className="detail-section">
           <h3>订单内容</h3>
+          {editing ? (
+            <div className="order-items-editor">
+              {draftItems.map((item, index) => (
+                <div key={index} className="order-item-edit">
+                  <input
+                    type="text"
+                    value={item.name}
+                    onChange={(e) => updateDraftItem(index, 'name', e.target.value)}
+                    placeholder="商品名称"
+                  />
+                  <input
+                    type="number"
+                    min="1"
+                    max="99"
+                    value={item.quantity}
+                    onChange={(e) => updateDraftItem(index, 'quantity', e.target.value)}
+                  />
+                  {/* 已有商品沿用原单价，新增商品由服务器按菜单计价 */}
+                  <span className="item-price">
+                    {item.price == null ? '按菜单计价' : `¥${Number(item.price).toFixed(2)}`}
+                  </span>
+                  <button
+                    type="button"
+                    className="remove-item-btn"
+                    onClick={() => setDraftItems(items => items.filter((_, i) => i !== index))}
+                  >
+                    &times;
+                  </button>
+                </div>
+              ))}
+              <button
+                type="button"
+                className="add-item-btn"
+                onClick={() => setDraftItems(items => [...items, { name: '', quantity: 1, price: null }])}
+              >
+                添加商品
+              </button>
+              <input
+                type="text"
+                className="amend-reason"
+                value={amendReason}
+                onChange={(e) => setAmendReason(e.target.value)}
+                placeholder="修改原因（可选）"
+              />
+              <div className="order-total">
+                <span>预计总计：</span>
+                <span className="total-amount">¥{draftTotal.toFixed(2)}</span>
+              </div>
+              <div className="edit-actions">
+                <button type="button" onClick={() => setEditing(false)}>取消</button>
+                <button type="button" className="primary" onClick={handleAmendSubmit} disabled={draftItems.length === 0}>
+                  保存修改
+                </button>
+              </div>
+            </div>
+          ) : (
+          <>
           <div className="order-items">
             {order.items.map((item, index) => (
               <div key={index} className="order-item-detail">
@@ -78,13 +264,57 @@ const OrderDetail = ({ order, onStatusUpdate, onClose }) => {
                 <div className="item-price">¥{item.price.toFixed(2)}</div>
               </div>
             ))}
+            {order.delivery_fee > 0 && (
+              <div className="order-item-detail">
+                <div className="item-name">外送费</div>
+                <div className="item-quantity"></div>
+                <div className="item-price">¥{order.delivery_fee.toFixed(2)}</div>
+              </div>
+            )}
           </div>
           <div className="order-total">
             <span>总计：</span>
             <span className="total-amount">¥{order.total_amount.toFixed(2)}</span>
           </div>
+          {/* 仅待处理（尚未开始制作）的订单可以修改商品 */}
+          {order.status === 'pending' && (
+            <button type="button" className="edit-items-btn" onClick={startEditing}>
+              修改商品
+            </button>
+          )}
+          </>
+          )}
         </div>
 
+        {/* 待支付的订单尚无可退金额 */}
+        {order.status !== 'awaiting_payment' && (
+          <div className="detail-section">
+            <h3>退款</h3>
+            {order.refund_status && (
+              <div className={`refund-status refund-${order.refund_status.replace('_', '-')}`}>
+                {refundStatusLabels[order.refund_status]}，已退款 ¥{order.refunded_amount.toFixed(2)}
+              </div>
+            )}
+            <div className="refund-form">
+              <input
+                type="number"
+                min="0.01"
+                step="0.01"
+                value={refundAmount}
+                onChange={(e) => setRefundAmount(e.target.value)}
+                placeholder="金额（留空全额退款）"
+              />
+              <input
+                type="text"
+                value={refundReason}
+                onChange={(e) => setRefundReason(e.target.value)}
+                placeholder="退款原因（可选）"
+              />
+              <button type="button" onClick={handleRefundSubmit}>退款</button>
+            </div>
+          </div>
+        )}
+
         {order.notes && (
           <div className="detail-section">
             <h3>备注</h3>
@@ -1,30 +1,47 @@
 import React, { useState, useEffect } from 'react';
+import { formatFulfilment } from '../fulfilment';
 import './OrderList.css';
 
-const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigger }) => {
+const PAGE_SIZE = 20;
+
+const OrderList = ({ filterStatus, onOrderSelect, selectedOrderNumber, refreshTrigger }) => {
   const [orders, setOrders] = useState([]);
+  const [total, setTotal] = useState(0);
+  const [page, setPage] = useState(1);
+  const [searchInput, setSearchInput] = useState('');
+  const [search, setSearch] = useState('');
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState(null);
   const [isRefreshing, setIsRefreshing] = useState(false);
 
+  // 筛选条件变化时回到第一页
+  useEffect(() => {
+    setPage(1);
+  }, [filterStatus, search]);
+
   useEffect(() => {
     fetchOrders();
-  }, [filterStatus, refreshTrigger]);
+  }, [filterStatus, search, page, refreshTrigger]);
 
   const fetchOrders = async () => {
     try {
       setLoading(true);
-      const url = filterStatus === 'all' 
-        ? '/api/orders'
-        : `/api/orders?status=${filterStatus}`;
-      
-      const response = await fetch(url);
+      const params = new URLSearchParams({ page, page_size: PAGE_SIZE });
+      if (filterStatus !== 'all') {
+        params.set('status', filterStatus);
+      }
+      if (search) {
+        params.set('q', search);
+      }
+
+      const response = await fetch(`/api/orders?${params}`);
       if (!response.ok) {
         throw new Error('获取订单列表失败');
       }
 
       const data = await response.json();
       setOrders(data.orders);
+      setTotal(data.total);
       setError(null);
     } catch (error) {
       console.error('获取订单列表失败:', error);
@@ -40,6 +57,22 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
     await fetchOrders();
   };
 
+  const handleSearch = (e) => {
+    e.preventDefault();
+    setSearch(searchInput.trim());
+  };
+
+  const totalPages = Math.max(1, Math.ceil(total / PAGE_SIZE));
+
+  // 按当前筛选条件导出订单地图（GeoJSON）
+  const exportParams = new URLSearchParams();
+  if (filterStatus !== 'all') {
+    exportParams.set('status', filterStatus);
+  }
+  if (search) {
+    exportParams.set('q', search);
+  }
+
   const formatDate = (dateString) => {
     const date = new Date(dateString);
     return new Intl.DateTimeFormat('zh-CN', {
@@ -53,6 +86,7 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
 
   const getStatusClass = (status) => {
     const statusMap = {
+      awaiting_payment: 'status-awaiting-payment',
       pending: 'status-pending',
       preparing: 'status-preparing',
       delivering: 'status-delivering',
@@ -64,6 +98,7 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
 
   const getStatusText = (status) => {
     const statusMap = {
+      awaiting_payment: '待支付',
       pending: '待处理',
       preparing: '制作中',
       delivering: '配送中',
@@ -85,6 +120,9 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
     <div className="order-list">
       <div className="order-list-header">
         <h2>订单列表</h2>
+        <a className="export-link" href={`/api/admin/orders.geojson?${exportParams}`} download="orders.geojson">
+          导出地图
+        </a>
         <button 
           className={`refresh-button ${isRefreshing ? 'refreshing' : ''}`}
           onClick={handleRefresh}
@@ -93,18 +131,27 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
           {isRefreshing ? '刷新中...' : '刷新'}
         </button>
       </div>
+      <form className="order-search" onSubmit={handleSearch}>
+        <input
+          type="text"
+          value={searchInput}
+          onChange={(e) => setSearchInput(e.target.value)}
+          placeholder="搜索姓名、电话或订单号"
+        />
+        <button type="submit">搜索</button>
+      </form>
       {orders.length === 0 ? (
         <div className="no-orders">暂无订单</div>
       ) : (
         <div className="orders-container">
           {orders.map(order => (
             <div 
-              key={order.id}
-              className={`order-item ${selectedOrderId === order.id ? 'selected' : ''}`}
+              key={order.order_number}
+              className={`order-item ${selectedOrderNumber === order.order_number ? 'selected' : ''}`}
               onClick={() => onOrderSelect(order)}
             >
               <div className="order-header">
-                <span className="order-number">#{order.order_number}</span>
+                <span className="order-number" title={order.order_number}>#{order.short_code}</span>
                 <span className={`order-status ${getStatusClass(order.status)}`}>
                   {getStatusText(order.status)}
                 </span>
@@ -117,12 +164,15 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
                 </div>
                 <div className="order-time">
                   {formatDate(order.created_at)}
+                  {order.requested_time && (
+                    <div className="order-scheduled">预约 {formatDate(order.requested_time)}</div>
+                  )}
                 </div>
               </div>
               
               <div className="order-summary">
                 <span className="order-items-count">
-                  {order.items.length} 件商品
+                  {formatFulfilment(order.fulfilment)} · {order.items.length} 件商品
                 </span>
                 <span className="order-total">
                   ¥{order.total_amount.toFixed(2)}
@@ -132,6 +182,15 @@ const OrderList = ({ filterStatus, onOrderSelect, selectedOrderId, refreshTrigge
           ))}
         </div>
       )}
+      <div className="order-pagination">
+        <button onClick={() => setPage(page - 1)} disabled={page <= 1}>
+          上一页
+        </button>
+        <span>第 {page} / {totalPages} 页，共 {total} 单</span>
+        <button onClick={() => setPage(page + 1)} disabled={page >= totalPages}>
+          下一页
+        </button>
+      </div>
     </div>
   );
 };
@@ -0,0 +1,74 @@
+import React, { useEffect, useState } from 'react';
+
+/**
+ * 门店接单控制
+ * 显示当前营业状态，并可暂停或恢复接单
+ */
+const StoreControl = () => {
+  const [status, setStatus] = useState(null); // 门店营业状态
+  const [reason, setReason] = useState(''); // 暂停原因
+  const [isSaving, setIsSaving] = useState(false);
+
+  useEffect(() => {
+    fetch('/api/store/status')
+      .then(response => (response.ok ? response.json() : null))
+      .then(setStatus)
+      .catch(error => console.error('获取门店状态失败:', error));
+  }, []);
+
+  const togglePause = async () => {
+    const paused = !status.paused;
+    if (paused && !window.confirm('暂停后顾客将无法下单，确定暂停接单吗？')) {
+      return;
+    }
+
+    setIsSaving(true);
+    try {
+      const response = await fetch('/api/store/pause', {
+        method: 'PUT',
+        headers: {
+          'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ paused, reason: paused ? reason.trim() || undefined : undefined }),
+      });
+      if (!response.ok) {
+        throw new Error('更新接单状态失败');
+      }
+      setStatus(await response.json());
+      setReason('');
+    } catch (error) {
+      console.error('更新接单状态失败:', error);
+      alert('更新接单状态时出错');
+    } finally {
+      setIsSaving(false);
+    }
+  };
+
+  if (!status) return null;
+
+  const statusText = status.paused
+    ? `已暂停接单${status.pause_reason ? `（${status.pause_reason}）` : ''}`
+    : status.open ? '营业中' : '休息中';
+
+  return (
+    <div className="store-control">
+      <span className={`store-state ${status.accepting_orders ? 'accepting' : 'closed'}`}>
+        {statusText}
+      </span>
+      {!status.paused && (
+        <input
+          type="text"
+          value={reason}
+          onChange={(e) => setReason(e.target.value)}
+          placeholder="暂停原因（可选）"
+          maxLength={200}
+        />
+      )}
+      <button onClick={togglePause} disabled={isSaving}>
+        {status.paused ? '恢复接单' : '暂停接单'}
+      </button>
+    </div>
+  );
+};
+
+export default StoreControl;
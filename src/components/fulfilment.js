@@ -0,0 +1,22 @@
+/**
+ * 取餐方式显示工具
+ * 将服务器返回的取餐方式转换为显示文本
+ */
+
+// 取餐方式对应的中文名称
+const FULFILMENT_LABELS = {
+  delivery: '外卖配送',
+  pickup: '到店自取',
+  dine_in: '堂食',
+};
+
+/**
+ * 格式化取餐方式
+ * @param {Object} fulfilment - 取餐方式，包含type及堂食时的table_number
+ * @returns {string} 显示文本
+ */
+export const formatFulfilment = (fulfilment) => {
+  if (!fulfilment) return FULFILMENT_LABELS.delivery;
+  const label = FULFILMENT_LABELS[fulfilment.type] || fulfilment.type;
+  return fulfilment.type === 'dine_in' ? `${label} · ${fulfilment.table_number}号桌` : label;
+};
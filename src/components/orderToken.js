@@ -0,0 +1,34 @@
+/**
+ * 订单查询令牌存储
+ * 下单时服务器返回的令牌保存在浏览器本地，用于之后自助取消订单等操作
+ */
+
+// 本地存储键名前缀
+const STORAGE_PREFIX = 'orderToken:';
+
+/**
+ * 保存订单查询令牌
+ * @param {string} orderNumber - 订单号
+ * @param {string} token - 查询令牌
+ */
+export const saveOrderToken = (orderNumber, token) => {
+  try {
+    localStorage.setItem(`${STORAGE_PREFIX}${orderNumber}`, token);
+  } catch (err) {
+    // 隐私模式等情况下本地存储不可用，仅影响自助操作
+    console.warn('无法保存订单令牌:', err);
+  }
+};
+
+/**
+ * 读取订单查询令牌
+ * @param {string} orderNumber - 订单号
+ * @returns {string|null} 查询令牌，不存在时返回null
+ */
+export const getOrderToken = (orderNumber) => {
+  try {
+    return localStorage.getItem(`${STORAGE_PREFIX}${orderNumber}`);
+  } catch (err) {
+    return null;
+  }
+};